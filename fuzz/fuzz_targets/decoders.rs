@@ -0,0 +1,33 @@
+//! Feeds arbitrary frames to the variable-length response decoders, none of them may panic
+//!
+//! Run with `cargo fuzz run decoders` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use noreya_sdbp::sdbp::response::SdbpResponse;
+use noreya_sdbp::sdbp::response::core::notification::NotificationResponse;
+use noreya_sdbp::sdbp::response::custom::bmc::usbhub::{enabled_port_mapping, UsbHubMapping, UsbHubPortMapping};
+use noreya_sdbp::sdbp::response::custom::io::input::GetValuesStatus;
+use noreya_sdbp::sdbp::response::custom::io::notification::IoEvent;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = GetValuesStatus::decode_records(data, 0);
+    let _ = GetValuesStatus::from_raw(data.to_vec());
+
+    if let Ok(response) = NotificationResponse::from_raw(data.to_vec()) {
+        let _ = IoEvent::decode(&response.notification);
+    }
+    let _ = IoEvent::decode(data);
+
+    // The first byte selects the disabled slots, the rest is the port mapping frame
+    if let Some((disabled, frame)) = data.split_first() {
+        if let Ok(mapping) = UsbHubPortMapping::from_raw(frame.to_vec()) {
+            let disabled = (0..8)
+                .filter(|slot| disabled & (1 << slot) != 0)
+                .map(|slot_number| UsbHubMapping { slot_number, is_disabled: true })
+                .collect();
+            let _ = enabled_port_mapping(disabled, mapping);
+        }
+    }
+});
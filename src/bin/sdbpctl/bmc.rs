@@ -0,0 +1,86 @@
+use noreya_sdbp::sdbp::ModuleProtocol;
+use noreya_sdbp::sdbp::request::custom::bmc::CustomBuilderBmc;
+use noreya_sdbp::sdbp::request::custom::bmc::protocol::classes::input::operation_code::input;
+use noreya_sdbp::sdbp::response::custom::bmc::buzzer::Buzzer;
+use noreya_sdbp::sdbp::response::custom::bmc::usbhub::{SetHubSuccess, SetPortSuccess, UsbHub, UsbHubPort};
+use noreya_sdbp::sdbp::response::custom::bmc::voltage::Voltage;
+use noreya_sdbp::sdbp::response::custom::bmc::watchdog::{ipc, json_response};
+
+use super::*;
+
+pub const USAGE : &str = "
+  bmc watchdog [left|alive|<MS>]              Watchdog timeout, time left, alive signal or new timeout (0 disables)
+  bmc usb [hub|<PORT>] [on|off]               USB hub and port states, or switch the hub / a port
+  bmc voltage                                 1V8 rail and RTC battery voltage
+  bmc buzzer <MODE> <DURATION>                Sound the buzzer
+  bmc shutdown                                Request a software shutdown";
+
+pub fn run(ctx : &mut Context, args : &[String]) -> CliResult {
+    let request = |result : Result<Vec<u8>,Error>| result.map_err(|err| CliError::Usage(err.to_string()));
+
+    match argument(args, 0, "BMC COMMAND")? {
+        "watchdog" => match args.get(1).map(|value| value.as_str()) {
+            None => {
+                let timeout : ipc::GetTimeout = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::watchdog().get_timeout())?)?;
+                print_value(&serde_json::json!({ "timeout" : timeout.timeout }), ctx.json);
+            },
+            Some("left") => {
+                let timeout : ipc::GetTimeout = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::watchdog().get_timeout_left())?)?;
+                print_value(&serde_json::json!({ "timeout_left" : timeout.timeout }), ctx.json);
+            },
+            Some("alive") => {
+                let status : json_response::AliveResponse = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::watchdog().alive())?)?;
+                print_serialized(&status, ctx.json);
+            },
+            Some(timeout) => {
+                let frame = request(CustomBuilderBmc::watchdog().timeout(parse_number("MS", timeout)?))?;
+                let status : json_response::TimeoutResponse = ctx.command(ModuleProtocol::Bmc, frame)?;
+                print_serialized(&status, ctx.json);
+            },
+        },
+        "usb" => match (args.get(1).map(|value| value.as_str()), args.get(2).map(|value| value.as_str())) {
+            (None, _) => {
+                let hub : UsbHub = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::usbhub().get_hub_state())?)?;
+                let ports : UsbHubPort = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::usbhub().get_slot_state())?)?;
+                let ports = [ports.slot0, ports.slot1, ports.slot2, ports.slot3, ports.slot4, ports.slot5, ports.slot6, ports.slot7];
+                print_value(&serde_json::json!({ "hub_state" : hub.state, "slot_states" : ports }), ctx.json);
+            },
+            (Some("hub"), Some(state)) => {
+                let frame = request(CustomBuilderBmc::usbhub().set_hub_state(parse_switch(state)?))?;
+                let status : SetHubSuccess = ctx.command(ModuleProtocol::Bmc, frame)?;
+                print_serialized(&status, ctx.json);
+            },
+            (Some(port), Some(state)) => {
+                let frame = request(CustomBuilderBmc::usbhub().set_slot_state(parse_switch(state)?, parse_number("PORT", port)?))?;
+                let status : SetPortSuccess = ctx.command(ModuleProtocol::Bmc, frame)?;
+                print_serialized(&status, ctx.json);
+            },
+            (Some(_), None) => return Err(CliError::Usage("Missing argument <on|off>".to_string())),
+        },
+        "voltage" => {
+            let rail : Voltage = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::voltage().input(input::RAIL_1V8))?)?;
+            let rtc : Voltage = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::voltage().input(input::RTC_BAT))?)?;
+            print_value(&serde_json::json!({ "voltage_1v8" : rail.voltage, "voltage_rtc" : rtc.voltage }), ctx.json);
+        },
+        "buzzer" => {
+            let mode = parse_number("MODE", argument(args, 1, "MODE")?)?;
+            let duration = parse_number("DURATION", argument(args, 2, "DURATION")?)?;
+            let status : Buzzer = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::buzzer().buzzer(mode, duration))?)?;
+            print_serialized(&status, ctx.json);
+        },
+        "shutdown" => {
+            let status : json_response::SwShutdown = ctx.command(ModuleProtocol::Bmc, request(CustomBuilderBmc::watchdog().sw_shutdown())?)?;
+            print_serialized(&status, ctx.json);
+        },
+        other => return Err(CliError::Usage(format!("Unknown bmc command {}", other))),
+    }
+    Ok(())
+}
+
+fn parse_switch(value : &str) -> Result<bool,CliError> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(CliError::Usage(format!("Invalid state {}, expected on or off", value))),
+    }
+}
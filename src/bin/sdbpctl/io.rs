@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use noreya_sdbp::sdbp::ModuleProtocol;
+use noreya_sdbp::sdbp::ioprofile::IoProfile;
+use noreya_sdbp::sdbp::request::custom::io::{IoBuilder, OutputMode, PinId, PrescalerDivider, PwmDuty, PwmTimer};
+use noreya_sdbp::sdbp::response::custom::io::input::GetValuesStatus;
+use noreya_sdbp::sdbp::response::custom::io::output::OutputModeStatus;
+use noreya_sdbp::sdbp::response::custom::io::powermgmt::{SetPowerConfig, TestPowerConfig};
+
+use super::*;
+
+pub const USAGE : &str = "
+  io get-values                               Values of all pins
+  io set-output <PIN> <0|1>                   Set a digital output
+  io pwm <PIN> <PRESCALER> <TIME_ON> <PERIOD> Set a PWM output, the timer options of pwm-hz add the frequency
+  io pwm-hz <PIN> <FREQUENCY_HZ> <DUTY_%>     Set a PWM output by frequency and duty cycle, needs the module timer clock
+     --clock <HZ> [--prescaler-plus-one]      as --clock and --prescaler-plus-one if the timer divides by prescaler + 1
+  io power-config [--test] <RAIL:LIMIT>x6     Configure (or test) the pin power rails
+  io profile <FILE> [--previous <FILE>]       Apply a JSON or TOML profile, restoring --previous on failure";
+
+pub fn run(ctx : &mut Context, args : &[String]) -> CliResult {
+    let request = |result : Result<Vec<u8>,Error>| result.map_err(|err| CliError::Usage(err.to_string()));
+
+    match argument(args, 0, "IO COMMAND")? {
+        "get-values" => {
+            let values : GetValuesStatus = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().input().get_values())?)?;
+            print_serialized(&values, ctx.json);
+        },
+        "set-output" => {
+            let pin = parse_pin(argument(args, 1, "PIN")?)?;
+            let state = match argument(args, 2, "STATE")? {
+                "0" => false,
+                "1" => true,
+                other => return Err(CliError::Usage(format!("Invalid STATE {}, expected 0 or 1", other))),
+            };
+            let status : OutputModeStatus = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().output().set_pin_output(pin, OutputMode::Digital(state)))?)?;
+            print_value(&serde_json::json!({ "status" : status.status, "message" : status.msg }), ctx.json);
+        },
+        "pwm" => {
+            let pin = parse_pin(argument(args, 1, "PIN")?)?;
+            let prescaler = parse_number("PRESCALER", argument(args, 2, "PRESCALER")?)?;
+            let time_on = parse_number("TIME_ON", argument(args, 3, "TIME_ON")?)?;
+            let period = parse_number("PERIOD", argument(args, 4, "PERIOD")?)?;
+            let mode = OutputMode::Pwm { prescaler, time_on, period };
+            let timer = parse_timer(&args[5.min(args.len())..])?;
+            let status : OutputModeStatus = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().output().set_pin_output(pin, mode))?)?;
+            match timer.and_then(|timer| timer.decode(&mode)) {
+                Some((frequency, duty)) => print_value(&serde_json::json!({ "status" : status.status, "message" : status.msg, "frequency_hz" : frequency, "duty_percent" : duty }), ctx.json),
+                None => print_value(&serde_json::json!({ "status" : status.status, "message" : status.msg }), ctx.json),
+            }
+        },
+        "pwm-hz" => {
+            let pin = parse_pin(argument(args, 1, "PIN")?)?;
+            let frequency = parse_float("FREQUENCY_HZ", argument(args, 2, "FREQUENCY_HZ")?)?;
+            let duty = parse_float("DUTY_%", argument(args, 3, "DUTY_%")?)?;
+            let timer = parse_timer(&args[4.min(args.len())..])?.ok_or_else(|| CliError::Usage("pwm-hz needs the timer clock, see --clock".to_string()))?;
+            let setting = timer.solve(frequency, PwmDuty::Percent(duty)).map_err(|err| CliError::Usage(err.to_string()))?;
+            let status : OutputModeStatus = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().output().set_pin_output(pin, setting.output_mode()))?)?;
+            print_value(&serde_json::json!({ "status" : status.status, "message" : status.msg, "pwm" : setting }), ctx.json);
+        },
+        "power-config" => {
+            let test = args.get(1).map(|value| value == "--test").unwrap_or(false);
+            let pins = &args[if test { 2 } else { 1 }..];
+            let config = pins.iter().map(|pin| parse_pin_config(pin)).collect::<Result<Vec<(u8,u16)>,CliError>>()?;
+
+            if test {
+                let status : TestPowerConfig = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().powermgmt().test_power_config(config))?)?;
+                print_serialized(&status, ctx.json);
+            } else {
+                let status : SetPowerConfig = ctx.command(ModuleProtocol::Io, request(IoBuilder::new().powermgmt().set_power_config(config))?)?;
+                print_serialized(&status, ctx.json);
+            }
+        },
+        "profile" => {
+            let profile = load_profile(argument(args, 1, "FILE")?)?;
+            let previous = match args.get(2).map(|value| value.as_str()) {
+                None => None,
+                Some("--previous") => Some(load_profile(argument(args, 3, "FILE")?)?),
+                Some(other) => return Err(CliError::Usage(format!("Unknown option for profile: {}", other))),
+            };
+            ctx.select()?;
+            let manager = ctx.manager();
+            profile.apply(previous.as_ref(), |frame| manager.transfer(frame))?;
+        },
+        other => return Err(CliError::Usage(format!("Unknown io command {}", other))),
+    }
+    Ok(())
+}
+
+/// Parses `<rail>:<current limit>` of a single pin
+fn parse_pin_config(value : &str) -> Result<(u8,u16),CliError> {
+    match value.split_once(':') {
+        Some((rail, limit)) => Ok((parse_number("RAIL", rail)?, parse_number("LIMIT", limit)?)),
+        None => Err(CliError::Usage(format!("Invalid pin power config {}, expected <RAIL:LIMIT>", value))),
+    }
+}
+
+/// Parses `--clock <HZ> [--prescaler-plus-one]`, the IO module timer clock is not known to the library
+fn parse_timer(args : &[String]) -> Result<Option<PwmTimer>,CliError> {
+    let mut clock = None;
+    let mut divider = PrescalerDivider::Prescaler;
+    let mut index = 0;
+    while index < args.len() {
+        match args[index].as_str() {
+            "--clock" => {
+                clock = Some(parse_number("HZ", argument(args, index + 1, "HZ")?)?);
+                index += 1;
+            },
+            "--prescaler-plus-one" => divider = PrescalerDivider::PrescalerPlusOne,
+            other => return Err(CliError::Usage(format!("Unknown timer option {}", other))),
+        }
+        index += 1;
+    }
+    Ok(clock.map(|clock_hz| PwmTimer::new(clock_hz, divider)))
+}
+
+fn parse_pin(value : &str) -> Result<PinId,CliError> {
+    value.parse().map_err(|err : Error| CliError::Usage(err.to_string()))
+}
+
+fn load_profile(path : &str) -> Result<IoProfile,CliError> {
+    Ok(IoProfile::load(Path::new(path))?)
+}
+
+fn parse_float(name : &str, value : &str) -> Result<f64,CliError> {
+    value.parse().map_err(|_| CliError::Usage(format!("Invalid value for {}: {}", name, value)))
+}
@@ -0,0 +1,376 @@
+//! Command line client for SDBP drivers, built on `drv::api::Manager`.
+//!
+//! Exit codes: `0` success, `2` usage error, `3` driver not reachable, `4` device not found,
+//! `5` invalid response, `6` refused by a slot lock or the access policy and `16 + status` if
+//! the module answered with a non-zero status code.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::process::exit;
+use std::time::Duration;
+
+#[cfg(feature = "firmware-experimental")]
+use noreya_sdbp::datatypes::BootloaderState;
+use noreya_sdbp::datatypes::Descriptor;
+use noreya_sdbp::drv::api::{Manager, Tag};
+use noreya_sdbp::sdbp::{Dissector, ModuleProtocol};
+#[cfg(feature = "firmware-experimental")]
+use noreya_sdbp::sdbp::firmware::{FirmwareError, FirmwareImage, FirmwareUpdater, UpdatePhase};
+#[cfg(feature = "io")]
+use noreya_sdbp::sdbp::ioprofile::ProfileError;
+use noreya_sdbp::sdbp::response::SdbpResponse;
+
+mod output;
+#[cfg(feature = "io")]
+mod io;
+#[cfg(feature = "power")]
+mod power;
+#[cfg(feature = "bmc")]
+mod bmc;
+
+use output::*;
+
+const EXIT_USAGE : i32 = 2;
+const EXIT_CONNECTION : i32 = 3;
+const EXIT_NOT_FOUND : i32 = 4;
+const EXIT_INVALID_RESPONSE : i32 = 5;
+const EXIT_PERMISSION_DENIED : i32 = 6;
+const EXIT_STATUS_BASE : i32 = 16;
+
+const USAGE : &str = "Usage: sdbpctl [OPTIONS] <COMMAND>
+
+Options:
+  --socket <PATH>                             Driver socket (default: $SDBP_SOCKET)
+  --slot <SLOT>                               Select a device by slot address (decimal or 0x hex)
+  --serial <SERIAL>                           Select a device by serial number
+  --timeout <MS>                              Response timeout in milliseconds (default: 2000)
+  --protocol <io|power|bmc>                   Module protocol used to decode raw frames
+  --json                                      Print JSON instead of tables
+
+Commands:
+  info                                        Driver versions
+  list [--short]                              Connected devices
+  select                                      Descriptor of the selected device
+  raw <HEX>                                   Send a raw SDBP frame to the selected device";
+
+#[cfg(feature = "firmware-experimental")]
+const FLASH_USAGE : &str = "
+  flash <IMAGE> [--force]                     Update the firmware of the selected device (experimental)";
+
+/// Failure of a command, mapped to the process exit code
+pub enum CliError {
+    Usage(String),
+    Connection(Error),
+    /// The slot is locked by another client or the access policy refused the command
+    PermissionDenied(String),
+    NotFound(String),
+    InvalidResponse(String),
+    Status(u8, String),
+}
+
+impl CliError {
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Connection(_) => EXIT_CONNECTION,
+            CliError::PermissionDenied(_) => EXIT_PERMISSION_DENIED,
+            CliError::NotFound(_) => EXIT_NOT_FOUND,
+            CliError::InvalidResponse(_) => EXIT_INVALID_RESPONSE,
+            CliError::Status(code, _) => (EXIT_STATUS_BASE + *code as i32).min(255),
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            CliError::Usage(msg) => format!("{}\n\n{}", msg, usage()),
+            CliError::Connection(err) => format!("Driver not reachable: {}", err),
+            CliError::PermissionDenied(msg) => format!("Refused by the driver: {}", msg),
+            CliError::NotFound(msg) => msg.clone(),
+            CliError::InvalidResponse(msg) => format!("Invalid response: {}", msg),
+            CliError::Status(code, meaning) => format!("Module returned status 0x{:02x}: {}", code, meaning),
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(err : Error) -> Self {
+        match err.kind() {
+            ErrorKind::NotFound => CliError::NotFound(err.to_string()),
+            ErrorKind::PermissionDenied => CliError::PermissionDenied(err.to_string()),
+            ErrorKind::InvalidData => CliError::InvalidResponse(err.to_string()),
+            _ => CliError::Connection(err),
+        }
+    }
+}
+
+#[cfg(feature = "io")]
+impl From<ProfileError> for CliError {
+    fn from(err : ProfileError) -> Self {
+        match err {
+            ProfileError::Io(err) => CliError::from(err),
+            ProfileError::Rejected { status, .. } | ProfileError::PowerConfig { status } => CliError::Status(status, err.to_string()),
+            ProfileError::Invalid(_) => CliError::Usage(err.to_string()),
+            _ => CliError::InvalidResponse(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "firmware-experimental")]
+impl From<FirmwareError> for CliError {
+    fn from(err : FirmwareError) -> Self {
+        match err {
+            FirmwareError::Io(err) => CliError::from(err),
+            FirmwareError::Bootloader { status, .. } => CliError::Status(status, err.to_string()),
+            FirmwareError::InvalidImage(_) | FirmwareError::WrongProduct { .. } | FirmwareError::NotNewer { .. } => CliError::Usage(err.to_string()),
+            _ => CliError::InvalidResponse(err.to_string()),
+        }
+    }
+}
+
+pub type CliResult = Result<(),CliError>;
+
+fn usage() -> String {
+    #[allow(unused_mut)]
+    let mut usage = USAGE.to_string();
+    #[cfg(feature = "firmware-experimental")]
+    usage.push_str(FLASH_USAGE);
+    #[cfg(feature = "io")]
+    usage.push_str(io::USAGE);
+    #[cfg(feature = "power")]
+    usage.push_str(power::USAGE);
+    #[cfg(feature = "bmc")]
+    usage.push_str(bmc::USAGE);
+    usage
+}
+
+enum Selection {
+    None,
+    Slot(u16),
+    Serial(String),
+}
+
+/// Connection and global options shared by all commands
+pub struct Context {
+    manager : Manager,
+    selection : Selection,
+    protocol : ModuleProtocol,
+    pub json : bool,
+}
+
+impl Context {
+
+    pub fn manager(&mut self) -> &mut Manager {
+        &mut self.manager
+    }
+
+    /// Selects the device given by `--slot` or `--serial`
+    pub fn select(&mut self) -> Result<Descriptor,CliError> {
+        let devices = self.manager.get_device_list(false)?;
+        let device = match &self.selection {
+            Selection::None => return Err(CliError::Usage("No device selected, use --slot or --serial".to_string())),
+            Selection::Slot(slot) => devices.into_iter().find(|device| device.adr() == *slot)
+                .ok_or_else(|| CliError::NotFound(format!("Cannot find device with address {}", slot)))?,
+            Selection::Serial(serial) => devices.into_iter().find(|device| device.serial() == serial)
+                .ok_or_else(|| CliError::NotFound(format!("Cannot find device with serial {}", serial)))?,
+        };
+        self.manager.select_via_slot(device.adr())?;
+        Ok(device)
+    }
+
+    /// Sends a frame to the selected device and returns the raw response
+    pub fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,CliError> {
+        self.select()?;
+        let tlv = self.manager.raw_command(request)?;
+        match tlv.get(&Tag::Response).and_then(|value| value.as_bytes()) {
+            Some(raw) => Ok(raw.clone()),
+            None => Err(CliError::InvalidResponse("Response block missing".to_string())),
+        }
+    }
+
+    /// Sends a frame, fails on a non-zero module status and parses the response
+    pub fn command<T : SdbpResponse>(&mut self, protocol : ModuleProtocol, request : Vec<u8>) -> Result<T,CliError> {
+        let raw = self.transfer(request)?;
+        if let Some(status) = Dissector::new(protocol).response(&raw).status {
+            if status.code != 0 {
+                return Err(CliError::Status(status.code, status.meaning));
+            }
+        }
+        T::from_raw(raw).map_err(|err| CliError::InvalidResponse(err.to_string()))
+    }
+}
+
+/// Parses a number given as decimal or with `0x` prefix
+pub fn parse_number<T : TryFrom<u64>>(name : &str, value : &str) -> Result<T,CliError> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse::<u64>(),
+    };
+    parsed.ok().and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| CliError::Usage(format!("Invalid value for {}: {}", name, value)))
+}
+
+/// Returns the argument at `index` or a usage error naming it
+pub fn argument<'a>(args : &'a [String], index : usize, name : &str) -> Result<&'a str,CliError> {
+    args.get(index).map(|value| value.as_str()).ok_or_else(|| CliError::Usage(format!("Missing argument <{}>", name)))
+}
+
+fn parse_protocol(value : &str) -> Result<ModuleProtocol,CliError> {
+    match value {
+        "generic" => Ok(ModuleProtocol::Generic),
+        "io" => Ok(ModuleProtocol::Io),
+        "power" => Ok(ModuleProtocol::Power),
+        "bmc" => Ok(ModuleProtocol::Bmc),
+        _ => Err(CliError::Usage(format!("Unknown protocol {}", value))),
+    }
+}
+
+fn parse_hex(input : &str) -> Result<Vec<u8>,CliError> {
+    let digits : String = input.chars().filter(|value| !value.is_whitespace() && *value != ':' && *value != ',').collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    hex::decode(digits).map_err(|err| CliError::Usage(format!("Invalid hex frame: {}", err)))
+}
+
+fn info(ctx : &mut Context) -> CliResult {
+    let info = ctx.manager.get_info()?;
+    let value = serde_json::json!({
+        "driver_version" : info.clone().get_version().to_string(),
+        "sdbpk_driver_version" : info.get_sdbpk_version().to_string(),
+    });
+    print_value(&value, ctx.json);
+    Ok(())
+}
+
+fn list(ctx : &mut Context, args : &[String]) -> CliResult {
+    let short = match args.first().map(|value| value.as_str()) {
+        None => false,
+        Some("--short") => true,
+        Some(other) => return Err(CliError::Usage(format!("Unknown option for list: {}", other))),
+    };
+
+    let devices = ctx.manager.get_device_list(short)?;
+    if ctx.json {
+        print_json(&devices);
+    } else {
+        print_devices(&devices, short);
+    }
+    Ok(())
+}
+
+fn select(ctx : &mut Context) -> CliResult {
+    let device = ctx.select()?;
+    if ctx.json {
+        print_json(&device);
+    } else {
+        print_descriptor(&device);
+    }
+    Ok(())
+}
+
+fn raw(ctx : &mut Context, args : &[String]) -> CliResult {
+    if args.is_empty() {
+        return Err(CliError::Usage("Missing argument <HEX>".to_string()));
+    }
+    let request = parse_hex(&args.join(""))?;
+    let response = ctx.transfer(request)?;
+    let dissected = Dissector::new(ctx.protocol).response(&response);
+
+    if ctx.json {
+        print_json(&serde_json::json!({ "response" : hex::encode(&response), "dissected" : dissected }));
+    } else {
+        println!("{}", response.iter().map(|value| format!("{:02x}", value)).collect::<Vec<String>>().join(" "));
+        println!("{}", dissected);
+    }
+
+    match dissected.status {
+        Some(status) if status.code != 0 => Err(CliError::Status(status.code, status.meaning)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "firmware-experimental")]
+fn flash(ctx : &mut Context, args : &[String]) -> CliResult {
+    let path = argument(args, 0, "IMAGE")?;
+    let force = match args.get(1).map(|value| value.as_str()) {
+        None => false,
+        Some("--force") => true,
+        Some(other) => return Err(CliError::Usage(format!("Unknown option for flash: {}", other))),
+    };
+
+    let device = ctx.select()?;
+    let image = FirmwareImage::load(path.as_ref())?;
+    image.check(&device, force)?;
+    let state = BootloaderState::try_from(device.bootloader_state().as_str()).unwrap_or(BootloaderState::NotSupported);
+
+    let mut updater = FirmwareUpdater::new(image).on_progress(|progress| match progress.phase {
+        UpdatePhase::Write if progress.bytes_done < progress.bytes_total => eprint!("\rWrite {:3}%", progress.percent()),
+        UpdatePhase::Write => eprintln!("\rWrite 100%"),
+        phase => eprintln!("{:?}", phase),
+    });
+    updater.run(ctx.manager(), &state)?;
+    Ok(())
+}
+
+fn run(args : Vec<String>) -> CliResult {
+    let mut socket = env::var("SDBP_SOCKET").ok();
+    let mut selection = Selection::None;
+    let mut timeout = Duration::from_millis(2000);
+    let mut protocol = ModuleProtocol::Generic;
+    let mut json = false;
+
+    let mut index = 0;
+    while index < args.len() && args[index].starts_with("--") {
+        let option = args[index].as_str();
+        match option {
+            "--json" => json = true,
+            "--help" => {
+                println!("{}", usage());
+                return Ok(());
+            },
+            "--socket" | "--slot" | "--serial" | "--timeout" | "--protocol" => {
+                index += 1;
+                let value = argument(&args, index, option)?;
+                match option {
+                    "--socket" => socket = Some(value.to_string()),
+                    "--slot" => selection = Selection::Slot(parse_number(option, value)?),
+                    "--serial" => selection = Selection::Serial(value.to_string()),
+                    "--protocol" => protocol = parse_protocol(value)?,
+                    _ => timeout = Duration::from_millis(parse_number(option, value)?),
+                }
+            },
+            _ => return Err(CliError::Usage(format!("Unknown option {}", option))),
+        }
+        index += 1;
+    }
+
+    let command = argument(&args, index, "COMMAND")?.to_string();
+    let rest = &args[index + 1..];
+
+    let socket = socket.ok_or_else(|| CliError::Usage("No driver socket given, use --socket or SDBP_SOCKET".to_string()))?;
+    let manager = Manager::new(socket, Some(timeout)).map_err(CliError::Connection)?;
+    let mut ctx = Context { manager, selection, protocol, json };
+
+    match command.as_str() {
+        "info" => info(&mut ctx),
+        "list" => list(&mut ctx, rest),
+        "select" => select(&mut ctx),
+        "raw" => raw(&mut ctx, rest),
+        #[cfg(feature = "firmware-experimental")]
+        "flash" => flash(&mut ctx, rest),
+        #[cfg(feature = "io")]
+        "io" => io::run(&mut ctx, rest),
+        #[cfg(feature = "power")]
+        "power" => power::run(&mut ctx, rest),
+        #[cfg(feature = "bmc")]
+        "bmc" => bmc::run(&mut ctx, rest),
+        _ => Err(CliError::Usage(format!("Unknown command {}", command))),
+    }
+}
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(args) {
+        eprintln!("{}", err.message());
+        exit(err.exit_code());
+    }
+}
@@ -0,0 +1,147 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use noreya_sdbp::datatypes::{Descriptor, Version};
+
+pub fn print_json<T : Serialize>(value : &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => println!("{}", text),
+        Err(err) => eprintln!("Cannot serialize output: {}", err),
+    }
+}
+
+/// Prints a serializable value as JSON or as human readable table
+#[cfg_attr(not(any(feature = "io", feature = "power", feature = "bmc")), allow(dead_code))]
+pub fn print_serialized<T : Serialize>(value : &T, json : bool) {
+    match serde_json::to_value(value) {
+        Ok(value) => print_value(&value, json),
+        Err(err) => eprintln!("Cannot serialize output: {}", err),
+    }
+}
+
+pub fn print_value(value : &Value, json : bool) {
+    if json {
+        print_json(value);
+        return;
+    }
+
+    match value {
+        Value::Object(map) => {
+            let width = map.keys().map(|key| key.len()).max().unwrap_or(0);
+            for (key, value) in map {
+                match value {
+                    Value::Array(rows) if rows.iter().all(|row| row.is_object()) && !rows.is_empty() => {
+                        println!("{}:", key);
+                        print_rows(rows);
+                    },
+                    _ => println!("{:width$}  {}", key, format_cell(value), width = width),
+                }
+            }
+        },
+        Value::Array(rows) if rows.iter().all(|row| row.is_object()) => print_rows(rows),
+        _ => println!("{}", format_cell(value)),
+    }
+}
+
+fn format_cell(value : &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        Value::Array(values) => values.iter().map(format_cell).collect::<Vec<String>>().join(", "),
+        _ => value.to_string(),
+    }
+}
+
+fn print_rows(rows : &[Value]) {
+    let mut header : Vec<String> = Vec::new();
+    for row in rows {
+        if let Value::Object(map) = row {
+            for key in map.keys() {
+                if !header.contains(key) {
+                    header.push(key.clone());
+                }
+            }
+        }
+    }
+
+    let cells : Vec<Vec<String>> = rows.iter()
+        .map(|row| header.iter().map(|key| row.get(key).map(format_cell).unwrap_or_default()).collect())
+        .collect();
+    print_table(&header, &cells);
+}
+
+pub fn print_table<H : AsRef<str>>(header : &[H], rows : &[Vec<String>]) {
+    let mut widths : Vec<usize> = header.iter().map(|title| title.as_ref().len()).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            widths[index] = widths[index].max(cell.len());
+        }
+    }
+
+    let line = |cells : Vec<&str>| {
+        let columns : Vec<String> = cells.iter().zip(widths.iter()).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+        println!("{}", columns.join("  ").trim_end());
+    };
+    line(header.iter().map(|title| title.as_ref()).collect());
+    for row in rows {
+        line(row.iter().map(|cell| cell.as_str()).collect());
+    }
+}
+
+fn version(version : &Version) -> String {
+    format!("{}.{}.{}", version.major(), version.minor(), version.patch())
+}
+
+pub fn print_devices(devices : &[Descriptor], short : bool) {
+    let rows : Vec<Vec<String>> = devices.iter().map(|device| {
+        if short {
+            vec![
+                format!("0x{:04x}", device.adr()),
+                device.fw_version().to_string(),
+                version(device.hw_version()),
+                version(device.protocol_version()),
+                device.max_frame_size().to_string(),
+                device.serial().clone(),
+                device.device_session().clone(),
+            ]
+        } else {
+            vec![
+                format!("0x{:04x}", device.adr()),
+                device.product_name().clone(),
+                device.vendor_name().clone(),
+                device.serial().clone(),
+                device.fw_version().to_string(),
+                version(device.hw_version()),
+                device.bootloader_state(),
+            ]
+        }
+    }).collect();
+
+    if short {
+        print_table(&["SLOT", "FW", "HW", "PROTOCOL", "FRAME", "SERIAL", "SESSION"], &rows);
+    } else {
+        print_table(&["SLOT", "PRODUCT", "VENDOR", "SERIAL", "FW", "HW", "BOOTLOADER"], &rows);
+    }
+}
+
+pub fn print_descriptor(device : &Descriptor) {
+    let rows = [
+        ("slot", format!("0x{:04x}", device.adr())),
+        ("product", device.product_name().clone()),
+        ("vendor", device.vendor_name().clone()),
+        ("vendor product id", device.vendor_product_id().clone()),
+        ("serial", device.serial().clone()),
+        ("fw version", device.fw_version().to_string()),
+        ("hw version", version(device.hw_version())),
+        ("protocol version", version(device.protocol_version())),
+        ("bootloader", device.bootloader_state()),
+        ("max frame size", device.max_frame_size().to_string()),
+        ("max sclk speed", device.max_sclk_speed().to_string()),
+        ("max power 12V", device.max_power_12v().to_string()),
+        ("max power 5V", device.max_power_5v().to_string()),
+        ("max power 3V3", device.max_power_3v3().to_string()),
+    ];
+    for (key, value) in rows {
+        println!("{:18}  {}", key, value);
+    }
+}
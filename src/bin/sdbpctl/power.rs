@@ -0,0 +1,48 @@
+use noreya_sdbp::sdbp::ModuleProtocol;
+use noreya_sdbp::sdbp::request::custom::power::Power;
+use noreya_sdbp::sdbp::response::custom::power::fan::{FanControl, FanStatus};
+use noreya_sdbp::sdbp::response::custom::power::powercmd::{ProtectionStatus, VoltageStatus};
+use noreya_sdbp::sdbp::response::custom::power::temperature::ResponseTemperature;
+
+use super::*;
+
+pub const USAGE : &str = "
+  power status                                Voltages and currents of all rails
+  power protection                            Protection status
+  power fan [auto|<0|20|40|60|80|100>]        Fan status, or switch to automatic / forced speed
+  power temp                                  Temperature sensor";
+
+pub fn run(ctx : &mut Context, args : &[String]) -> CliResult {
+    let request = |result : Result<Vec<u8>,Error>| result.map_err(|err| CliError::Usage(err.to_string()));
+
+    match argument(args, 0, "POWER COMMAND")? {
+        "status" => {
+            let status : VoltageStatus = ctx.command(ModuleProtocol::Power, request(Power::power_builder().voltage_current_status())?)?;
+            print_serialized(&status, ctx.json);
+        },
+        "protection" => {
+            let status : ProtectionStatus = ctx.command(ModuleProtocol::Power, request(Power::power_builder().protection_status())?)?;
+            print_serialized(&status, ctx.json);
+        },
+        "fan" => match args.get(1).map(|value| value.as_str()) {
+            None => {
+                let status : FanStatus = ctx.command(ModuleProtocol::Power, request(Power::temperature_builder().fan_status())?)?;
+                print_serialized(&status, ctx.json);
+            },
+            Some(mode) => {
+                let frame = match mode {
+                    "auto" => Power::temperature_builder().fan_control(false, None),
+                    speed => Power::temperature_builder().fan_control(true, Some(parse_number("SPEED", speed)?)),
+                };
+                let status : FanControl = ctx.command(ModuleProtocol::Power, request(frame)?)?;
+                print_serialized(&status, ctx.json);
+            },
+        },
+        "temp" => {
+            let temperature : ResponseTemperature = ctx.command(ModuleProtocol::Power, request(Power::temperature_builder().temperature_sensor())?)?;
+            print_serialized(&temperature, ctx.json);
+        },
+        other => return Err(CliError::Usage(format!("Unknown power command {}", other))),
+    }
+    Ok(())
+}
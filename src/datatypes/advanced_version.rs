@@ -18,8 +18,8 @@ impl AdvancedVersion {
     /// major - Major Version [0-65535]
     /// minor - Minor Version [0-65535]
     /// patch - Patch Version [0-65535]
-    pub fn new(stability: char, major : u16 , minor : u16 , patch : u16) {
-        AdvancedVersion{stability,version: Version::new(major,minor,patch)};
+    pub fn new(stability: char, major : u16 , minor : u16 , patch : u16) -> AdvancedVersion {
+        AdvancedVersion{stability,version: Version::new(major,minor,patch)}
     }
 
     /// Converts a String into a AdvancedVersion Object
@@ -45,6 +45,15 @@ impl AdvancedVersion {
         Ok(AdvancedVersion {version, stability})
     }
 
+    /// Stability [A - Alpha, B - Beta, S - Stable Release]
+    pub fn stability(&self) -> char {
+        self.stability
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
     /// Converts a AdvancedVersion object into a byte array
     pub fn to_bytes(&self) -> Vec<u8> {
 
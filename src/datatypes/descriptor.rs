@@ -212,6 +212,24 @@ impl Descriptor {
 
     pub fn uid(&self) -> u32 { self.uid }
     pub fn set_uid(&mut self,uid : u32) {self.uid= uid; }
+
+    /// Takes the fields reported by the module from `other`, e.g. from `read_full_descriptor`.
+    /// Path, slot, session, timestamp, device file and uid are kept.
+    pub fn update_from(&mut self, other : &Descriptor) {
+        self.vendor_product_id = other.vendor_product_id.clone();
+        self.product_name = other.product_name.clone();
+        self.vendor_name = other.vendor_name.clone();
+        self.serial_code = other.serial_code.clone();
+        self.fw_version = other.fw_version.clone();
+        self.hw_version = other.hw_version.clone();
+        self.protocol_version = other.protocol_version.clone();
+        self.bootloader_state = other.bootloader_state.clone();
+        self.max_frame_size = other.max_frame_size;
+        self.max_power_12v = other.max_power_12v;
+        self.max_power_5v0 = other.max_power_5v0;
+        self.max_power_3v3 = other.max_power_3v3;
+        self.max_sclk_speed = other.max_sclk_speed;
+    }
 }
 
 impl fmt::Display for Descriptor {
@@ -9,11 +9,15 @@ pub enum Command {
     GetDeviceList  = 0x0003,
     GetDescriptor = 0x0004,
     GetNotification = 0x0005,
+    LockSlot = 0x0006,
+    UnlockSlot = 0x0007,
+    SubscribeEvents = 0x0008,
 
     Device = 0x010,
 
     Error = 0x1001,
     Response = 0x1002,
+    Event = 0x1003,
 }
 
 impl Command {
@@ -35,6 +39,9 @@ impl Command {
                     op_id if op_id == Command::GetDeviceList as u16 => Some(Command::GetDeviceList),
                     op_id if op_id == Command::GetNotification as u16 => Some(Command::GetNotification),
                     op_id if op_id == Command::GetDescriptor as u16 => Some(Command::GetDescriptor),
+                    op_id if op_id == Command::LockSlot as u16 => Some(Command::LockSlot),
+                    op_id if op_id == Command::UnlockSlot as u16 => Some(Command::UnlockSlot),
+                    op_id if op_id == Command::SubscribeEvents as u16 => Some(Command::SubscribeEvents),
                     _ => None,
                 };
                 result
@@ -7,7 +7,11 @@ pub enum Error {
     InvalidLength = 0xE002,
     InvalidParameter = 0xE003,
     TlvError = 0xE004,
-    VirtualDeviceError = 0xE005
+    VirtualDeviceError = 0xE005,
+    SlotLocked = 0xE006,
+    PermissionDenied = 0xE007,
+    /// The module answered with a transaction error, its code follows the error code
+    TransactionError = 0xE008,
 }
 
 impl Error {
@@ -21,9 +25,26 @@ impl Error {
             Error::InvalidLength => { (Error::InvalidLength as u16).to_ne_bytes() },
             Error::TlvError => { (Error::TlvError as u16).to_ne_bytes() },
             Error::VirtualDeviceError => { (Error::VirtualDeviceError as u16).to_ne_bytes() },
+            Error::SlotLocked => { (Error::SlotLocked as u16).to_ne_bytes() },
+            Error::PermissionDenied => { (Error::PermissionDenied as u16).to_ne_bytes() },
+            Error::TransactionError => { (Error::TransactionError as u16).to_ne_bytes() },
         };
         result
     }
+
+    pub fn msg(&self) -> &'static str {
+        match self {
+            Error::UnknownCommand => "Unknown command",
+            Error::DeviceNotConnected => "Device not connected",
+            Error::InvalidParameter => "Invalid parameter",
+            Error::InvalidLength => "Invalid length",
+            Error::TlvError => "TLV error",
+            Error::VirtualDeviceError => "Virtual device error",
+            Error::SlotLocked => "Slot is locked by another client",
+            Error::PermissionDenied => "Permission denied",
+            Error::TransactionError => "Transaction error",
+        }
+    }
 }
 
 impl TryFrom<tlv::error::Error> for Error {
@@ -32,4 +53,23 @@ impl TryFrom<tlv::error::Error> for Error {
     fn try_from(_value: tlv::error::Error) -> Result<Self, Self::Error> {
       Ok(Error::TlvError)
     }
-}
\ No newline at end of file
+}
+
+impl TryFrom<u16> for Error {
+    type Error = ();
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            x if x == Error::UnknownCommand as u16 => Ok(Error::UnknownCommand),
+            x if x == Error::DeviceNotConnected as u16 => Ok(Error::DeviceNotConnected),
+            x if x == Error::InvalidLength as u16 => Ok(Error::InvalidLength),
+            x if x == Error::InvalidParameter as u16 => Ok(Error::InvalidParameter),
+            x if x == Error::TlvError as u16 => Ok(Error::TlvError),
+            x if x == Error::VirtualDeviceError as u16 => Ok(Error::VirtualDeviceError),
+            x if x == Error::SlotLocked as u16 => Ok(Error::SlotLocked),
+            x if x == Error::PermissionDenied as u16 => Ok(Error::PermissionDenied),
+            x if x == Error::TransactionError as u16 => Ok(Error::TransactionError),
+            _ => Err(()),
+        }
+    }
+}
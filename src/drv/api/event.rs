@@ -0,0 +1,151 @@
+use std::convert::TryFrom;
+use super::*;
+use crate::datatypes::Descriptor;
+
+/// Types of events pushed to clients which subscribed with `Command::SubscribeEvents`
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum EventType {
+    DeviceListChanged = 0x0001,
+    DeviceAdded = 0x0002,
+    DeviceRemoved = 0x0003,
+    DeviceUpdated = 0x0004,
+}
+
+impl TryFrom<u16> for EventType {
+    type Error = ();
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            x if x == EventType::DeviceListChanged as u16 => Ok(EventType::DeviceListChanged),
+            x if x == EventType::DeviceAdded as u16 => Ok(EventType::DeviceAdded),
+            x if x == EventType::DeviceRemoved as u16 => Ok(EventType::DeviceRemoved),
+            x if x == EventType::DeviceUpdated as u16 => Ok(EventType::DeviceUpdated),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct ModEvent {
+    pub event_type : EventType,
+    /// Version of the device list after the change
+    pub list_version : u32,
+    /// Slot of a hotplug event
+    pub slot : Option<u16>,
+    /// New descriptor of the slot, for `DeviceRemoved` the last known one
+    pub descriptor : Option<Descriptor>,
+}
+
+impl ModEvent {
+
+    pub fn device_list_changed(list_version : u32) -> ModEvent {
+        ModEvent { event_type : EventType::DeviceListChanged, list_version, slot : None, descriptor : None }
+    }
+
+    /// Creates a hotplug event, the slot is taken from the descriptor
+    pub fn hotplug(event_type : EventType, list_version : u32, descriptor : Descriptor) -> ModEvent {
+        ModEvent { event_type, list_version, slot : Some(descriptor.adr()), descriptor : Some(descriptor) }
+    }
+
+    /// Compares two device lists and returns the hotplug events leading from `old` to `new`
+    pub fn diff(old : &[Descriptor], new : &[Descriptor], list_version : u32) -> Vec<ModEvent> {
+        let mut events = Vec::new();
+
+        for device in old {
+            if !new.iter().any(|current| current.adr() == device.adr()) {
+                events.push(ModEvent::hotplug(EventType::DeviceRemoved, list_version, device.clone()));
+            }
+        }
+        for device in new {
+            match old.iter().find(|previous| previous.adr() == device.adr()) {
+                None => events.push(ModEvent::hotplug(EventType::DeviceAdded, list_version, device.clone())),
+                Some(previous) if previous != device => events.push(ModEvent::hotplug(EventType::DeviceUpdated, list_version, device.clone())),
+                Some(_) => (),
+            }
+        }
+        events
+    }
+
+    pub fn to_response(&self) -> Response {
+        let mut tlv = TlvValue::new();
+        let array = tlv.push(Tag::EventBlock,TlvValue::new_array()).unwrap();
+        array.push(Tag::EventType,TlvValue::from(self.event_type as u16));
+        array.push(Tag::ListVersion,TlvValue::from(self.list_version));
+
+        if let Some(slot) = self.slot {
+            array.push(Tag::DeviceAddress,TlvValue::from(slot));
+        }
+        if let Some(descriptor) = &self.descriptor {
+            let block = array.push(Tag::DeviceBlock,TlvValue::new_array()).unwrap();
+            ModApi::push_device(block, descriptor, false);
+        }
+
+        let mut response = Response::new_event();
+        response.append_bytes(tlv.into_bytes().as_slice());
+        response
+    }
+
+    pub fn from_response(response : &Response) -> Option<ModEvent> {
+        if !response.is_event() {
+            return None;
+        }
+
+        let tlv = TlvValue::try_from(response.get_payload()).ok()?;
+        let block = tlv.get(&Tag::EventBlock)?;
+        let event_type = EventType::try_from(block.get(&Tag::EventType)?.as_u16()?).ok()?;
+        let list_version = block.get(&Tag::ListVersion)?.as_32()?;
+
+        let slot = match block.get(&Tag::DeviceAddress) {
+            Some(value) => Some(value.as_u16()?),
+            None => None,
+        };
+        let descriptor = match block.get(&Tag::DeviceBlock) {
+            Some(value) => Some(ModApi::parse_device_block(value)?),
+            None => None,
+        };
+        Some(ModEvent { event_type, list_version, slot, descriptor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::datatypes::AdvancedVersion;
+
+    fn device(adr : u16, serial : &str) -> Descriptor {
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_adr(adr);
+        desc.set_product_name("IO Module".to_string());
+        desc.set_vendor_name("Noreya".to_string());
+        desc.set_vendor_product_id("io".to_string());
+        desc.set_serial(serial.to_string());
+        desc.set_fw_version(AdvancedVersion::new('S', 1, 0, 0));
+        desc.set_bootloader_state("not supported".to_string());
+        desc
+    }
+
+    #[test]
+    fn event_roundtrip() {
+        let event = ModEvent::device_list_changed(7);
+        let response = event.to_response();
+        assert!(response.is_event());
+        assert_eq!(ModEvent::from_response(&response), Some(event));
+        assert_eq!(ModEvent::from_response(&Response::new_empty_response()), None);
+
+        let event = ModEvent::hotplug(EventType::DeviceRemoved, 8, device(3, "s-3"));
+        assert_eq!(ModEvent::from_response(&event.to_response()), Some(event));
+    }
+
+    #[test]
+    fn diff_reports_hotplug_events() {
+        let old = vec![device(1, "s-1"), device(2, "s-2")];
+        let new = vec![device(2, "s-2b"), device(3, "s-3")];
+
+        let events = ModEvent::diff(&old, &new, 4);
+        let summary : Vec<(EventType,Option<u16>)> = events.iter().map(|event| (event.event_type, event.slot)).collect();
+        assert_eq!(summary, vec![(EventType::DeviceRemoved, Some(1)), (EventType::DeviceUpdated, Some(2)), (EventType::DeviceAdded, Some(3))]);
+        assert_eq!(events[0].descriptor.as_ref().unwrap().serial(), "s-1");
+        assert!(ModEvent::diff(&new, &new, 5).is_empty());
+    }
+}
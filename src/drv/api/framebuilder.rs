@@ -19,7 +19,22 @@ impl RequestBuilder {
     }
 
     pub fn get_descriptor(self, short : bool, dev_adr : u16) -> Request {
-        Request::new_from_bytes(DRV_DEV_ADR,Command::GetDescriptor as u16,&[short as u8, (dev_adr << 8) as u8, (dev_adr & 0xFF) as u8])
+        Request::new_from_bytes(DRV_DEV_ADR,Command::GetDescriptor as u16,&[short as u8, (dev_adr >> 8) as u8, (dev_adr & 0xFF) as u8])
+    }
+
+    pub fn lock_slot(self, dev_adr : u16, exclusive : bool, timeout_ms : u32) -> Request {
+        let mut payload = vec![exclusive as u8, (dev_adr >> 8) as u8, (dev_adr & 0xFF) as u8];
+        payload.extend_from_slice(&timeout_ms.to_be_bytes());
+        Request::new_from_vec(DRV_DEV_ADR,Command::LockSlot as u16,payload)
+    }
+
+    pub fn unlock_slot(self, dev_adr : u16) -> Request {
+        Request::new_from_bytes(DRV_DEV_ADR,Command::UnlockSlot as u16,&[(dev_adr >> 8) as u8, (dev_adr & 0xFF) as u8])
+    }
+
+    /// Enables or disables pushed events (see `ModEvent`) for this connection
+    pub fn subscribe_events(self, enable : bool) -> Request {
+        Request::new_from_bytes(DRV_DEV_ADR,Command::SubscribeEvents as u16,&[enable as u8])
     }
 
     pub fn device_command(self, dev_adr : u16, payload : &[u8]) -> Request{
@@ -1,20 +1,44 @@
-use std::path::PathBuf;
-
+use std::collections::VecDeque;
 use std::time::Duration;
 use std::io::{Error, ErrorKind};
 use std::os::unix::net::UnixStream;
 
 use crate::sdbp::response::SdbpResponse;
-use crate::drv::api::{FrameBuilder, Tag, TlvValue, Response};
+use crate::sdbp::response::core::transaction_error::TransactionError;
+#[cfg(feature = "firmware-experimental")]
+use crate::sdbp::firmware::BootloaderTransport;
+use crate::drv::api::{FrameBuilder, ModApi, ModEvent, Tag, TlvValue, Response};
+use crate::drv::api::Error as ModApiError;
 use crate::util::{UnixStreamReader, Connection};
 use crate::datatypes::{Version, Descriptor};
 use std::convert::TryFrom;
-use crate::datatypes::BootloaderState;
 
 pub struct Manager {
    com : UnixStreamReader,
    is_selected : bool,
     selected_slot : u16,
+    subscribed : bool,
+    events : VecDeque<ModEvent>,
+}
+
+/// Blocking iterator over the events of a subscribed `Manager`, see `Manager::events`
+pub struct EventIter<'a> {
+    manager : &'a mut Manager,
+    closed : bool,
+}
+
+impl Iterator for EventIter<'_> {
+    type Item = Result<ModEvent,Error>;
+
+    /// Waits for the next event, ends after the first error (e.g. the socket was closed)
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.closed {
+            return None;
+        }
+        let result = self.manager.next_event();
+        self.closed = result.is_err();
+        Some(result)
+    }
 }
 
 
@@ -45,6 +69,23 @@ impl ModApiInfo {
 #[allow(unused)]
 impl Manager {
 
+    fn check_error(response : &Response) -> Result<(),Error> {
+        if !response.is_error() {
+            return Ok(());
+        }
+        match response.get_error() {
+            Some(ModApiError::SlotLocked) => Err(Error::new(ErrorKind::PermissionDenied, ModApiError::SlotLocked.msg())),
+            Some(ModApiError::PermissionDenied) => Err(Error::new(ErrorKind::PermissionDenied, ModApiError::PermissionDenied.msg())),
+            Some(ModApiError::DeviceNotConnected) => Err(Error::new(ErrorKind::NotConnected, ModApiError::DeviceNotConnected.msg())),
+            Some(ModApiError::TransactionError) => match response.get_error_data().first() {
+                Some(code) => Err(TransactionError::new(*code).into()),
+                None => Err(Error::new(ErrorKind::InvalidData, ModApiError::TransactionError.msg())),
+            },
+            Some(err) => Err(Error::new(ErrorKind::InvalidData, err.msg())),
+            None => Err(Error::new(ErrorKind::InvalidData, "Unknown error response")),
+        }
+    }
+
     pub fn new(socket_path : String, timeout : Option<Duration>) -> Result<Manager,Error> {
 
         let stream = match UnixStream::connect(socket_path) {
@@ -54,7 +95,83 @@ impl Manager {
                 return Err(err);
             }
         };
-        Ok(Manager{com : UnixStreamReader::from_unix_stream(stream,timeout), is_selected : false, selected_slot : 0})
+        Ok(Manager{com : UnixStreamReader::from_unix_stream(stream,timeout), is_selected : false, selected_slot : 0, subscribed : false, events : VecDeque::new()})
+    }
+
+    /// Reads the next response, events received in between are queued for `next_event`
+    fn read_response(&mut self) -> Result<Response,Error> {
+        loop {
+            let raw = self.com.read_msg()?;
+            let response = match Response::from_bytes(raw.as_slice()) {
+                Some(value) => value,
+                None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
+            };
+
+            if !response.is_event() {
+                return Ok(response);
+            }
+            match ModEvent::from_response(&response) {
+                Some(event) => self.events.push_back(event),
+                None => warn!("Dropped invalid event"),
+            }
+        }
+    }
+
+    /// Enables or disables the delivery of device list and hotplug events for this connection
+    pub fn subscribe_events(&mut self, enable : bool) -> Result<(),Error> {
+
+        self.com.write_msg(FrameBuilder::request().subscribe_events(enable).to_bytes())?;
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
+
+        self.subscribed = enable;
+        if !enable {
+            self.events.clear();
+        }
+        Ok(())
+    }
+
+    /// Blocks until the next event is received, read timeouts of the connection are ignored
+    pub fn next_event(&mut self) -> Result<ModEvent,Error> {
+
+        if !self.subscribed {
+            return Err(Error::new(ErrorKind::NotConnected,"Events are not subscribed"));
+        }
+
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            let raw = match self.com.read_msg() {
+                Ok(value) => value,
+                Err(err) if err.kind() == ErrorKind::TimedOut => continue,
+                Err(err) => return Err(err),
+            };
+
+            match Response::from_bytes(raw.as_slice()).as_ref().and_then(ModEvent::from_response) {
+                Some(event) => return Ok(event),
+                None => warn!("Dropped unexpected message while waiting for events"),
+            }
+        }
+    }
+
+    /// Subscribes to events and returns a blocking iterator over them
+    pub fn events(&mut self) -> Result<EventIter<'_>,Error> {
+        if !self.subscribed {
+            self.subscribe_events(true)?;
+        }
+        Ok(EventIter { manager : self, closed : false })
+    }
+
+    /// Subscribes to events and calls `callback` for each of them until it returns `false`
+    pub fn on_event<F>(&mut self, mut callback : F) -> Result<(),Error> where F : FnMut(&ModEvent) -> bool {
+        for event in self.events()? {
+            if !callback(&event?) {
+                break;
+            }
+        }
+        Ok(())
     }
 
     pub fn select_via_slot(&mut self, slot : u16) -> Result<(),Error>{
@@ -114,6 +231,37 @@ impl Manager {
     }
 
 
+    /// Acquires a lease on the selected slot.
+    ///
+    /// An exclusive lease rejects mutating commands of all other clients, a shared lease rejects
+    /// them for clients which do not hold the lease. Acquiring again renews the lease.
+    /// The lease expires after `timeout` or when the connection is closed.
+    pub fn lock_slot(&mut self, exclusive : bool, timeout : Duration) -> Result<(),Error> {
+
+        if !self.is_selected {
+            return Err(Error::new(ErrorKind::AddrNotAvailable,"Device is not selected"));
+        }
+
+        let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let request = FrameBuilder::request().lock_slot(self.selected_slot, exclusive, timeout_ms);
+        self.com.write_msg(request.to_bytes())?;
+        let response = self.read_response()?;
+        Manager::check_error(&response)
+    }
+
+    /// Releases the lease on the selected slot
+    pub fn unlock_slot(&mut self) -> Result<(),Error> {
+
+        if !self.is_selected {
+            return Err(Error::new(ErrorKind::AddrNotAvailable,"Device is not selected"));
+        }
+
+        let request = FrameBuilder::request().unlock_slot(self.selected_slot);
+        self.com.write_msg(request.to_bytes())?;
+        let response = self.read_response()?;
+        Manager::check_error(&response)
+    }
+
     pub fn get_info(&mut self) -> Result<ModApiInfo,Error> {
 
         match self.com.write_msg(FrameBuilder::request().info().to_bytes())     {
@@ -121,15 +269,8 @@ impl Manager {
             Err(err) => return Err(err),
         }
 
-        let raw = match self.com.read_msg() {
-            Ok(value) => value,
-            Err(err) => return Err(err)
-        };
-
-        let response = match Response::from_bytes(raw.as_slice()) {
-            Some(value) => value,
-            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
-        };
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
 
         let tlv = match TlvValue::try_from(response.get_payload()) {
             Ok(value ) => value,
@@ -150,15 +291,8 @@ impl Manager {
             Err(err) => return Err(err),
         }
 
-        let raw = match self.com.read_msg() {
-            Ok(value) => value,
-            Err(err) => return Err(err)
-        };
-
-        let response = match Response::from_bytes(raw.as_slice()) {
-            Some(value) => value,
-            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
-        };
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
 
         let tlv = match TlvValue::try_from(response.get_payload()) {
             Ok(value ) => value,
@@ -169,36 +303,10 @@ impl Manager {
 
         for block in tlv.members() {
             if block.0 == Tag::DeviceBlock {
-                let mut desc = Descriptor::new(PathBuf::new());
-                for (tag, value) in block.1.members() {
-                    match tag {
-
-                        Tag::DeviceAddress => desc.set_adr(value.as_u16().unwrap()),
-                        Tag::BootloaderState => {
-                            let bl_state = match  BootloaderState::try_from(value.as_string().unwrap().to_owned().as_str()) {
-                                Ok(value) => {value},
-                                Err(_) => panic!("Could not parse bootloader state!"),
-                            };
-                            let bl_state = format!("{}", bl_state);
-                            desc.set_bootloader_state(bl_state)
-                        },
-                        Tag::HardwareVersion => desc.set_hw_version(value.as_version().unwrap().clone()),
-                        Tag::FirmwareVersion => desc.set_fw_version(value.as_advanced_version().unwrap().clone()),
-                        Tag::SupportedSdbpVersion => desc.set_protocol_version(value.as_version().unwrap().clone()),
-                        Tag::MaxFrameSize => desc.set_max_frame_size(value.as_u16().unwrap()),
-                        Tag::SerialNumber => desc.set_serial(value.as_string().unwrap().clone()),
-                        Tag::ProductName => desc.set_product_name(value.as_string().unwrap().clone()),
-                        Tag::VendorName => desc.set_vendor_name(value.as_string().unwrap().clone()),
-                        Tag::MaxPower3v3 => desc.set_max_power_3v3(value.as_u16().unwrap()),
-                        Tag::MaxPower5v => desc.set_max_power_5v(value.as_u16().unwrap()),
-                        Tag::MaxPower12v => desc.set_max_power_12v(value.as_u16().unwrap()),
-                        Tag::MaxSclkSpeed => desc.set_max_sclk_speed(value.as_32().unwrap()),
-                        Tag::VendorProductId => desc.set_vendor_product_id(value.as_string().unwrap().clone()),
-                        Tag::DeviceSession => desc.set_device_session(value.as_string().unwrap().clone()),
-                        _ => (),
-                    }
+                match ModApi::parse_device_block(&block.1) {
+                    Some(desc) => result.push(desc),
+                    None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Invalid device block (get_device_list).")),
                 }
-                result.push(desc);
             }
         }
         return Ok(result);
@@ -219,15 +327,8 @@ impl Manager {
             Err(err) => return Err(err),
         }
 
-        let raw_response = match self.com.read_msg() {
-            Ok(value) => value,
-            Err(err) => return Err(err),
-        };
-
-        let response = match Response::from_bytes(raw_response.as_slice()) {
-            Some(value) => value,
-            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
-        };
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
 
         let tlv = match TlvValue::try_from(response.get_payload()) {
             Ok(value ) => value,
@@ -253,15 +354,8 @@ impl Manager {
             Err(err) => return Err(err),
         }
 
-        let raw_response = match self.com.read_msg() {
-            Ok(value) => value,
-            Err(err) => return Err(err),
-        };
-
-        let response = match Response::from_bytes(raw_response.as_slice()) {
-            Some(value) => value,
-            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
-        };
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
 
         let tlv = match TlvValue::try_from(response.get_payload()) {
             Ok(value ) => value,
@@ -270,7 +364,20 @@ impl Manager {
             },
         };
 
-        T::from_raw(tlv[Tag::Response].as_bytes().unwrap().clone())
+        let raw = tlv[Tag::Response].as_bytes().unwrap().clone();
+        if TransactionError::is_transaction_error(raw.as_slice()) {
+            return Err(TransactionError::from_raw(raw)?.into());
+        }
+        T::from_raw(raw)
+    }
+
+    /// Sends a raw frame to the selected device and returns the raw response of the module
+    pub fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,Error> {
+        let tlv = self.raw_command(request)?;
+        match tlv.get(&Tag::Response).and_then(|value| value.as_bytes()) {
+            Some(raw) => Ok(raw.clone()),
+            None => Err(Error::new(ErrorKind::InvalidData, "TLV Parsing failed (transfer).")),
+        }
     }
 
     pub fn get_descriptor(&mut self, device : &mut Descriptor, short : bool) -> Result<(),std::io::Error>{
@@ -282,15 +389,8 @@ impl Manager {
             Ok(_) => (),
             Err(err) => return Err(err),
         }
-        let raw_response = match self.com.read_msg() {
-            Ok(value) => value,
-            Err(err) => return Err(err),
-        };
-
-        let response = match Response::from_bytes(raw_response.as_slice()) {
-            Some(value) => value,
-            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Response invalid")),
-        };
+        let response = self.read_response()?;
+        Manager::check_error(&response)?;
 
         let tlv = match TlvValue::try_from(response.get_payload()) {
             Ok(value ) => value,
@@ -304,5 +404,26 @@ impl Manager {
     }
 }
 
+/// Flashes the selected module through the driver, see `sdbp::firmware::FirmwareUpdater`
+///
+/// The driver has to accept modules in bootloader mode, see `DeviceFilter::accept_bootloader`.
+#[cfg(feature = "firmware-experimental")]
+impl BootloaderTransport for Manager {
 
+    fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,Error> {
+        Manager::transfer(self, request)
+    }
 
+    /// Waits until the driver lists the slot again, the module is detected anew after a mode switch
+    fn reconnect(&mut self) -> Result<(),Error> {
+        const TRIES : u32 = 50;
+        let slot = self.selected_slot;
+        for _ in 0..TRIES {
+            if self.select_via_slot(slot).is_ok() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Err(Error::new(ErrorKind::NotFound, format!("Device with address {} did not reappear", slot)))
+    }
+}
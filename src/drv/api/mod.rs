@@ -3,6 +3,7 @@ mod parser;
 mod request;
 mod response;
 mod error;
+mod event;
 mod tlv;
 
 mod framebuilder;
@@ -12,6 +13,7 @@ pub use request::*;
 pub use response::*;
 pub use parser::*;
 pub use error::*;
+pub use event::*;
 pub use framebuilder::*;
 pub use command::*;
 pub use tlv::*;
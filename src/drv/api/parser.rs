@@ -1,5 +1,7 @@
 use super::*;
-use crate::datatypes::{Descriptor, Version};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use crate::datatypes::{BootloaderState, Descriptor, Version};
 
 pub struct ModApi {}
 
@@ -40,39 +42,72 @@ impl ModApi {
 
             trace!("Descriptor: {:?}",device);
             let array = tlv.push(Tag::DeviceBlock,TlvValue::new_array()).unwrap();
+            ModApi::push_device(array, device, mode);
+        }
+        tlv
+    }
 
-            if mode == false {
-
-                array.push(Tag::DeviceAddress,TlvValue::from(device.adr()));
-                array.push(Tag::ProductName,TlvValue::from(device.product_name().clone()));
-                array.push(Tag::VendorName,TlvValue::from(device.vendor_name().clone()));
-                array.push(Tag::VendorProductId,TlvValue::from(device.vendor_product_id().clone()));
-                array.push(Tag::BootloaderState,TlvValue::from(device.bootloader_state()));
-
-                array.push(Tag::FirmwareVersion,TlvValue::from(device.fw_version().clone()));
-                array.push(Tag::HardwareVersion,TlvValue::from(device.hw_version().clone()));
-                array.push(Tag::SupportedSdbpVersion,TlvValue::from(device.protocol_version().clone()));
-
-                array.push(Tag::MaxFrameSize,TlvValue::from(device.max_frame_size()));
-                array.push(Tag::MaxSclkSpeed,TlvValue::from(device.max_sclk_speed()));
-                array.push(Tag::MaxPower12v,TlvValue::from(device.max_power_12v()));
-                array.push(Tag::MaxPower5v,TlvValue::from(device.max_power_5v()));
-                array.push(Tag::MaxPower3v3,TlvValue::from(device.max_power_3v3()));
-
-                array.push(Tag::SerialNumber, TlvValue::from(device.serial().clone()));
-            } else {
+    /// Writes the descriptor fields into a `DeviceBlock`, `mode` selects the short form
+    pub(crate) fn push_device(array : &mut TlvValue, device : &Descriptor, mode : bool) {
+        if !mode {
+
+            array.push(Tag::DeviceAddress,TlvValue::from(device.adr()));
+            array.push(Tag::ProductName,TlvValue::from(device.product_name().clone()));
+            array.push(Tag::VendorName,TlvValue::from(device.vendor_name().clone()));
+            array.push(Tag::VendorProductId,TlvValue::from(device.vendor_product_id().clone()));
+            array.push(Tag::BootloaderState,TlvValue::from(device.bootloader_state()));
+
+            array.push(Tag::FirmwareVersion,TlvValue::from(device.fw_version().clone()));
+            array.push(Tag::HardwareVersion,TlvValue::from(device.hw_version().clone()));
+            array.push(Tag::SupportedSdbpVersion,TlvValue::from(device.protocol_version().clone()));
+
+            array.push(Tag::MaxFrameSize,TlvValue::from(device.max_frame_size()));
+            array.push(Tag::MaxSclkSpeed,TlvValue::from(device.max_sclk_speed()));
+            array.push(Tag::MaxPower12v,TlvValue::from(device.max_power_12v()));
+            array.push(Tag::MaxPower5v,TlvValue::from(device.max_power_5v()));
+            array.push(Tag::MaxPower3v3,TlvValue::from(device.max_power_3v3()));
+
+            array.push(Tag::SerialNumber, TlvValue::from(device.serial().clone()));
+        } else {
+
+            array.push(Tag::DeviceAddress,TlvValue::from(device.adr()));
+            array.push(Tag::FirmwareVersion,TlvValue::from(device.fw_version().clone()));
+            array.push(Tag::HardwareVersion,TlvValue::from(device.hw_version().clone()));
+            array.push(Tag::SupportedSdbpVersion,TlvValue::from(device.protocol_version().clone()));
+            array.push(Tag::MaxFrameSize,TlvValue::from(device.max_frame_size()));
+            array.push(Tag::SerialNumber, TlvValue::from(device.serial().clone()));
+            array.push(Tag::DeviceSession, TlvValue::from(device.device_session().clone()));
 
-                array.push(Tag::DeviceAddress,TlvValue::from(device.adr()));
-                array.push(Tag::FirmwareVersion,TlvValue::from(device.fw_version().clone()));
-                array.push(Tag::HardwareVersion,TlvValue::from(device.hw_version().clone()));
-                array.push(Tag::SupportedSdbpVersion,TlvValue::from(device.protocol_version().clone()));
-                array.push(Tag::MaxFrameSize,TlvValue::from(device.max_frame_size()));
-                array.push(Tag::SerialNumber, TlvValue::from(device.serial().clone()));
-                array.push(Tag::DeviceSession, TlvValue::from(device.device_session().clone()));
+        }
+    }
 
+    /// Reads a descriptor from a `DeviceBlock`, fails on malformed fields
+    pub fn parse_device_block(block : &TlvValue) -> Option<Descriptor> {
+        let mut desc = Descriptor::new(PathBuf::new());
+        for (tag, value) in block.members() {
+            match tag {
+                Tag::DeviceAddress => desc.set_adr(value.as_u16()?),
+                Tag::BootloaderState => {
+                    let bl_state = BootloaderState::try_from(value.as_string()?.as_str()).ok()?;
+                    desc.set_bootloader_state(format!("{}", bl_state))
+                },
+                Tag::HardwareVersion => desc.set_hw_version(value.as_version()?.clone()),
+                Tag::FirmwareVersion => desc.set_fw_version(value.as_advanced_version()?.clone()),
+                Tag::SupportedSdbpVersion => desc.set_protocol_version(value.as_version()?.clone()),
+                Tag::MaxFrameSize => desc.set_max_frame_size(value.as_u16()?),
+                Tag::SerialNumber => desc.set_serial(value.as_string()?.clone()),
+                Tag::ProductName => desc.set_product_name(value.as_string()?.clone()),
+                Tag::VendorName => desc.set_vendor_name(value.as_string()?.clone()),
+                Tag::MaxPower3v3 => desc.set_max_power_3v3(value.as_u16()?),
+                Tag::MaxPower5v => desc.set_max_power_5v(value.as_u16()?),
+                Tag::MaxPower12v => desc.set_max_power_12v(value.as_u16()?),
+                Tag::MaxSclkSpeed => desc.set_max_sclk_speed(value.as_32()?),
+                Tag::VendorProductId => desc.set_vendor_product_id(value.as_string()?.clone()),
+                Tag::DeviceSession => desc.set_device_session(value.as_string()?.clone()),
+                _ => (),
             }
         }
-        return tlv;
+        Some(desc)
     }
 
     pub fn get_descriptor(devices : &Vec<Descriptor>, payload : &[u8]) -> Response{
@@ -2,6 +2,7 @@ use std::convert::TryInto;
 use super::Command;
 use super::error::Error as MdError;
 use std::fmt;
+use std::convert::TryFrom;
 
 pub struct Response {
     frame: Vec<u8>
@@ -25,10 +26,41 @@ impl Response {
         Response {frame}
     }
 
+    /// Frame pushed to subscribed clients, the payload is an `EventBlock` TLV
+    pub fn new_event() -> Response{
+        let mut frame = Vec::<u8>::new();
+        frame.extend_from_slice(&(Command::Event as u16).to_ne_bytes());
+        Response {frame}
+    }
+
     pub fn get_op_id(&self) -> u16 {
         u16::from_ne_bytes(self.frame.as_slice()[0..2].try_into().unwrap())
     }
 
+    pub fn is_error(&self) -> bool {
+        self.get_op_id() == Command::Error as u16
+    }
+
+    pub fn is_event(&self) -> bool {
+        self.get_op_id() == Command::Event as u16
+    }
+
+    pub fn get_error(&self) -> Option<MdError> {
+        if !self.is_error() || self.frame.len() < Response::HEADER_OFFSET + 2 {
+            return None;
+        }
+        let code = u16::from_ne_bytes(self.frame.as_slice()[Response::HEADER_OFFSET..Response::HEADER_OFFSET + 2].try_into().unwrap());
+        MdError::try_from(code).ok()
+    }
+
+    /// Bytes following the error code of an error response
+    pub fn get_error_data(&self) -> &[u8] {
+        if !self.is_error() || self.frame.len() < Response::HEADER_OFFSET + 2 {
+            return &[];
+        }
+        &self.frame.as_slice()[Response::HEADER_OFFSET + 2..]
+    }
+
     pub fn get_payload(&self) -> &[u8]{
         &self.frame.as_slice()[Response::HEADER_OFFSET..self.frame.len()]
     }
@@ -37,6 +37,9 @@ pub enum Tag {
     SerialNumber =  0x200E,
     DeviceTunnel =  0x3000,
     Response =  0x3001,
+    EventBlock = 0x4000,
+    EventType = 0x4001,
+    ListVersion = 0x4002,
     ErrorValue = 0xEEEE,
     ErrorMsg = 0xEEEF,
 }
@@ -74,6 +77,9 @@ impl Tag {
             Tag::SerialNumber => 0x200E,
             Tag::DeviceTunnel => 0x3000,
             Tag::Response => 0x3001,
+            Tag::EventBlock => 0x4000,
+            Tag::EventType => 0x4001,
+            Tag::ListVersion => 0x4002,
             Tag::ErrorValue => 0xEEEE,
             Tag::ErrorMsg => 0xEEEF,
         } as u16;
@@ -112,6 +118,9 @@ impl TryFrom<u16> for Tag {
             x if  x == ( Tag::SerialNumber as u16 ) => Ok(Tag::SerialNumber),
             x if  x == ( Tag::DeviceTunnel as u16 ) => Ok(Tag::DeviceTunnel),
             x if  x == ( Tag::Response as u16 ) => Ok(Tag::Response),
+            x if  x == ( Tag::EventBlock as u16 ) => Ok(Tag::EventBlock),
+            x if  x == ( Tag::EventType as u16 ) => Ok(Tag::EventType),
+            x if  x == ( Tag::ListVersion as u16 ) => Ok(Tag::ListVersion),
             x if  x == ( Tag::ErrorValue as u16 ) => Ok(Tag::ErrorValue),
             x if  x == ( Tag::ErrorMsg as u16 ) => Ok(Tag::ErrorMsg),
             _ => Err(()),
@@ -330,6 +339,9 @@ impl TryFrom<&[u8]> for TlvValue {
                 Tag::SerialNumber => Parser::parse_string(&value[offset..offset_end]),
                 Tag::DeviceTunnel => TlvValue::try_from(&value[offset..offset_end]),
                 Tag::Response => Ok(TlvValue::Bytes(Vec::from(&value[offset..offset_end]))),
+                Tag::EventBlock => TlvValue::try_from(&value[offset..offset_end]),
+                Tag::EventType => Parser::parse_u16(&value[offset..offset_end]),
+                Tag::ListVersion => Parser::parse_u32(&value[offset..offset_end]),
                 Tag::ErrorValue => Parser::parse_u16(&value[offset..offset_end]),
                 Tag::ErrorMsg  => Parser::parse_string(&value[offset..offset_end]),
                 _ => {
@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+use nix::sys::socket::{getsockopt, sockopt};
+use nix::unistd::{getgrouplist, Gid, Uid, User};
+
+use crate::sdbp::ModuleProtocol;
+use crate::sdbp::request::core::protocol;
+
+/// Credentials of the process on the other end of a client socket (SO_PEERCRED)
+#[derive(Debug,Clone,PartialEq)]
+pub struct PeerCredentials {
+    pub pid : i32,
+    pub uid : u32,
+    pub gid : u32,
+    pub groups : Vec<u32>,
+}
+
+impl PeerCredentials {
+
+    /// Reads the peer credentials and resolves the supplementary groups of the peer user
+    pub fn from_stream<S : AsRawFd>(stream : &S) -> Result<PeerCredentials,Error> {
+
+        let cred = match getsockopt(stream.as_raw_fd(), sockopt::PeerCredentials) {
+            Ok(value) => value,
+            Err(err) => return Err(Error::new(ErrorKind::PermissionDenied, format!("Could not read peer credentials: {}", err))),
+        };
+
+        let mut groups = vec![cred.gid()];
+        if let Ok(Some(user)) = User::from_uid(Uid::from_raw(cred.uid())) {
+            if let Ok(name) = CString::new(user.name) {
+                match getgrouplist(&name, Gid::from_raw(cred.gid())) {
+                    Ok(list) => groups = list.iter().map(|gid| gid.as_raw()).collect(),
+                    Err(err) => debug!("Could not resolve groups of uid {}: {}", cred.uid(), err),
+                }
+            }
+        }
+
+        Ok(PeerCredentials { pid : cred.pid(), uid : cred.uid(), gid : cred.gid(), groups })
+    }
+}
+
+/// Command classes used for authorization decisions
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandClass {
+    /// Driver info, descriptors and telemetry reads
+    ReadOnly,
+    /// Module specific control commands (outputs, fans, usb ports, ...)
+    IoControl,
+    /// Core control commands (mode changes, resets, link settings)
+    CoreControl,
+    /// Commands which switch or limit the power of the system
+    PowerAction,
+}
+
+impl CommandClass {
+
+    /// Classifies core SDBP frames, custom classes are treated as `IoControl`
+    ///
+    /// Only the descriptor, notification and dummy classes are `ReadOnly`, every other core class
+    /// (control, bootloader, unknown classes) is `CoreControl`.
+    pub fn from_core_frame(raw : &[u8]) -> CommandClass {
+        if raw.len() < 2 || raw[0] != protocol::CLASS_ID {
+            return CommandClass::IoControl;
+        }
+        match raw[1] {
+            protocol::classes::descriptor::ID => CommandClass::ReadOnly,
+            protocol::classes::notification::ID => CommandClass::ReadOnly,
+            protocol::classes::dummy::ID => CommandClass::ReadOnly,
+            _ => CommandClass::CoreControl,
+        }
+    }
+
+    /// Classifies frames of a module, custom classes of protocols without a classifier, including
+    /// `Generic` for modules of unknown protocol, are treated as `PowerAction`
+    pub fn from_frame(protocol : ModuleProtocol, raw : &[u8]) -> CommandClass {
+        match protocol {
+            #[cfg(feature = "io")]
+            ModuleProtocol::Io => CommandClass::from_io_frame(raw),
+            #[cfg(feature = "power")]
+            ModuleProtocol::Power => CommandClass::from_power_frame(raw),
+            #[cfg(feature = "bmc")]
+            ModuleProtocol::Bmc => CommandClass::from_bmc_frame(raw),
+            _ => match CommandClass::from_core_frame(raw) {
+                CommandClass::IoControl => CommandClass::PowerAction,
+                class => class,
+            },
+        }
+    }
+
+    #[cfg(feature = "io")]
+    pub fn from_io_frame(raw : &[u8]) -> CommandClass {
+        use crate::sdbp::request::custom::io::protocol::*;
+
+        if raw.len() < 3 || raw[0] != CLASS_ID {
+            return CommandClass::from_core_frame(raw);
+        }
+        match (raw[1], raw[2]) {
+            (classes::input_class::ID, classes::input_class::operation_code::GET_VALUES) => CommandClass::ReadOnly,
+            (classes::input_class::ID, classes::input_class::operation_code::GET_CURRENT_VALUES) => CommandClass::ReadOnly,
+            _ => CommandClass::IoControl,
+        }
+    }
+
+    #[cfg(feature = "power")]
+    pub fn from_power_frame(raw : &[u8]) -> CommandClass {
+        use crate::sdbp::request::custom::power::protocol::*;
+
+        if raw.len() < 3 || raw[0] != CLASS_ID {
+            return CommandClass::from_core_frame(raw);
+        }
+        match (raw[1], raw[2]) {
+            (classes::power_class::ID, classes::power_class::operation_code::CURRENT_LIMIT) => CommandClass::PowerAction,
+            (classes::power_class::ID, _) => CommandClass::ReadOnly,
+            (classes::temperature_control_class::ID, classes::temperature_control_class::operation_code::FAN_CONTROL) => CommandClass::IoControl,
+            (classes::temperature_control_class::ID, classes::temperature_control_class::operation_code::FAN_RPM_CONTROL) => CommandClass::IoControl,
+            (classes::temperature_control_class::ID, _) => CommandClass::ReadOnly,
+            _ => CommandClass::IoControl,
+        }
+    }
+
+    #[cfg(feature = "bmc")]
+    pub fn from_bmc_frame(raw : &[u8]) -> CommandClass {
+        use crate::sdbp::request::custom::bmc::protocol::*;
+
+        if raw.len() < 3 || raw[0] != CLASS_ID {
+            return CommandClass::from_core_frame(raw);
+        }
+        match (raw[1], raw[2]) {
+            (classes::input::ID, _) => CommandClass::ReadOnly,
+            (classes::usbhub::ID, classes::usbhub::operation_code::GET_HUB_STATE) => CommandClass::ReadOnly,
+            (classes::usbhub::ID, classes::usbhub::operation_code::GET_USB_SLOT_STATE) => CommandClass::ReadOnly,
+            (classes::usbhub::ID, classes::usbhub::operation_code::GET_PORT_MAPPING) => CommandClass::ReadOnly,
+            (classes::watchdog::ID, classes::watchdog::operation_code::GET_TIMEOUT) => CommandClass::ReadOnly,
+            (classes::watchdog::ID, classes::watchdog::operation_code::GET_TIME_LEFT) => CommandClass::ReadOnly,
+            (classes::watchdog::ID, classes::watchdog::operation_code::GET_SHUTDOWN_TIMEOUT) => CommandClass::ReadOnly,
+            (classes::watchdog::ID, classes::watchdog::operation_code::EMERGENCY_MODE_STATE) => CommandClass::ReadOnly,
+            (classes::watchdog::ID, classes::watchdog::operation_code::ALIVE) => CommandClass::IoControl,
+            (classes::watchdog::ID, classes::watchdog::operation_code::SAVE_CONFIG) => CommandClass::IoControl,
+            (classes::watchdog::ID, _) => CommandClass::PowerAction,
+            (classes::cmc::ID, _) => CommandClass::PowerAction,
+            _ => CommandClass::IoControl,
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessSubject {
+    Any,
+    User(u32),
+    Group(u32),
+}
+
+#[derive(Debug,Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccessRule {
+    pub subject : AccessSubject,
+    pub allow : Vec<CommandClass>,
+}
+
+impl AccessRule {
+
+    fn matches(&self, peer : &PeerCredentials) -> bool {
+        match self.subject {
+            AccessSubject::Any => true,
+            AccessSubject::User(uid) => peer.uid == uid,
+            AccessSubject::Group(gid) => peer.gid == gid || peer.groups.contains(&gid),
+        }
+    }
+}
+
+/// Policy table mapping users and groups to the command classes they may use.
+///
+/// A class is granted if any matching rule allows it. Device frames are classified with the
+/// protocol `modules` assigns to the vendor product id of the addressed module, custom frames
+/// to other modules are `PowerAction`.
+#[derive(Debug,Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccessPolicy {
+    pub rules : Vec<AccessRule>,
+    /// Protocol of the modules by vendor product id
+    #[serde(default)]
+    pub modules : BTreeMap<String,ModuleProtocol>,
+}
+
+impl Default for AccessPolicy {
+    /// Every peer may read and control the IO of modules listed in `modules`, root and the user of
+    /// the driver may use all classes
+    fn default() -> Self {
+        let all = vec![CommandClass::ReadOnly, CommandClass::IoControl, CommandClass::CoreControl, CommandClass::PowerAction];
+        let mut rules = vec![
+            AccessRule { subject : AccessSubject::Any, allow : vec![CommandClass::ReadOnly, CommandClass::IoControl] },
+            AccessRule { subject : AccessSubject::User(0), allow : all.clone() },
+        ];
+        let owner = Uid::current().as_raw();
+        if owner != 0 {
+            rules.push(AccessRule { subject : AccessSubject::User(owner), allow : all });
+        }
+        AccessPolicy::new(rules)
+    }
+}
+
+impl AccessPolicy {
+
+    pub fn new(rules : Vec<AccessRule>) -> AccessPolicy {
+        AccessPolicy { rules, modules : BTreeMap::new() }
+    }
+
+    /// Grants every class to every peer
+    pub fn allow_all() -> AccessPolicy {
+        AccessPolicy::new(vec![AccessRule {
+            subject : AccessSubject::Any,
+            allow : vec![CommandClass::ReadOnly, CommandClass::IoControl, CommandClass::CoreControl, CommandClass::PowerAction],
+        }])
+    }
+
+    pub fn from_file(path : &PathBuf) -> Result<AccessPolicy,Error> {
+        let content = fs::read(path)?;
+        match serde_json::from_slice::<AccessPolicy>(content.as_slice()) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::new(ErrorKind::InvalidData, format!("Could not parse access policy: {}", err))),
+        }
+    }
+
+    /// Classifies the frames of modules with `vendor_product_id` with the classifier of `protocol`
+    pub fn with_module(mut self, vendor_product_id : &str, protocol : ModuleProtocol) -> AccessPolicy {
+        self.modules.insert(vendor_product_id.to_string(), protocol);
+        self
+    }
+
+    /// Classifies a frame sent to a module, `vendor_product_id` is `None` if the module is not known
+    pub fn classify(&self, vendor_product_id : Option<&str>, raw : &[u8]) -> CommandClass {
+        let protocol = vendor_product_id.and_then(|id| self.modules.get(id)).copied().unwrap_or_default();
+        CommandClass::from_frame(protocol, raw)
+    }
+
+    pub fn is_allowed(&self, peer : &PeerCredentials, class : &CommandClass) -> bool {
+        self.rules.iter().any(|rule| rule.matches(peer) && rule.allow.contains(class))
+    }
+
+    /// Returns true if the peer may use any class besides `ReadOnly`
+    pub fn may_control(&self, peer : &PeerCredentials) -> bool {
+        self.rules.iter().any(|rule| rule.matches(peer) && rule.allow.iter().any(|class| *class != CommandClass::ReadOnly))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(uid : u32, gid : u32, groups : Vec<u32>) -> PeerCredentials {
+        PeerCredentials { pid : 1, uid, gid, groups }
+    }
+
+    #[test]
+    fn policy_grants_by_user_and_group() {
+        let policy = AccessPolicy::new(vec![
+            AccessRule { subject : AccessSubject::Any, allow : vec![CommandClass::ReadOnly] },
+            AccessRule { subject : AccessSubject::Group(100), allow : vec![CommandClass::IoControl] },
+            AccessRule { subject : AccessSubject::User(0), allow : vec![CommandClass::CoreControl, CommandClass::PowerAction] },
+        ]);
+
+        let guest = peer(1000, 1000, vec![1000]);
+        let operator = peer(1001, 1001, vec![1001, 100]);
+        let root = peer(0, 0, vec![0]);
+
+        assert!(policy.is_allowed(&guest, &CommandClass::ReadOnly));
+        assert!(!policy.is_allowed(&guest, &CommandClass::IoControl));
+        assert!(!policy.may_control(&guest));
+        assert!(policy.is_allowed(&operator, &CommandClass::IoControl));
+        assert!(!policy.is_allowed(&operator, &CommandClass::PowerAction));
+        assert!(policy.is_allowed(&root, &CommandClass::PowerAction));
+    }
+
+    #[test]
+    fn policy_from_json() {
+        let json = r#"{"rules":[{"subject":{"group":27},"allow":["read_only","core_control"]}]}"#;
+        let policy : AccessPolicy = serde_json::from_str(json).unwrap();
+        assert!(policy.is_allowed(&peer(1000, 1000, vec![27]), &CommandClass::CoreControl));
+        assert_eq!(policy.classify(None, &[0x01, 0x03, 0x06]), CommandClass::CoreControl);
+        assert_eq!(policy.classify(None, &[0x01, 0x02, 0x04]), CommandClass::ReadOnly);
+    }
+
+    #[cfg(feature = "firmware-experimental")]
+    #[test]
+    fn read_only_peer_is_denied_bootloader_writes() {
+        use protocol::classes::bootloader::{ID, operation_code};
+
+        let policy = AccessPolicy::new(vec![AccessRule { subject : AccessSubject::Any, allow : vec![CommandClass::ReadOnly] }]);
+        let guest = peer(1000, 1000, vec![1000]);
+        for operation in [operation_code::ERASE, operation_code::WRITE] {
+            let class = policy.classify(None, &[protocol::CLASS_ID, ID, operation]);
+            assert_eq!(class, CommandClass::CoreControl);
+            assert!(!policy.is_allowed(&guest, &class));
+        }
+        assert_eq!(policy.classify(None, &[protocol::CLASS_ID, 0x7F, 0x01]), CommandClass::CoreControl);
+    }
+
+    #[cfg(feature = "bmc")]
+    #[test]
+    fn restricted_peer_is_denied_a_bmc_shutdown() {
+        use crate::sdbp::request::custom::bmc::protocol::*;
+
+        let json = r#"{"rules":[{"subject":"any","allow":["read_only","io_control"]}],"modules":{"modules.noreya.tech/bmc":"Bmc"}}"#;
+        let policy : AccessPolicy = serde_json::from_str(json).unwrap();
+        let shutdown = [CLASS_ID, classes::watchdog::ID, classes::watchdog::operation_code::SW_SHUTDOWN];
+
+        let class = policy.classify(Some("modules.noreya.tech/bmc"), &shutdown);
+        assert_eq!(class, CommandClass::PowerAction);
+        assert!(!policy.is_allowed(&peer(1000, 1000, vec![1000]), &class));
+        assert_eq!(policy.classify(Some("modules.noreya.tech/other"), &shutdown), CommandClass::PowerAction);
+
+        // Without a registered protocol the default policy does not know the module is a bmc
+        let policy = AccessPolicy::default();
+        let restricted = peer(Uid::current().as_raw() + 1, 1000, vec![1000]);
+        let class = policy.classify(Some("modules.noreya.tech/bmc"), &shutdown);
+        assert!(!policy.is_allowed(&restricted, &class));
+        assert!(!policy.is_allowed(&restricted, &policy.classify(None, &shutdown)));
+        assert!(policy.is_allowed(&restricted, &CommandClass::IoControl));
+    }
+}
@@ -0,0 +1,400 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::drv::core::FrameHandler;
+use crate::sdbp::response::SdbpResponse;
+
+const MAGIC : &[u8; 8] = b"SDBPCAP\0";
+const FORMAT_VERSION : u16 = 1;
+/// Timestamp, slot, direction and payload length
+const RECORD_HEADER_LENGTH : usize = 8 + 2 + 1 + 4;
+const MAX_PAYLOAD_LENGTH : usize = 0x10000;
+
+const DEFAULT_MAX_FILE_SIZE : u64 = 4 * 1024 * 1024;
+const DEFAULT_MAX_FILES : usize = 4;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CaptureDirection {
+    /// Request written to the module
+    Tx = 0,
+    /// Response read from the module
+    Rx = 1,
+    /// Notification read by the notification handler
+    Notification = 2,
+    /// Failed transfer, the payload holds the error message
+    Error = 3,
+}
+
+impl TryFrom<u8> for CaptureDirection {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, ()> {
+        match value {
+            0 => Ok(CaptureDirection::Tx),
+            1 => Ok(CaptureDirection::Rx),
+            2 => Ok(CaptureDirection::Notification),
+            3 => Ok(CaptureDirection::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Single captured frame.
+///
+/// Records are stored little endian as `[timestamp_us u64][slot u16][direction u8][length u32][payload]`
+/// after the file header `SDBPCAP\0` and the format version (u16).
+#[derive(Debug,Clone,PartialEq)]
+pub struct CaptureRecord {
+    /// Microseconds since the unix epoch
+    pub timestamp_us : u64,
+    pub slot : u16,
+    pub direction : CaptureDirection,
+    pub payload : Vec<u8>,
+}
+
+impl CaptureRecord {
+
+    pub fn now(slot : u16, direction : CaptureDirection, payload : &[u8]) -> CaptureRecord {
+        let timestamp_us = SystemTime::now().duration_since(UNIX_EPOCH).map(|value| value.as_micros() as u64).unwrap_or(0);
+        CaptureRecord { timestamp_us, slot, direction, payload : payload.to_vec() }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(RECORD_HEADER_LENGTH + self.payload.len());
+        result.extend_from_slice(&self.timestamp_us.to_le_bytes());
+        result.extend_from_slice(&self.slot.to_le_bytes());
+        result.push(self.direction as u8);
+        result.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        result.extend_from_slice(&self.payload);
+        result
+    }
+
+    /// Reads the next record, `None` marks the end of the capture
+    pub fn read_from<R : Read>(reader : &mut R) -> Result<Option<CaptureRecord>,Error> {
+        let mut header = [0u8; RECORD_HEADER_LENGTH];
+
+        let mut filled = 0;
+        while filled < header.len() {
+            match reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated capture record")),
+                Ok(len) => filled += len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let timestamp_us = u64::from_le_bytes([header[0], header[1], header[2], header[3], header[4], header[5], header[6], header[7]]);
+        let slot = u16::from_le_bytes([header[8], header[9]]);
+        let direction = CaptureDirection::try_from(header[10])
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid capture direction {}", header[10])))?;
+        let length = u32::from_le_bytes([header[11], header[12], header[13], header[14]]) as usize;
+        if length > MAX_PAYLOAD_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Capture record length out of range: {}", length)));
+        }
+
+        let mut payload = vec![0u8; length];
+        reader.read_exact(&mut payload)?;
+        Ok(Some(CaptureRecord { timestamp_us, slot, direction, payload }))
+    }
+}
+
+/// Location and rotation of the per slot capture files
+#[derive(Debug,Clone)]
+pub struct CaptureConfig {
+    pub directory : PathBuf,
+    /// A file is rotated once it reaches this size in bytes
+    pub max_file_size : u64,
+    /// Number of files kept per slot, including the active one
+    pub max_files : usize,
+}
+
+impl CaptureConfig {
+
+    pub fn new(directory : PathBuf) -> CaptureConfig {
+        CaptureConfig { directory, max_file_size : DEFAULT_MAX_FILE_SIZE, max_files : DEFAULT_MAX_FILES }
+    }
+
+    /// Reads `SDBP_CAPTURE_DIR`, `SDBP_CAPTURE_MAX_FILE_SIZE` and `SDBP_CAPTURE_MAX_FILES`, capturing is disabled without a directory
+    pub fn from_env() -> Option<CaptureConfig> {
+        let mut config = CaptureConfig::new(PathBuf::from(env::var("SDBP_CAPTURE_DIR").ok()?));
+
+        if let Ok(value) = env::var("SDBP_CAPTURE_MAX_FILE_SIZE") {
+            match value.parse::<u64>() {
+                Ok(size) if size > 0 => config.max_file_size = size,
+                _ => warn!("SDBP_CAPTURE_MAX_FILE_SIZE value invalid, using {}", config.max_file_size),
+            }
+        }
+        if let Ok(value) = env::var("SDBP_CAPTURE_MAX_FILES") {
+            match value.parse::<usize>() {
+                Ok(files) if files > 0 => config.max_files = files,
+                _ => warn!("SDBP_CAPTURE_MAX_FILES value invalid, using {}", config.max_files),
+            }
+        }
+        Some(config)
+    }
+
+    /// Active capture file of a slot, rotated files get the suffix `.1` (newest) to `.<max_files - 1>`
+    pub fn path(&self, slot : u16) -> PathBuf {
+        self.directory.join(format!("slot-{:04x}.sdbpcap", slot))
+    }
+}
+
+/// Writes the traffic of one slot, see `DeviceHandle::set_capture`
+pub struct CaptureWriter {
+    config : CaptureConfig,
+    slot : u16,
+    file : File,
+    size : u64,
+}
+
+impl CaptureWriter {
+
+    /// Opens a new capture, the capture of a previous session is rotated
+    pub fn open(config : CaptureConfig, slot : u16) -> Result<CaptureWriter,Error> {
+        fs::create_dir_all(&config.directory)?;
+        if config.path(slot).exists() {
+            CaptureWriter::rotate_files(&config, slot)?;
+        }
+        let (file, size) = CaptureWriter::create(&config.path(slot))?;
+        debug!("Capturing slot {} to {:?}", slot, config.path(slot));
+        Ok(CaptureWriter { config, slot, file, size })
+    }
+
+    fn create(path : &Path) -> Result<(File,u64),Error> {
+        let mut file = File::create(path)?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        file.write_all(&header)?;
+        Ok((file, header.len() as u64))
+    }
+
+    fn rotated_path(path : &Path, index : usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate_files(config : &CaptureConfig, slot : u16) -> Result<(),Error> {
+        let path = config.path(slot);
+        if config.max_files <= 1 {
+            return fs::remove_file(&path);
+        }
+
+        let oldest = CaptureWriter::rotated_path(&path, config.max_files - 1);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (1..config.max_files - 1).rev() {
+            let source = CaptureWriter::rotated_path(&path, index);
+            if source.exists() {
+                fs::rename(&source, CaptureWriter::rotated_path(&path, index + 1))?;
+            }
+        }
+        fs::rename(&path, CaptureWriter::rotated_path(&path, 1))
+    }
+
+    pub fn record(&mut self, direction : CaptureDirection, payload : &[u8]) -> Result<(),Error> {
+        let raw = CaptureRecord::now(self.slot, direction, payload).to_bytes();
+        self.file.write_all(&raw)?;
+        self.size += raw.len() as u64;
+
+        if self.size >= self.config.max_file_size {
+            CaptureWriter::rotate_files(&self.config, self.slot)?;
+            let (file, size) = CaptureWriter::create(&self.config.path(self.slot))?;
+            self.file = file;
+            self.size = size;
+        }
+        Ok(())
+    }
+}
+
+/// Iterates the records of a capture file
+pub struct CaptureReader<R : Read> {
+    reader : R,
+    failed : bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+
+    pub fn open(path : &Path) -> Result<CaptureReader<BufReader<File>>,Error> {
+        CaptureReader::from_reader(BufReader::new(File::open(path)?))
+    }
+}
+
+impl <R : Read>CaptureReader<R> {
+
+    pub fn from_reader(mut reader : R) -> Result<CaptureReader<R>,Error> {
+        let mut header = [0u8; 10];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a SDBP capture"));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != FORMAT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported capture format version {}", version)));
+        }
+        Ok(CaptureReader { reader, failed : false })
+    }
+}
+
+impl <R : Read>Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord,Error>;
+
+    /// Ends after the last record or the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match CaptureRecord::read_from(&mut self.reader) {
+            Ok(record) => record.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Feeds a recorded session back to response parsers or a simulated slot.
+///
+/// Requests are matched against the recorded `Tx` frames in order, frames which are not requested
+/// (e.g. the `MODE_RUN` keep-alive) are skipped.
+pub struct ReplayTransport {
+    records : Vec<CaptureRecord>,
+    position : usize,
+}
+
+impl ReplayTransport {
+
+    pub fn from_records(records : Vec<CaptureRecord>) -> ReplayTransport {
+        ReplayTransport { records, position : 0 }
+    }
+
+    /// Loads the given capture files, rotated files have to be passed oldest first
+    pub fn open(paths : &[PathBuf]) -> Result<ReplayTransport,Error> {
+        let mut records = Vec::new();
+        for path in paths {
+            for record in CaptureReader::open(path)? {
+                records.push(record?);
+            }
+        }
+        Ok(ReplayTransport::from_records(records))
+    }
+
+    pub fn records(&self) -> &Vec<CaptureRecord> {
+        &self.records
+    }
+
+    /// All recorded notifications in order
+    pub fn notifications(&self) -> Vec<&CaptureRecord> {
+        self.records.iter().filter(|record| record.direction == CaptureDirection::Notification).collect()
+    }
+
+    /// Returns the recorded answer to the next occurrence of `request`
+    pub fn transfer(&mut self, request : &[u8]) -> Result<Vec<u8>,Error> {
+        let start = self.records[self.position..].iter()
+            .position(|record| record.direction == CaptureDirection::Tx && record.payload == request)
+            .map(|offset| self.position + offset)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Request {:02x?} not found in capture", request)))?;
+
+        let slot = self.records[start].slot;
+        let answer = self.records[start + 1..].iter().enumerate()
+            .find(|(_, record)| record.slot == slot && (record.direction == CaptureDirection::Rx || record.direction == CaptureDirection::Error));
+
+        match answer {
+            Some((offset, record)) => {
+                self.position = start + 1 + offset + 1;
+                match record.direction {
+                    CaptureDirection::Rx => Ok(record.payload.clone()),
+                    _ => Err(Error::other(String::from_utf8_lossy(&record.payload).to_string())),
+                }
+            }
+            None => {
+                self.position = self.records.len();
+                Err(Error::new(ErrorKind::UnexpectedEof, "Capture ends before the response"))
+            }
+        }
+    }
+
+    /// Replays `request` and parses the recorded response
+    pub fn parse<T : SdbpResponse>(&mut self, request : &[u8]) -> Result<T,Error> {
+        T::from_raw(self.transfer(request)?)
+    }
+
+    /// Serves the recording as simulated slot, see `Controller::start_virtual_device`
+    pub fn into_frame_handler(mut self) -> FrameHandler {
+        FrameHandler::new().otherwise(move |raw| match self.transfer(raw) {
+            Ok(response) => response,
+            Err(err) => {
+                debug!("Replay: {}", err);
+                FrameHandler::invalid_command(raw)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::{CoreBuilder, request};
+    use crate::sdbp::response::core::control::RunResponse;
+
+    fn test_dir(name : &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sdbpcap-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn capture_rotates_and_reads_back() {
+        let dir = test_dir("rotate");
+        let config = CaptureConfig { directory : dir.clone(), max_file_size : 64, max_files : 3 };
+        let mut writer = CaptureWriter::open(config.clone(), 2).unwrap();
+        for value in 0..10u8 {
+            writer.record(CaptureDirection::Tx, &[0x01, 0x03, value]).unwrap();
+        }
+
+        let path = config.path(2);
+        assert!(CaptureWriter::rotated_path(&path, 2).exists());
+        assert!(!CaptureWriter::rotated_path(&path, 3).exists());
+
+        let records : Vec<CaptureRecord> = CaptureReader::open(&CaptureWriter::rotated_path(&path, 1)).unwrap().map(|record| record.unwrap()).collect();
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|record| record.slot == 2 && record.direction == CaptureDirection::Tx));
+
+        let mut truncated = MAGIC.to_vec();
+        truncated.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        truncated.extend_from_slice(&records[0].to_bytes()[..RECORD_HEADER_LENGTH + 1]);
+        let mut reader = CaptureReader::from_reader(truncated.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replay_answers_recorded_requests() {
+        let run = CoreBuilder::new().control().mode_run().unwrap();
+        let run_response = vec![request::core::protocol::CLASS_ID, request::core::protocol::classes::control::ID, request::core::protocol::classes::control::operation_code::MODE_RUN, 0x00];
+
+        let mut replay = ReplayTransport::from_records(vec![
+            CaptureRecord::now(1, CaptureDirection::Tx, &[0x01, 0x03, 0x7F]),
+            CaptureRecord::now(1, CaptureDirection::Rx, &[0x01, 0x03, 0x7F, 0x00]),
+            CaptureRecord::now(1, CaptureDirection::Tx, &run),
+            CaptureRecord::now(1, CaptureDirection::Notification, &[0x01, 0x02, 0x02]),
+            CaptureRecord::now(1, CaptureDirection::Rx, &run_response),
+            CaptureRecord::now(1, CaptureDirection::Tx, &run),
+            CaptureRecord::now(1, CaptureDirection::Error, b"Device disconnected"),
+        ]);
+
+        assert!(replay.parse::<RunResponse>(&run).is_ok());
+        assert_eq!(replay.transfer(&run).unwrap_err().to_string(), "Device disconnected");
+        assert_eq!(replay.transfer(&run).unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(replay.notifications().len(), 1);
+    }
+}
@@ -55,6 +55,11 @@ impl ComHandler {
         self.send_device_evt(evt);
     }
 
+    /// Returns the sender used by clients to reach the dispatcher
+    pub fn client_source(&self) -> Sender<PMsg> {
+        self.client_src.clone()
+    }
+
     pub fn unregister_client(&self, client_id: u16) {
         let evt = IntUdsEvent { evt_type: UdsEventState::DISCONNECTED, id: client_id, chn: None };
         self.send_client_evt(evt);
@@ -1,26 +1,68 @@
 use std::time::Duration;
 use std::collections::HashMap;
-use crossbeam_channel::{Receiver, Select};
+use std::sync::{Arc, Mutex};
+use crossbeam_channel::{Receiver, Select, Sender};
 
 
 use crate::util::*;
+use crate::datatypes::Descriptor;
 use super::*;
 
 pub struct Controller {
-    handle : ManagedThreadHandle<()>
+    handle : ManagedThreadHandle<()>,
+    power : Sender<PowerRequest>,
 }
 
 impl Controller {
 
-    pub fn start_virtual_device(name: String, id: u16,  com : &mut ComHandler,shared : SharedStats, handle_func : FuncVirtualDeviceHandler) -> VirtualDeviceThread{
-        let id = id | 0x2000;
-        let pair = com.register_new_device(id);
-       VirtualDeviceThread::start(name, id ,pair,handle_func,shared)
+    /// Registers a software device, it is listed and routed like a physical slot until the returned thread is stopped.
+    ///
+    /// The descriptor is usually created with `VirtualDeviceThread::descriptor`.
+    pub fn start_virtual_device(desc : Descriptor, com : &ComHandler, shared : SharedStats, handler : FrameHandler) -> VirtualDeviceThread {
+        let mut desc = desc;
+        desc.set_adr(desc.adr() | 0x2000);
+        VirtualDeviceThread::start(desc, com.clone(), shared, handler)
     }
 
-    fn handle_evt(evt :  DeviceEvent, map : &mut HashMap<u16,DeviceThread>, com : &mut ComHandler, shared : &mut SharedStats, handle_func : FuncDeviceHandler, compatible_fw_major: u16, compatible_fw_minor: u16) {
+    /// Suspends or resumes the slots of `request` one after the other, see `PowerHandle`
+    fn handle_power(request : PowerRequest, ports : &HashMap<u16,SlotPower>, suspended : &Mutex<Vec<u16>>) {
+        let order = {
+            let suspended = suspended.lock().expect("Suspended slots poisoned");
+            power_order(request.command, request.slots, ports.keys().copied().collect(), &suspended)
+        };
+        let mut report = PowerReport { command : request.command, slots : Vec::new() };
+
+        for slot in order {
+            let result = match ports.get(&slot) {
+                None => Err(format!("Slot {} is not connected", slot)),
+                Some(port) => port.power(request.command, request.timeout),
+            };
+            if result.is_ok() {
+                let mut suspended = suspended.lock().expect("Suspended slots poisoned");
+                suspended.retain(|value| *value != slot);
+                if request.command == PowerCommand::Suspend {
+                    suspended.push(slot);
+                }
+            }
+            report.slots.push(SlotPowerResult { slot, error : result.err() });
+        }
 
-        let mut stats = shared.read();
+        info!("{:?} of {} slots, {} failed", report.command, report.slots.len(), report.failed().count());
+        let _ = request.reply.send(report);
+    }
+
+    /// Handles the power requests, a suspend or resume waits for every slot and must not block the controller
+    fn power_worker(ctl_pair : ChannelPair<ManagedThreadState>, rx : Receiver<(PowerRequest, HashMap<u16,SlotPower>)>, suspended : Arc<Mutex<Vec<u16>>>) {
+        let mut stopped = false;
+        while !stopped {
+            if let Ok((request, ports)) = rx.recv_timeout(Duration::from_millis(100)) {
+                Controller::handle_power(request, &ports, &suspended);
+            }
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+        }
+    }
+
+    fn handle_evt(evt :  DeviceEvent, map : &mut HashMap<u16,DeviceThread>, com : &mut ComHandler, shared : &mut SharedStats, factory : &DeviceDriverFactory) {
 
         if evt.evt_type == DeviceEventType::Connected {
             trace!("{:?}", evt);
@@ -43,16 +85,10 @@ impl Controller {
                     }
                 };
 
-                let device = DeviceThread::start(format!("dev-slot-{}", evt.id), pair, desc.clone(), handle_func, compatible_fw_major, compatible_fw_minor);
+                let device = DeviceThread::start(format!("dev-slot-{}", evt.id), pair, desc.clone(), factory(&desc), SlotConfig::from_env(evt.id), shared.clone());
                 map.insert(evt.id, device);
-                if !evt.is_virtual {
-                    stats.get_devices().push(desc);
-                }
-
-                match shared.write(stats,Some(Duration::from_secs(100))) {
-                    Ok(_) => debug!("Write was successful"),
-                    Err(_) => error!("Write to shared stats failed!")
-                };
+                let version = shared.update(|stats| stats.get_devices().push(desc));
+                debug!("Published device list version {}", version);
             }
 
         }
@@ -71,24 +107,12 @@ impl Controller {
                     }
                 };
 
-                let dev = stats.get_devices();
-
-                let mut idx = -1;
-                for i in 0..dev.len() {
-                    if dev[i].adr() == evt.id {
-                        idx = i as isize;
-                        break;
+                let version = shared.update(|stats| {
+                    if let Some(device) = stats.get_devices().iter_mut().find(|device| device.adr() == evt.id) {
+                        *device = desc;
                     }
-                }
-
-                if idx != -1 {
-                    dev[idx as usize] = desc;
-                }
-
-                match shared.write(stats,Some(Duration::from_secs(100))) {
-                    Ok(_) => debug!("Write was successful"),
-                    Err(_) => error!("Write to shared stats failed!")
-                };
+                });
+                debug!("Published device list version {}", version);
             }
 
         } else if evt.evt_type == DeviceEventType::Disconnected {
@@ -96,32 +120,13 @@ impl Controller {
 
             let t = map.get(&evt.id);
 
-            let list = stats.get_devices();
-            let mut found = false;
-            for i in 0..list.len() {
-                match list.get(i) {
-                    None => {
-                        info!("Could not find device in connected list")
-                    }
-                    Some(val) => {
-                        if val.adr() == evt.id {
-                            info!("Removed device from slot {}", evt.id);
-                            list.remove(i);
-                            found=true;
-                            break;
-                        }
-                    }
-                };
-            }
-            if !found {
+            if !shared.snapshot().devices().iter().any(|device| device.adr() == evt.id) {
                 debug!("Device not found for removal {} (not handled by this driver)", evt.id);
                 return;
             }
-            match shared.write(stats,Some(Duration::from_secs(100))) {
-               Ok(_) => (),
-               Err(_) => error!("Write to shared stats failed")
-            };
-            //info!("{}",shared.read());
+            shared.update(|stats| stats.get_devices().retain(|device| device.adr() != evt.id));
+            info!("Removed device from slot {}", evt.id);
+
             match t {
                 None => {
                     warn!("Could not stop thread!");
@@ -135,15 +140,21 @@ impl Controller {
         }
     }
 
-    fn task(ctl_pair : ChannelPair<ManagedThreadState>, mut com : ComHandler, chn_devt : Receiver<DeviceEvent>,stats : SharedStats, func_handle : FuncDeviceHandler, compatible_fw_major: u16, compatible_fw_minor: u16){
+    fn task(ctl_pair : ChannelPair<ManagedThreadState>, mut com : ComHandler, chn_devt : Receiver<DeviceEvent>, chn_power : Receiver<PowerRequest>, stats : SharedStats, factory : DeviceDriverFactory){
 
         let mut shared = stats;
         let mut stopped = false;
         let mut device_map : HashMap<u16,DeviceThread> = HashMap::new();
+        // Suspended slots in the order they were suspended
+        let suspended = Arc::new(Mutex::new(Vec::<u16>::new()));
+        let (power_tx, power_rx) = crossbeam_channel::unbounded();
+        let worker_suspended = suspended.clone();
+        let power_worker = spawn("PowerWorker".to_string(), move |ctl_pair| Controller::power_worker(ctl_pair, power_rx, worker_suspended));
 
         let mut sel = Select::new();
         let op_evt = sel.recv(&chn_devt);
         let op_ctl = sel.recv(ctl_pair.rx());
+        let op_power = sel.recv(&chn_power);
 
         info!("Started Controller");
 
@@ -157,7 +168,19 @@ impl Controller {
                 i if i == op_evt => {
                     let event = op.recv(&chn_devt);
                     match event {
-                        Ok(value) => Controller::handle_evt(value, &mut device_map, &mut com, &mut shared, func_handle, compatible_fw_major, compatible_fw_minor),
+                        Ok(value) => {
+                            Controller::handle_evt(value, &mut device_map, &mut com, &mut shared, &factory);
+                            suspended.lock().expect("Suspended slots poisoned").retain(|slot| device_map.contains_key(slot));
+                        },
+                        Err(err) => error!("Controller error: {:?}", err)
+                    }
+                },
+                i if i == op_power => {
+                    match op.recv(&chn_power) {
+                        Ok(request) => {
+                            let ports = device_map.iter().map(|(slot, device)| (*slot, device.power_port())).collect();
+                            let _ = power_tx.send((request, ports));
+                        },
                         Err(err) => error!("Controller error: {:?}", err)
                     }
                 },
@@ -180,16 +203,27 @@ impl Controller {
 
         }
 
+        let _ = power_worker.stop(Duration::from_millis(1000));
         for (_nr , t) in device_map {
             t.stop(Duration::from_millis(1000));
         }
         info!("Stopped Controller");
     }
 
-    pub fn start(com : ComHandler, chn_devt : Receiver<DeviceEvent>, stats : SharedStats, handle_func :  FuncDeviceHandler, compatible_fw_major: u16, compatible_fw_minor: u16 ) -> Controller {
+    /// Starts the controller, `factory` creates the driver of every connected module (e.g. `SdbpModule::factory`).
+    ///
+    /// The device traffic is recorded per slot if `SDBP_CAPTURE_DIR` is set, see `CaptureConfig::from_env`.
+    /// The link of every slot is negotiated with the caps of `LinkConfig::from_env`.
+    pub fn start(com : ComHandler, chn_devt : Receiver<DeviceEvent>, stats : SharedStats, factory : DeviceDriverFactory) -> Controller {
+
+        let (power, chn_power) = crossbeam_channel::unbounded();
+        let handle = spawn("Controller".to_string(),move |ctl_pair |  Controller::task(ctl_pair,com,chn_devt,chn_power,stats,factory));
+        Controller {handle, power}
+    }
 
-        let handle = spawn("Controller".to_string(),move |ctl_pair |  Controller::task(ctl_pair,com,chn_devt,stats,handle_func, compatible_fw_major, compatible_fw_minor));
-        Controller {handle}
+    /// Returns a handle to suspend and resume the connected modules
+    pub fn power_handle(&self) -> PowerHandle {
+        PowerHandle::new(self.power.clone())
     }
 
     pub fn stop(&self, dur : Duration) {
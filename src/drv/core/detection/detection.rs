@@ -38,6 +38,7 @@ impl DeviceMeta {
 pub struct DeviceFilter<T> {
 
     supported_device : Vec<T>,
+    bootloader : bool,
 }
 
 impl<T: Clone + std::cmp::PartialEq + std::fmt::Debug > DeviceFilter<T> {
@@ -45,7 +46,7 @@ impl<T: Clone + std::cmp::PartialEq + std::fmt::Debug > DeviceFilter<T> {
     pub fn new() -> DeviceFilter<String> {
 
         let foo  = vec!["test".to_string(),"test".to_string()];
-        DeviceFilter {supported_device: foo}
+        DeviceFilter {supported_device: foo, bootloader: false}
     }
 
     pub fn is_match(&self , value: T) -> bool {
@@ -63,6 +64,18 @@ impl<T: Clone + std::cmp::PartialEq + std::fmt::Debug > DeviceFilter<T> {
         self.supported_device.push(value);
     }
 
+    /// Also reports matching modules in bootloader mode, needed to flash them through the driver
+    ///
+    /// Device threads of such modules only forward frames and do not run the driver hooks.
+    #[cfg(feature = "firmware-experimental")]
+    pub fn accept_bootloader(&mut self, enable : bool) {
+        self.bootloader = enable;
+    }
+
+    pub fn accepts_bootloader(&self) -> bool {
+        self.bootloader
+    }
+
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.supported_device.clear();
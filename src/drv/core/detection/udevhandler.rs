@@ -24,7 +24,7 @@ pub fn init_devices(sender : &Sender<DeviceEvent>, filter: &DeviceFilter<String>
 fn is_compatible(device: &Device, filter: &DeviceFilter<String>) -> bool {
     let vendor_product_id = device.attribute_value("vendor_product_id").unwrap_or("".as_ref()).to_string_lossy().to_string();
     let bootloader_state = device.attribute_value("bootloader_state").unwrap_or("".as_ref()).to_string_lossy().to_string();
-    if BootloaderState::try_from(bootloader_state.as_str()).unwrap_or(BootloaderState::BootloaderMode) == BootloaderState::BootloaderMode {
+    if BootloaderState::try_from(bootloader_state.as_str()).unwrap_or(BootloaderState::BootloaderMode) == BootloaderState::BootloaderMode && !filter.accepts_bootloader() {
         return false; // Ignore devices in bootloader mode
     }
     if filter.is_match(vendor_product_id) {
@@ -1,24 +1,504 @@
+use std::{fs, process};
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
 use std::time::Duration;
+
+use rand::Rng;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
 use crate::util::*;
 use crate::datatypes::*;
-use crate::drv::core::PMsg;
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::drv::core::{CaptureConfig, CaptureDirection, CaptureWriter, DeviceDriver, DeviceHandle, LinkConfig, NotificationHandler, PMsg, PowerCommand, SharedStats, STEP_DOWN_ERRORS};
+use crate::sdbp::{FrameBuilder, request};
+use crate::sdbp::response::SdbpResponse;
+#[cfg(feature = "firmware-experimental")]
+use crate::sdbp::response::core::control::BootloaderModeResponse;
+use crate::sdbp::response::core::control::{RunResponse, SetSclkSpeedResponse, SuspendResponse};
+use crate::sdbp::response::core::descriptor::read_full_descriptor;
+use crate::sdbp::response::core::transaction_error::TransactionError;
+use crate::sdbp::response::core::wait::WaitResponse;
+use crate::{err_slot, info_slot, warn_slot};
 
+/// A busy module is asked again after `WAIT_DELAY`, the `WAIT` frame is forwarded after `WAIT_TRIES`
+const WAIT_TRIES: u32 = 50;
+const WAIT_DELAY: Duration = Duration::from_millis(10);
 
-pub type FuncDeviceHandler = fn (Descriptor, ChannelPair<ManagedThreadState>,ChannelPair<PMsg>, compatible_fw_major: u16, compatible_fw_minor: u16);
+const NO_NOTIFICATION_PENDING: [u8; 4] = [request::core::protocol::CLASS_ID, request::core::protocol::classes::notification::ID, request::core::protocol::classes::notification::operation_code::ERROR, 0x03];
+
+/// Settings of a single slot, read from the environment by the `Controller`
+#[derive(Debug,Clone,Default)]
+pub struct SlotConfig {
+    /// The traffic of the slot is recorded if set
+    pub capture : Option<CaptureConfig>,
+    pub link : LinkConfig,
+}
+
+impl SlotConfig {
+
+    /// See `CaptureConfig::from_env` and `LinkConfig::from_env`
+    pub fn from_env(slot : u16) -> SlotConfig {
+        SlotConfig { capture : CaptureConfig::from_env(), link : LinkConfig::from_env(slot) }
+    }
+}
+
+/// Suspend or resume of a single slot, answered with the outcome
+type SlotPowerRequest = (PowerCommand, Sender<Result<(),String>>);
+
+/// Sends suspend and resume commands to a device thread, can be cloned into other threads
+#[derive(Clone)]
+pub(crate) struct SlotPower {
+    tx : Sender<SlotPowerRequest>,
+}
+
+impl SlotPower {
+
+    /// Suspends or resumes the module, fails if the thread does not answer within `timeout`
+    pub(crate) fn power(&self, command : PowerCommand, timeout : Duration) -> Result<(),String> {
+        let (reply, rx) = crossbeam_channel::bounded(1);
+        self.tx.send((command, reply)).map_err(|_| "Device thread is stopped".to_string())?;
+        rx.recv_timeout(timeout).map_err(|_| format!("{:?} timed out", command))?
+    }
+}
 
 pub struct DeviceThread {
    handle : ManagedThreadHandle<()>,
+   power : SlotPower,
 }
 
 impl DeviceThread{
 
-    pub fn start(name:String,dev_chn: ChannelPair<PMsg>,desc : Descriptor, func : FuncDeviceHandler, compatible_fw_major: u16, compatible_fw_minor: u16 ) -> DeviceThread{
+    fn is_connected(device_path: &String) -> bool {
+        let path = PathBuf::from(device_path);
+        let mut cnt = 0;
+        while cnt < 200 {
+            if !path.as_path().exists() {
+                return false;
+            }
+            cnt += 1;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    fn is_not_get_notification(raw: &[u8]) -> bool {
+        if raw.len() >= 3 &&
+            raw[0] == request::core::protocol::CLASS_ID &&
+            raw[1] == request::core::protocol::classes::notification::ID &&
+            raw[2] == request::core::protocol::classes::notification::operation_code::GET_NOTIFICATION {
+            return false;
+        }
+        true
+    }
+
+    fn is_suspend(raw: &[u8]) -> bool {
+        DeviceThread::is_control(raw, request::core::protocol::classes::control::operation_code::MODE_SUSPEND)
+    }
+
+    fn is_control(raw: &[u8], operation_code: u8) -> bool {
+        raw.len() >= 3 &&
+            raw[0] == request::core::protocol::CLASS_ID &&
+            raw[1] == request::core::protocol::classes::control::ID &&
+            raw[2] == operation_code
+    }
+
+    /// Executes a client command, repeats it while the module answers `WAIT` and returns transaction errors as `TransactionError`
+    fn execute(desc: &Descriptor, driver: &mut Box<dyn DeviceDriver>, dev_handle: &mut DeviceHandle, command: &[u8], bootloader: bool) -> Result<Vec<u8>, std::io::Error> {
+        let mut tries = 0;
+        loop {
+            let response = match bootloader {
+                true => dev_handle.transfer(command.to_vec())?,
+                false => driver.on_command(desc, dev_handle, command)?,
+            };
+            if WaitResponse::is_wait(response.as_slice()) && tries < WAIT_TRIES {
+                tries += 1;
+                std::thread::sleep(WAIT_DELAY);
+                continue;
+            }
+            if TransactionError::is_transaction_error(response.as_slice()) {
+                return Err(TransactionError::from_raw(response)?.into());
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Lowers the SCLK speed after repeated transfer errors, returns false if it can not go any lower
+    fn step_down_link(path: &String, dev_handle: &mut DeviceHandle, stats: &SharedStats, slot: u16) -> bool {
+        let link = match dev_handle.link().and_then(|link| link.step_down()) {
+            Some(value) => value,
+            None => return false,
+        };
+        dev_handle.reset_failed_transfers();
+        let request = FrameBuilder::new().core().control().set_sclk_speed(link.sclk_speed).unwrap();
+        match dev_handle.transfer(request).and_then(SetSclkSpeedResponse::from_raw) {
+            Ok(_) => {
+                warn_slot!(path, format!("Lowered communication speed to {} kHz after transfer errors", link.sclk_speed));
+                dev_handle.set_link(Some(link));
+                stats.update(|stats| stats.set_link(slot, Some(link)));
+            }
+            Err(err) => warn_slot!(path, format!("Could not lower communication speed: {}", err)),
+        }
+        true
+    }
+
+    /// Suspends the module, the driver `on_suspend` hook runs afterwards
+    fn suspend(desc: &Descriptor, driver: &mut Box<dyn DeviceDriver>, dev_handle: &mut DeviceHandle) -> Result<(), std::io::Error> {
+        SuspendResponse::from_raw(dev_handle.transfer(FrameBuilder::new().core().control().mode_suspend()?)?)?;
+        if let Err(err) = driver.on_suspend(desc, dev_handle) {
+            warn_slot!(desc.path().to_string_lossy(), format!("Suspend hook failed: {}", err));
+        }
+        Ok(())
+    }
+
+    /// Wakes the module, reads its descriptor again and publishes it in `stats`
+    fn resume(desc: &mut Descriptor, driver: &mut Box<dyn DeviceDriver>, dev_handle: &mut DeviceHandle, stats: &SharedStats) -> Result<(), std::io::Error> {
+        RunResponse::from_raw(dev_handle.transfer(FrameBuilder::new().core().control().mode_run()?)?)?;
+
+        DeviceThread::refresh_descriptor(desc, dev_handle, stats)?;
+        driver.on_resume(desc, dev_handle)
+    }
+
+    /// Reads the descriptor from the module, e.g. after a firmware change, and publishes it
+    fn refresh_descriptor(desc: &mut Descriptor, dev_handle: &mut DeviceHandle, stats: &SharedStats) -> Result<(), std::io::Error> {
+        let current = read_full_descriptor(|frame| dev_handle.transfer(frame))?;
+        desc.update_from(&current);
+        stats.update(|stats| {
+            if let Some(device) = stats.get_devices().iter_mut().find(|device| device.adr() == desc.adr()) {
+                device.update_from(&current);
+            }
+        });
+        Ok(())
+    }
+
+    fn stop_notification_handler(device_path: &String, ctl_chn: &ChannelPair<ManagedThreadState>,  timeout: Duration) -> Result<(), std::io::Error> {
+        if let Err(err) = ctl_chn.tx().send(ManagedThreadState::STOPPED) {
+            trace!("{:?}", err);
+            trace!("Thread already stopped or channel is inactive");
+            return Ok(());
+        }
+
+        if let Err(err) = fs::write(format!("{}/close_notification", device_path), [1,]) {
+            err_slot!(&device_path, format!("Close notification error: {}", err));
+        }
 
-        let handle = spawn(name,move |ctl_chn| func(desc, ctl_chn,dev_chn, compatible_fw_major, compatible_fw_minor));
-        DeviceThread { handle }
+        let input = ctl_chn.rx().recv_timeout(timeout).unwrap_or(ManagedThreadState::UNDEFINED);
+
+        match input {
+            ManagedThreadState::OK => {
+                return Ok(());
+            },
+            ManagedThreadState::UNDEFINED => {
+                // Note: This is ok because some threads may already be dead
+            },
+            _ => (),
+        };
+        Err(Error::new(std::io::ErrorKind::TimedOut, "Cannot stop thread"))
+    }
+
+    /// Opens the device, runs the driver setup hooks and serves client commands until the module is removed
+    fn task(mut desc: Descriptor, ctl_pair: ChannelPair<ManagedThreadState>, dev_pair: ChannelPair<PMsg>, power: Receiver<SlotPowerRequest>, mut driver : Box<dyn DeviceDriver>, config : SlotConfig, stats : SharedStats) {
+        let mut stopped = false;
+        let mut err_cnt: u32 = 0;
+        let thread_name = std::thread::current().name().expect("Could not get thread name").to_string();
+        let path = desc.path().to_str().expect("Could not get path").to_string();
+        debug!("Started {} for {}" , &thread_name, &path);
+
+
+        let tmp = desc.clone();
+        let (notification_chn, notification_sender) = ChannelPair::new_bound();
+        let notification_handler = spawn("NotifHandler".to_string(), |inner_ctl_pair| NotificationHandler::task(tmp, inner_ctl_pair, notification_sender));
+
+
+        let SlotConfig { capture, link } = config;
+        let mut capture = capture.and_then(|config| match CaptureWriter::open(config, desc.adr()) {
+            Ok(writer) => Some(writer),
+            Err(err) => {
+                warn_slot!(&path, format!("Could not open capture: {}", err));
+                None
+            }
+        });
+
+        let mut latest_notification: Option<Vec<u8>> = None;
+        let mut open_file_errors: u32 = 0;
+        // Modules in bootloader mode only get frames forwarded, they do not know the driver commands
+        // Suspended modules get neither keep-alives nor notification reads until they are resumed
+        let mut suspended = false;
+        let mut bootloader = cfg!(feature = "firmware-experimental") && matches!(BootloaderState::try_from(desc.bootloader_state().as_str()), Ok(BootloaderState::BootloaderMode));
+        // Set after leaving the bootloader, the firmware version and bootloader state have changed
+        let mut refresh = false;
+        while !stopped {
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+            info!("Started driver for {}" , &path);
+
+            //Init Sequence
+            let result = DeviceHandle::new(desc.dev_file());
+            let mut dev_handle = match result {
+                None => {
+                    debug!("{:?} - Cannot open device file", desc.dev_file());
+                    std::thread::sleep(Duration::from_millis(500)); // WARNING: This affects the connection time!
+                    open_file_errors += 1;
+                    if open_file_errors == 120  {
+                        error!("Could not open {:?} after {} tries", desc.dev_file(), open_file_errors);
+                        stopped = true;
+                    }
+                    continue;
+                }
+                Some(value) => value,
+            };
+            dev_handle.set_capture(capture.take());
+            if refresh {
+                if let Err(err) = DeviceThread::refresh_descriptor(&mut desc, &mut dev_handle, &stats) {
+                    debug!("{:?} - Cannot read descriptor: {}", desc.dev_file(), err);
+                    capture = dev_handle.take_capture();
+                    std::thread::sleep(Duration::from_millis(500));
+                    open_file_errors += 1;
+                    if open_file_errors == 120  {
+                        error!("Could not read the descriptor of {:?} after {} tries", desc.dev_file(), open_file_errors);
+                        stopped = true;
+                    }
+                    continue;
+                }
+                refresh = false;
+            }
+            let setup = match bootloader {
+                true => Ok(()),
+                false => link.negotiate(&desc)
+                    .map(|negotiated| dev_handle.set_link(Some(negotiated)))
+                    .and_then(|_| driver.on_open(&desc, &mut dev_handle))
+                    .and_then(|_| driver.check_compatibility(&desc, &mut dev_handle))
+                    .and_then(|_| driver.configure(&desc, &mut dev_handle)),
+            };
+
+            if setup.is_ok() {
+                let negotiated = dev_handle.link();
+                stats.update(|stats| stats.set_link(desc.adr(), negotiated));
+            }
+
+            if let Err(err) = setup {
+                err_slot!(&path, err);
+                stopped = true;
+                // Terminate using os signal
+                signal::kill(Pid::from_raw(process::id().try_into().expect("Could not get own process id")), Signal::SIGTERM).expect("Could not send TERM signal");
+            }
+
+            while !stopped {
+                ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+
+                if let Ok((command, reply)) = power.try_recv() {
+                    let result = match (command, bootloader) {
+                        (_, true) => Err(Error::new(ErrorKind::Unsupported, "Module is in bootloader mode")),
+                        (PowerCommand::Suspend, false) => DeviceThread::suspend(&desc, &mut driver, &mut dev_handle),
+                        (PowerCommand::Resume, false) => DeviceThread::resume(&mut desc, &mut driver, &mut dev_handle, &stats),
+                    };
+                    match &result {
+                        Ok(_) => {
+                            info_slot!(&path, format!("{:?} done", command));
+                            suspended = command == PowerCommand::Suspend;
+                            latest_notification = None;
+                            while notification_chn.rx().try_recv().is_ok() {} // Drop notifications from before the mode change
+                        }
+                        Err(err) => {
+                            warn_slot!(&path, format!("{:?} failed: {}", command, err));
+                            if err.kind() == ErrorKind::NotConnected {
+                                stopped = true;
+                            }
+                        }
+                    }
+                    let _ = reply.send(result.map_err(|err| err.to_string()));
+                }
+                // Randomize timeout value to avoid all devices sending synchronous which may cause a blocked bus
+                let random_timeout = rand::thread_rng().gen_range(0..20);
+
+                let com_result = dev_pair.rx().recv_timeout(Duration::from_millis(90 + random_timeout));
+                let mut reset_after_suspend = false;
+                let mut left_bootloader = false;
+                if let Ok(msg) = com_result {
+                    trace!("{:?} - rx - {:?}",&path,msg);
+                    match msg.get_msg() {
+                        None => { warn!("Received message is empty"); }
+                        Some(command) => {
+                            if DeviceThread::is_suspend(command.as_slice()) {
+                                reset_after_suspend = true;
+                            }
+
+                            if DeviceThread::is_not_get_notification(command.as_slice()) {
+                                let wakes = DeviceThread::is_suspend(command.as_slice()) || DeviceThread::is_control(command.as_slice(), request::core::protocol::classes::control::operation_code::MODE_RUN);
+                                let response = match suspended && !wakes {
+                                    true => Err(Error::new(ErrorKind::WouldBlock, "Module is suspended")),
+                                    false => DeviceThread::execute(&desc, &mut driver, &mut dev_handle, command.as_slice(), bootloader),
+                                };
+                                if let Ok(raw) = &response {
+                                    if !bootloader && DeviceThread::is_suspend(command.as_slice()) {
+                                        suspended = SuspendResponse::from_raw(raw.clone()).is_ok();
+                                    } else if !bootloader && DeviceThread::is_control(command.as_slice(), request::core::protocol::classes::control::operation_code::MODE_RUN) {
+                                        suspended = suspended && RunResponse::from_raw(raw.clone()).is_err();
+                                    }
+                                    #[cfg(feature = "firmware-experimental")]
+                                    if !bootloader && DeviceThread::is_control(command.as_slice(), request::core::protocol::classes::control::operation_code::MODE_BOOTLOADER) {
+                                        bootloader = BootloaderModeResponse::from_raw(raw.clone()).is_ok();
+                                    }
+                                    if bootloader && DeviceThread::is_control(command.as_slice(), request::core::protocol::classes::control::operation_code::MODE_RUN) {
+                                        left_bootloader = RunResponse::from_raw(raw.clone()).is_ok();
+                                    }
+                                }
+                                if let Err(err) = &response {
+                                    if let Some(error) = TransactionError::from_io_error(err) {
+                                        warn_slot!(&path, format!("{}", error));
+                                    }
+                                    if err.kind() == ErrorKind::NotConnected {
+                                        info_slot!(&path, "Device disconnected");
+                                        stopped = true;
+                                    }
+                                }
+                                trace!("{:?} - tx - {:?}",&path,msg);
+                                let answer = msg.reply(response);
+                                debug!("Answer: {:?}", answer);
+                                match dev_pair.tx().send(answer) {
+                                    Ok(_) => {}
+                                    Err(_) => {
+                                        // Client is gone
+                                        info_slot!(&path, "Could not send message to client");
+                                    }
+                                };
+                            } else {
+                                let answer = match &latest_notification {
+                                    None => msg.reply(Ok(Vec::from(NO_NOTIFICATION_PENDING))),
+                                    Some(value) => {
+                                        msg.reply(Ok(value.clone()))
+                                    }
+                                };
+                                trace!("{:?} - tx - {:?}", &path, msg);
+                                match dev_pair.tx().send(answer) {
+                                    Ok(_) => {
+                                        latest_notification = None;
+                                    }
+                                    Err(_) => {
+                                        // Client is gone
+                                        info_slot!(&path, "Could not send notification");
+                                    }
+                                };
+                            }
+                        }
+                    };
+                }
+
+                if left_bootloader {
+                    // Reopen the module and run the driver setup again
+                    info_slot!(&path, "Left bootloader mode");
+                    bootloader = false;
+                    refresh = true;
+                    break;
+                }
+
+                if latest_notification.is_none() && !suspended { // Receive the next notification only if the old one is reset
+                    let result = notification_chn.rx().recv_timeout(Duration::from_millis(10));
+                    if let Ok(value) = result {
+                        match value.get_msg() {
+                            None => {
+                                warn_slot!(&path, "Notification was empty");
+                            }
+                            Some(val) => {
+                                debug!("Received Notification {:?}", &val);
+                                dev_handle.capture(CaptureDirection::Notification, &val);
+                                latest_notification = driver.on_notification(&desc, val);
+                            }
+                        };
+                    }
+                }
+                if reset_after_suspend {
+                    let _discard = notification_chn.rx().recv_timeout(Duration::from_millis(1)); // Discard notification in buffer
+                    latest_notification = None;
+                }
+                // A module the client suspended gets no further transfers until it is woken
+                if reset_after_suspend && !suspended {
+                    if let Err(err) = driver.on_suspend(&desc, &mut dev_handle) {
+                        if err.kind() == ErrorKind::NotConnected {
+                            info_slot!(&path, "Device disconnected");
+                            stopped=true;
+                            break;
+                        }
+                        err_cnt += 1;
+                        warn_slot!(&path, format!("Update descriptor failed {}", err_cnt));
+                        // Warn but ignore failure
+                    }
+                }
+
+                if bootloader || suspended {
+                    continue; // MODE_RUN would leave the bootloader or wake the module
+                }
+
+                const TRIES: u8 = 10;
+                let mut send_cnt = 0;
+                while send_cnt < TRIES { // Try to send it 3 times
+                    match dev_handle.transfer(FrameBuilder::new().core().control().mode_run().unwrap()) {
+                        Err(err) => {
+                            if err.kind() == ErrorKind::NotConnected {
+                                info_slot!(&path, "Device disconnected");
+                                stopped=true;
+                                break;
+                            }
+                            err_cnt += 1;
+                            warn_slot!(&path, format!("Send MODE_RUN failed {}", err_cnt));
+                            std::thread::sleep(Duration::from_millis(10));
+                            send_cnt += 1;
+                        }
+                        Ok(_) => break,
+                    };
+                }
+
+                if dev_handle.failed_transfers() >= STEP_DOWN_ERRORS && !DeviceThread::step_down_link(&path, &mut dev_handle, &stats, desc.adr()) {
+                    dev_handle.reset_failed_transfers(); // Already at the lowest speed
+                }
+
+                if send_cnt >= TRIES {
+                    info_slot!(&path, format!("Communication failed {} times in a row, checking connection...", send_cnt));
+                    if !DeviceThread::is_connected(&path) {
+                        stopped = true;
+                        err_slot!(&path, "Module disconnected");
+                    }
+                    info_slot!(&path, "Module is still connected");
+                }
+
+            }
+            capture = dev_handle.take_capture();
+            drop(dev_handle);
+        }
+        match DeviceThread::stop_notification_handler(&path,&notification_handler.chn, Duration::from_millis(200)) {
+            Ok(_) => {}
+            Err(err) => {
+                trace!("Could not stop notification handler: {} ({})", err,  &path)
+            }
+        };
+        stats.update(|stats| stats.set_link(desc.adr(), None));
+        driver.on_remove(&desc);
+        info_slot!(&path, "Stopped driver");
+        debug!("Stopped {}", &thread_name);
+    }
+
+    /// Starts the thread of a slot, the traffic of the slot is recorded if `config.capture` is set
+    ///
+    /// The link is negotiated with `config.link` and published in `stats` once the driver setup succeeded,
+    /// a failed negotiation fails the setup.
+    pub fn start(name:String,dev_chn: ChannelPair<PMsg>,desc : Descriptor, driver : Box<dyn DeviceDriver>, config : SlotConfig, stats : SharedStats) -> DeviceThread{
+
+        let (power, power_rx) = crossbeam_channel::unbounded();
+        let handle = spawn(name,move |ctl_chn| DeviceThread::task(desc, ctl_chn, dev_chn, power_rx, driver, config, stats));
+        DeviceThread { handle, power : SlotPower { tx : power } }
+    }
+
+    /// Suspends or resumes the module, fails if the thread does not answer within `timeout`
+    pub fn power(&self, command : PowerCommand, timeout : Duration) -> Result<(),String> {
+        self.power.power(command, timeout)
+    }
+
+    pub(crate) fn power_port(&self) -> SlotPower {
+        self.power.clone()
     }
 
     pub fn stop(&self,timout: Duration){
         let _  = self.handle.stop(timout);
     }
-}
\ No newline at end of file
+}
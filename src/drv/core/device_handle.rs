@@ -2,11 +2,18 @@ use std::fs::{File, OpenOptions};
 use std::path::{PathBuf};
 use std::io::{ErrorKind, Read, Write};
 
+use crate::drv::core::{CaptureDirection, CaptureWriter, LinkSettings, DEFAULT_READ_SIZE};
+use crate::sdbp::Dissector;
+#[cfg(feature = "firmware-experimental")]
+use crate::sdbp::firmware::BootloaderTransport;
+
 
 pub struct DeviceHandle {
     dev_file: File,
     dev_file_path: PathBuf,
-
+    capture: Option<CaptureWriter>,
+    link: Option<LinkSettings>,
+    failed_transfers: u32,
 }
 
 impl DeviceHandle {
@@ -17,7 +24,7 @@ impl DeviceHandle {
         let file = OpenOptions::new().write(true).read(true).open(slot_path);
 
         match file {
-            Ok(value) => return Some(DeviceHandle { dev_file: value, dev_file_path: slot_path.clone() }),
+            Ok(value) => return Some(DeviceHandle { dev_file: value, dev_file_path: slot_path.clone(), capture: None, link: None, failed_transfers: 0 }),
             Err(error) => {
                 trace!("{:?}",error);
             }
@@ -25,7 +32,67 @@ impl DeviceHandle {
         return None;
     }
 
+    /// Records all frames read and written through this handle
+    pub fn set_capture(&mut self, capture: Option<CaptureWriter>) {
+        self.capture = capture;
+    }
+
+    pub fn take_capture(&mut self) -> Option<CaptureWriter> {
+        self.capture.take()
+    }
+
+    /// Negotiated link of the module, responses are read with its frame size
+    pub fn link(&self) -> Option<LinkSettings> {
+        self.link
+    }
+
+    pub fn set_link(&mut self, link: Option<LinkSettings>) {
+        self.link = link;
+    }
+
+    /// Transfers which failed in a row, reset by the next successful transfer
+    pub fn failed_transfers(&self) -> u32 {
+        self.failed_transfers
+    }
+
+    pub fn reset_failed_transfers(&mut self) {
+        self.failed_transfers = 0;
+    }
+
+    /// Adds a frame to the capture, a failing capture is disabled
+    pub fn capture(&mut self, direction: CaptureDirection, payload: &[u8]) {
+        if let Some(capture) = &mut self.capture {
+            if let Err(err) = capture.record(direction, payload) {
+                warn!("{:?} - Capture failed, disabling it: {}", self.dev_file_path, err);
+                self.capture = None;
+            }
+        }
+    }
+
+    fn capture_result(&mut self, direction: CaptureDirection, buf: &[u8], result: &Result<usize, std::io::Error>) {
+        match result {
+            Ok(len) => self.capture(direction, &buf[..(*len).min(buf.len())]),
+            Err(err) => self.capture(CaptureDirection::Error, err.to_string().as_bytes()),
+        }
+    }
+
     pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<usize, std::io::Error> {
+        let result = self.read_file(buf);
+        if self.capture.is_some() {
+            self.capture_result(CaptureDirection::Rx, buf, &result);
+        }
+        result
+    }
+
+    pub fn write(&mut self, buf: Vec<u8>) -> Result<usize, std::io::Error> {
+        let result = self.write_file(&buf);
+        if self.capture.is_some() {
+            self.capture_result(CaptureDirection::Tx, &buf, &result);
+        }
+        result
+    }
+
+    fn read_file(&mut self, buf: &mut Vec<u8>) -> Result<usize, std::io::Error> {
         if self.dev_file_path.as_path().exists() {
             let result = self.dev_file.read(&mut buf.as_mut_slice());
             result
@@ -34,9 +101,9 @@ impl DeviceHandle {
         }
     }
 
-    pub fn write(&mut self, buf: Vec<u8>) -> Result<usize, std::io::Error> {
+    fn write_file(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
         if self.dev_file_path.as_path().exists() {
-            let result = self.dev_file.write(buf.as_slice());
+            let result = self.dev_file.write(buf);
             match result {
                 Err(err) => {
                     const ENODEV: i32 = 19;
@@ -51,4 +118,37 @@ impl DeviceHandle {
             return Err(std::io::Error::new(ErrorKind::NotConnected, "Device disconnected"));
         }
     }
-}
\ No newline at end of file
+
+    /// Writes a request and reads the response of the module
+    pub fn transfer(&mut self, buf: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        if log_enabled!(log::Level::Trace) {
+            trace!("{:?} - {}", self.dev_file_path, Dissector::default().request(&buf));
+        }
+        let mut response = vec![0; self.link.map(|link| link.frame_size as usize).unwrap_or(DEFAULT_READ_SIZE)];
+        let result = self.write(buf).and_then(|_| self.read(&mut response));
+        let len = match result {
+            Ok(len) => {
+                self.failed_transfers = 0;
+                len
+            }
+            Err(err) => {
+                self.failed_transfers += 1;
+                return Err(err);
+            }
+        };
+        response.truncate(len);
+        if log_enabled!(log::Level::Trace) {
+            trace!("{:?} - {}", self.dev_file_path, Dissector::default().response(&response));
+        }
+        Ok(response)
+    }
+}
+
+/// Flashes the module of the handle directly, e.g. from a driver hook
+#[cfg(feature = "firmware-experimental")]
+impl BootloaderTransport for DeviceHandle {
+
+    fn transfer(&mut self, request: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        DeviceHandle::transfer(self, request)
+    }
+}
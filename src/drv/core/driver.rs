@@ -0,0 +1,96 @@
+use std::io::{Error, ErrorKind};
+
+use crate::datatypes::Descriptor;
+use crate::drv::core::{DeviceHandle, LinkConfig};
+use crate::sdbp::{CoreBuilder, FrameBuilder};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::core::control::{SetFrameSizeResponse, SetSclkSpeedResponse};
+use crate::warn_slot;
+
+/// Module driver hooks called by the device thread of a slot.
+///
+/// Every hook has a default which reproduces the generic SDBP driver behavior,
+/// module drivers only override the steps they need and may carry their own state.
+pub trait DeviceDriver : Send {
+
+    /// Called after the device file was opened, before the compatibility check
+    fn on_open(&mut self, _desc : &Descriptor, _handle : &mut DeviceHandle) -> Result<(),Error> {
+        Ok(())
+    }
+
+    /// Checks if the module firmware is supported, an error stops the service
+    fn check_compatibility(&mut self, _desc : &Descriptor, _handle : &mut DeviceHandle) -> Result<(),Error> {
+        Ok(())
+    }
+
+    /// Configures the link, by default the frame size and SCLK speed of `DeviceHandle::link` are set.
+    ///
+    /// Modules which reject the frame size keep their maximum frame size, see `LinkConfig::negotiate`.
+    fn configure(&mut self, desc : &Descriptor, handle : &mut DeviceHandle) -> Result<(),Error> {
+
+        let path = desc.path().to_string_lossy().to_string();
+        let mut link = match handle.link() {
+            Some(link) => link,
+            None => LinkConfig::new().negotiate(desc)?,
+        };
+
+        info!("Setting frame size to: {} bytes", link.frame_size);
+        let accepted = handle.transfer(CoreBuilder::new().control().set_frame_size(link.frame_size)?)
+            .and_then(SetFrameSizeResponse::from_raw);
+        if let Err(err) = accepted {
+            warn_slot!(&path, format!("Frame size change failed ({}), using {} bytes", err, desc.max_frame_size()));
+            link.frame_size = desc.max_frame_size();
+        }
+        handle.set_link(Some(link));
+
+        info!("Setting communication speed to: {} kHz", link.sclk_speed);
+        match handle.transfer(CoreBuilder::new().control().set_sclk_speed(link.sclk_speed)?) {
+            Ok(response) => {
+                SetSclkSpeedResponse::from_raw(response).map_err(|_| Error::new(ErrorKind::InvalidData, "Communication speed change failed"))?;
+                Ok(())
+            }
+            Err(_) => Err(Error::other("Failed setting communication speed")),
+        }
+    }
+
+    /// Forwards a client command to the module, by default it is sent up to three times
+    fn on_command(&mut self, desc : &Descriptor, handle : &mut DeviceHandle, command : &[u8]) -> Result<Vec<u8>,Error> {
+
+        let path = desc.path().to_string_lossy().to_string();
+        let mut response = Err(Error::from(ErrorKind::NotConnected));
+        for i in 0..3 {
+            response = handle.transfer(command.to_vec());
+            match &response {
+                Ok(_) => break,
+                Err(err) if err.kind() == ErrorKind::NotConnected => break,
+                Err(_) => warn_slot!(&path, format!("Could not send message to device (attempt {}), retrying", i)),
+            }
+            if i == 2 {
+                warn_slot!(&path, "Return error");
+            }
+        }
+        response
+    }
+
+    /// Called for every notification read from the module, `None` drops it
+    fn on_notification(&mut self, _desc : &Descriptor, notification : Vec<u8>) -> Option<Vec<u8>> {
+        Some(notification)
+    }
+
+    /// Called after a suspend command was forwarded, by default the descriptor is updated
+    fn on_suspend(&mut self, _desc : &Descriptor, handle : &mut DeviceHandle) -> Result<(),Error> {
+        handle.transfer(FrameBuilder::new().core().control().update_descriptor().unwrap()).map(|_| ())
+    }
+
+    /// Called after the module was resumed and its descriptor was read again, see `PowerHandle::resume`
+    fn on_resume(&mut self, _desc : &Descriptor, _handle : &mut DeviceHandle) -> Result<(),Error> {
+        Ok(())
+    }
+
+    /// Called once the device thread of the slot stops
+    fn on_remove(&mut self, _desc : &Descriptor) {
+    }
+}
+
+/// Creates the driver instance for a newly connected module
+pub type DeviceDriverFactory = Box<dyn Fn(&Descriptor) -> Box<dyn DeviceDriver> + Send>;
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+use crate::datatypes::Descriptor;
+
+/// Frame size range accepted by `SET_FRAME_SIZE`
+pub const MIN_FRAME_SIZE : u16 = 64;
+pub const MAX_FRAME_SIZE : u16 = 65472;
+/// Lowest SCLK speed in kHz, the clock is not stepped down any further
+pub const MIN_SCLK_SPEED : u32 = 100;
+/// Read size of a handle before the link was negotiated
+pub const DEFAULT_READ_SIZE : usize = 4096;
+/// Failed transfers in a row after which the SCLK speed is halved
+pub const STEP_DOWN_ERRORS : u32 = 5;
+
+/// Upper limits for the link of a module, unset values do not limit
+#[derive(Debug,Clone,Copy,Default,PartialEq)]
+pub struct LinkLimits {
+    /// kHz
+    pub sclk_speed : Option<u32>,
+    pub frame_size : Option<u16>,
+}
+
+impl LinkLimits {
+
+    fn from_env(sclk_var : &str, frame_var : &str) -> LinkLimits {
+        LinkLimits { sclk_speed : LinkLimits::read_env(sclk_var), frame_size : LinkLimits::read_env(frame_var) }
+    }
+
+    fn read_env<T : FromStr>(name : &str) -> Option<T> {
+        let value = env::var(name).ok()?;
+        match T::from_str(value.as_str()) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("{} value {:?} invalid, ignoring it", name, value);
+                None
+            }
+        }
+    }
+}
+
+/// Host side caps and per slot settings used to negotiate the link, see `negotiate`
+#[derive(Debug,Clone,Default)]
+pub struct LinkConfig {
+    host : LinkLimits,
+    slots : HashMap<u16,LinkLimits>,
+}
+
+impl LinkConfig {
+
+    pub fn new() -> LinkConfig {
+        LinkConfig::default()
+    }
+
+    /// Caps applied to every slot
+    pub fn host(mut self, limits : LinkLimits) -> LinkConfig {
+        self.host = limits;
+        self
+    }
+
+    /// Caps applied to a single slot in addition to the host caps
+    pub fn slot(mut self, slot : u16, limits : LinkLimits) -> LinkConfig {
+        self.slots.insert(slot, limits);
+        self
+    }
+
+    /// Reads the host caps `MAX_SCLK_SPEED_KHZ` and `MAX_FRAME_SIZE` and the caps of `slot` from
+    /// `SDBP_SLOT<slot>_MAX_SCLK_SPEED_KHZ` and `SDBP_SLOT<slot>_MAX_FRAME_SIZE`
+    pub fn from_env(slot : u16) -> LinkConfig {
+        let slot_limits = LinkLimits::from_env(&format!("SDBP_SLOT{}_MAX_SCLK_SPEED_KHZ", slot), &format!("SDBP_SLOT{}_MAX_FRAME_SIZE", slot));
+        LinkConfig::new()
+            .host(LinkLimits::from_env("MAX_SCLK_SPEED_KHZ", "MAX_FRAME_SIZE"))
+            .slot(slot, slot_limits)
+    }
+
+    /// Picks the lowest of the module maximum, the host cap and the slot cap.
+    ///
+    /// Fails if the module maximum is below `MIN_SCLK_SPEED` or `MIN_FRAME_SIZE`, caps below
+    /// the minimum are raised to it.
+    pub fn negotiate(&self, desc : &Descriptor) -> Result<LinkSettings,Error> {
+        if desc.max_sclk_speed() < MIN_SCLK_SPEED {
+            return Err(Error::new(ErrorKind::Unsupported, format!("Module SCLK speed of {} kHz is below {} kHz", desc.max_sclk_speed(), MIN_SCLK_SPEED)));
+        }
+        if desc.max_frame_size() < MIN_FRAME_SIZE {
+            return Err(Error::new(ErrorKind::Unsupported, format!("Module frame size of {} bytes is below {} bytes", desc.max_frame_size(), MIN_FRAME_SIZE)));
+        }
+        let slot = self.slots.get(&desc.adr()).copied().unwrap_or_default();
+
+        let sclk_speed = [self.host.sclk_speed, slot.sclk_speed].iter().flatten()
+            .fold(desc.max_sclk_speed(), |speed, cap| speed.min(*cap));
+        let frame_size = [self.host.frame_size, slot.frame_size].iter().flatten()
+            .fold(desc.max_frame_size(), |size, cap| size.min(*cap));
+        if sclk_speed < MIN_SCLK_SPEED || frame_size < MIN_FRAME_SIZE {
+            warn!("Link caps of slot {} below the minimum of {} kHz / {} bytes, using the minimum", desc.adr(), MIN_SCLK_SPEED, MIN_FRAME_SIZE);
+        }
+
+        Ok(LinkSettings {
+            frame_size : frame_size.clamp(MIN_FRAME_SIZE, MAX_FRAME_SIZE),
+            sclk_speed : sclk_speed.max(MIN_SCLK_SPEED),
+            step_downs : 0,
+        })
+    }
+}
+
+/// Frame size and SCLK speed in use on the link of a module
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LinkSettings {
+    pub frame_size : u16,
+    /// kHz
+    pub sclk_speed : u32,
+    /// Number of times the clock was lowered after transfer errors
+    pub step_downs : u32,
+}
+
+impl LinkSettings {
+
+    /// Halves the SCLK speed, returns `None` once `MIN_SCLK_SPEED` is reached
+    pub fn step_down(&self) -> Option<LinkSettings> {
+        if self.sclk_speed <= MIN_SCLK_SPEED {
+            return None;
+        }
+        Some(LinkSettings { sclk_speed : (self.sclk_speed / 2).max(MIN_SCLK_SPEED), step_downs : self.step_downs + 1, ..*self })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn lowest_limit_wins_and_clock_steps_down() {
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_adr(3);
+        desc.set_max_sclk_speed(20_000);
+        desc.set_max_frame_size(1024);
+
+        let config = LinkConfig::new()
+            .host(LinkLimits { sclk_speed : Some(10_000), frame_size : None })
+            .slot(3, LinkLimits { sclk_speed : None, frame_size : Some(256) });
+        let link = config.negotiate(&desc).unwrap();
+        assert_eq!(link, LinkSettings { frame_size : 256, sclk_speed : 10_000, step_downs : 0 });
+
+        desc.set_adr(4);
+        assert_eq!(config.negotiate(&desc).unwrap().frame_size, 1024);
+        let config = config.slot(4, LinkLimits { sclk_speed : Some(10), frame_size : Some(1) });
+        assert_eq!(config.negotiate(&desc).unwrap(), LinkSettings { frame_size : MIN_FRAME_SIZE, sclk_speed : MIN_SCLK_SPEED, step_downs : 0 });
+
+        let slower = link.step_down().unwrap();
+        assert_eq!((slower.sclk_speed, slower.step_downs, slower.frame_size), (5_000, 1, 256));
+        assert_eq!(LinkSettings { sclk_speed : 150, ..link }.step_down().unwrap().sclk_speed, MIN_SCLK_SPEED);
+        assert!(LinkSettings { sclk_speed : MIN_SCLK_SPEED, ..link }.step_down().is_none());
+    }
+
+    #[test]
+    fn modules_below_the_minimum_are_not_negotiated() {
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_max_sclk_speed(MIN_SCLK_SPEED - 1);
+        desc.set_max_frame_size(1024);
+        assert_eq!(LinkConfig::new().negotiate(&desc).unwrap_err().kind(), ErrorKind::Unsupported);
+
+        desc.set_max_sclk_speed(MIN_SCLK_SPEED);
+        desc.set_max_frame_size(MIN_FRAME_SIZE - 1);
+        assert!(LinkConfig::new().negotiate(&desc).is_err());
+    }
+}
@@ -1,10 +1,14 @@
 mod mapper;
+mod access;
+mod capture;
 mod comhandler;
 mod controller;
 mod detection;
 mod device;
+mod driver;
 mod vdevice;
 mod dispatcher;
+mod notification_handler;
 mod pmessage;
 mod uds_sessionhandler;
 mod uds_server;
@@ -12,19 +16,31 @@ mod events;
 mod sharedstats;
 mod device_handle;
 mod drvmeta;
+mod link;
 mod sdbpk;
+mod slotlock;
+mod sleephook;
+mod suspend;
 
+pub use access::*;
+pub use capture::*;
 pub use comhandler::*;
 pub use controller::*;
 pub use detection::*;
 pub use device::*;
+pub use driver::*;
 pub use device_handle::*;
 pub use dispatcher::*;
 pub use drvmeta::*;
 pub use events::*;
+pub use link::*;
+pub use notification_handler::*;
 pub use pmessage::*;
 pub use sharedstats::*;
 pub use uds_server::*;
 pub use uds_sessionhandler::*;
 pub use vdevice::*;
 pub use sdbpk::*;
+pub use slotlock::*;
+pub use sleephook::*;
+pub use suspend::*;
@@ -6,6 +6,8 @@ pub struct PMsg {
 
     src: u16,
     dst: u16,
+    /// Set by the client of a request and copied to the answer, see `reply`
+    seq: u32,
     message: Result<Vec<u8>, std::io::Error>,
 }
 
@@ -18,11 +20,9 @@ impl PMsg {
         return false;
     }
 
+    /// Returns true for physical slots and virtual devices
     pub fn is_device(id: u16) -> bool {
-        if (id & VIRTUAL_DEVICE_MASK) != 0 && (id & UDS_CLIENT_MASK) != 0  {
-            return true;
-        }
-        return false;
+        (id & UDS_CLIENT_MASK) == 0
     }
 
     pub fn is_virtual_device(id: u16) -> bool {
@@ -35,7 +35,17 @@ impl PMsg {
 
 
     pub fn create(src: u16, dst: u16, msg: Result<Vec<u8>,std::io::Error> ) -> PMsg {
-        PMsg{src,dst, message: msg}
+        PMsg{src,dst, seq: 0, message: msg}
+    }
+
+    pub fn with_seq(mut self, seq: u32) -> PMsg {
+        self.seq = seq;
+        self
+    }
+
+    /// Answer to this message, sent back to the source with the same sequence number
+    pub fn reply(&self, msg: Result<Vec<u8>,std::io::Error>) -> PMsg {
+        PMsg{src: self.dst, dst: self.src, seq: self.seq, message: msg}
     }
 
 
@@ -47,17 +57,24 @@ impl PMsg {
         }
     }
 
+    pub fn get_error(&self) -> Option<&std::io::Error> {
+        self.message.as_ref().err()
+    }
+
     pub fn get_src(&self) -> u16{
         self.src
     }
     pub fn get_dst(&self) -> u16 {
         self.dst
     }
+    pub fn get_seq(&self) -> u32 {
+        self.seq
+    }
 }
 
 impl std::fmt::Display for PMsg {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "(vsrc: {}, dst: {}, msg: {:?})", self.src, self.dst, self.message)
+        write!(f, "(vsrc: {}, dst: {}, seq: {}, msg: {:?})", self.src, self.dst, self.seq, self.message)
     }
 }
 
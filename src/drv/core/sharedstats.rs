@@ -1,6 +1,7 @@
+use std::collections::HashMap;
 use std::fmt;
-use std::time::{SystemTime};
 use crate::datatypes::*;
+use crate::drv::core::LinkSettings;
 use crate::util::SharedObject;
 
 
@@ -10,19 +11,38 @@ pub struct Stats{
     version : Version,
     sdbpk_version : Version,
     devices : Vec<Descriptor>,
-    timestamp : u128,
-
+    links : HashMap<u16,LinkSettings>,
 }
 
 impl Stats {
     pub fn new(name : String,version : Version, sdbpk_version : Version) -> Stats {
-        Stats{name, devices : Vec::new(), timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos() ,version, sdbpk_version}
+        Stats{name, devices : Vec::new(), links : HashMap::new(), version, sdbpk_version}
     }
 
     pub fn get_devices(&mut self) -> &mut Vec<Descriptor> {
         &mut self.devices
     }
 
+    pub fn devices(&self) -> &Vec<Descriptor> {
+        &self.devices
+    }
+
+    /// Negotiated link of a slot, only set while its device thread runs
+    pub fn link(&self, slot : u16) -> Option<&LinkSettings> {
+        self.links.get(&slot)
+    }
+
+    pub fn links(&self) -> &HashMap<u16,LinkSettings> {
+        &self.links
+    }
+
+    pub fn set_link(&mut self, slot : u16, link : Option<LinkSettings>) {
+        match link {
+            Some(value) => self.links.insert(slot, value),
+            None => self.links.remove(&slot),
+        };
+    }
+
     pub fn get_name(&self) -> &String {
         &self.name
     }
@@ -35,15 +55,6 @@ impl Stats {
         &self.sdbpk_version
     }
 
-    pub fn get_timestamp(&self) -> u128 {
-        self.timestamp
-    }
-
-    pub fn update(&mut self) {
-        self.timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("Could not get system time").as_nanos();
-    }
-
-
 }
 
 impl fmt::Display for Stats {
@@ -51,7 +62,6 @@ impl fmt::Display for Stats {
     fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
 
         fmt.write_str(fmt::format(format_args!("Name: {}\n",self.name)).as_str()).unwrap();
-        fmt.write_str(fmt::format(format_args!("timestamp: {}\n",self.timestamp)).as_str()).unwrap();
 
         for device in &self.devices {
             fmt.write_str(fmt::format(format_args!("{}\n",device)).as_str()).unwrap();
@@ -61,43 +71,6 @@ impl fmt::Display for Stats {
     }
 }
 
+/// Device list and driver information, published as versioned snapshots
 pub type SharedStats = SharedObject<Stats>;
 
-/*
-
-#[derive(Debug,Clone)]
-pub struct SharedStats {
-    stats : Arc<RwLock<Stats>>,
-    ucnt: u32,
-}
-
-impl SharedStats {
-
-    pub fn new(stats : Stats) -> SharedStats{
-        SharedStats {stats : Arc::new(RwLock::new(stats)),ucnt : 0}
-    }
-
-    pub fn is_changed(&self) -> bool{
-
-        if self.ucnt != self.stats.read().unwrap().ucnt {
-            return true;
-        }
-        return false;
-    }
-
-    pub fn read_stats(&mut self) -> Stats {
-        let guard = self.stats.read().unwrap();
-        let result = guard.clone();
-        self.ucnt = result.ucnt;
-        result
-    }
-
-    pub fn write_stats(&self, stats: Stats) {
-        let mut _stats = stats;
-        _stats.ucnt += 1;
-        let mut stats_guard = self.stats.write().unwrap();
-        *stats_guard = _stats;
-
-    }
-}
-*/
@@ -0,0 +1,177 @@
+use std::io::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::util::*;
+use crate::warn_slot;
+use super::*;
+
+/// Signal of the logind manager interface, `true` before the system sleeps and `false` after it woke up
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SleepSignal {
+    PrepareForSleep(bool),
+}
+
+/// Connection to logind as used by `SleepHook`
+///
+/// `inhibit` takes a delay inhibitor lock for `sleep`, logind waits with the suspend until the lock
+/// is released or its `InhibitDelayMaxSec` passed.
+pub trait SleepBus : Send {
+    fn inhibit(&mut self) -> Result<(),Error>;
+    fn release(&mut self);
+    fn next_signal(&mut self, timeout : Duration) -> Option<SleepSignal>;
+}
+
+/// In-process stand-in for the logind D-Bus interface, signals are emitted with the `SleepEmitter`
+pub struct LocalSleepBus {
+    rx : Receiver<SleepSignal>,
+    inhibitors : Arc<AtomicUsize>,
+    inhibited : bool,
+}
+
+/// Emits the signals of a `LocalSleepBus` like logind would
+#[derive(Clone)]
+pub struct SleepEmitter {
+    tx : Sender<SleepSignal>,
+    inhibitors : Arc<AtomicUsize>,
+}
+
+impl LocalSleepBus {
+
+    pub fn new() -> (LocalSleepBus, SleepEmitter) {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let inhibitors = Arc::new(AtomicUsize::new(0));
+        (LocalSleepBus { rx, inhibitors : inhibitors.clone(), inhibited : false }, SleepEmitter { tx, inhibitors })
+    }
+}
+
+impl SleepBus for LocalSleepBus {
+
+    fn inhibit(&mut self) -> Result<(),Error> {
+        if !self.inhibited {
+            self.inhibited = true;
+            self.inhibitors.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        if self.inhibited {
+            self.inhibited = false;
+            self.inhibitors.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn next_signal(&mut self, timeout : Duration) -> Option<SleepSignal> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
+
+impl SleepEmitter {
+
+    pub fn prepare_for_sleep(&self, start : bool) {
+        let _ = self.tx.send(SleepSignal::PrepareForSleep(start));
+    }
+
+    /// Number of delay inhibitor locks currently held
+    pub fn inhibitors(&self) -> usize {
+        self.inhibitors.load(Ordering::SeqCst)
+    }
+}
+
+/// Suspends all modules before the system sleeps and resumes them after it woke up
+///
+/// The inhibitor lock is held while the system is awake, so logind waits for the modules to be suspended.
+pub struct SleepHook {
+    handle : ManagedThreadHandle<()>,
+}
+
+impl SleepHook {
+
+    fn task(ctl_pair : ChannelPair<ManagedThreadState>, mut bus : Box<dyn SleepBus>, power : PowerHandle, timeout : Duration) {
+        let mut stopped = false;
+        if let Err(err) = bus.inhibit() {
+            warn!("Could not take sleep inhibitor lock: {}", err);
+        }
+
+        while !stopped {
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+            if stopped {
+                break;
+            }
+
+            match bus.next_signal(Duration::from_millis(100)) {
+                Some(SleepSignal::PrepareForSleep(true)) => {
+                    info!("System is going to sleep, suspending modules");
+                    SleepHook::log_report(power.suspend(None, timeout));
+                    bus.release();
+                }
+                Some(SleepSignal::PrepareForSleep(false)) => {
+                    info!("System woke up, resuming modules");
+                    if let Err(err) = bus.inhibit() {
+                        warn!("Could not take sleep inhibitor lock: {}", err);
+                    }
+                    SleepHook::log_report(power.resume(None, timeout));
+                }
+                None => (),
+            }
+        }
+        bus.release();
+    }
+
+    fn log_report(report : Result<PowerReport,Error>) {
+        match report {
+            Ok(report) => {
+                for slot in report.failed() {
+                    warn_slot!(slot.slot, format!("{:?} failed: {}", report.command, slot.error.as_deref().unwrap_or_default()));
+                }
+            }
+            Err(err) => warn!("Could not reach the controller: {}", err),
+        }
+    }
+
+    /// Starts the hook, `timeout` is passed to every suspend and resume, see `PowerHandle`
+    pub fn start(bus : Box<dyn SleepBus>, power : PowerHandle, timeout : Duration) -> SleepHook {
+        let handle = spawn("SleepHook".to_string(), move |ctl_pair| SleepHook::task(ctl_pair, bus, power, timeout));
+        SleepHook { handle }
+    }
+
+    pub fn stop(&self, timeout : Duration) {
+        let _ = self.handle.stop(timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_signals_suspend_and_resume_the_modules() {
+        let (tx, rx) = crossbeam_channel::unbounded::<PowerRequest>();
+        let (bus, emitter) = LocalSleepBus::new();
+        let hook = SleepHook::start(Box::new(bus), PowerHandle::new(tx), Duration::from_millis(100));
+
+        let answer = |expected : PowerCommand| {
+            let request = rx.recv_timeout(Duration::from_secs(1)).expect("No power request");
+            assert_eq!(request.command, expected);
+            request.reply.send(PowerReport { command : request.command, slots : vec![] }).unwrap();
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(emitter.inhibitors(), 1);
+        emitter.prepare_for_sleep(true);
+        answer(PowerCommand::Suspend);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(emitter.inhibitors(), 0);
+
+        emitter.prepare_for_sleep(false);
+        answer(PowerCommand::Resume);
+        assert_eq!(emitter.inhibitors(), 1);
+
+        hook.stop(Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(emitter.inhibitors(), 0);
+    }
+}
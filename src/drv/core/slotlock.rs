@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::drv::api::Error;
+use crate::sdbp::request::core::protocol;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+#[derive(Debug,Clone)]
+struct SlotLease {
+    mode : LockMode,
+    holders : HashMap<u16,Instant>,
+}
+
+impl SlotLease {
+
+    fn prune(&mut self, now : Instant) {
+        self.holders.retain(|_, expires| *expires > now);
+    }
+}
+
+/// Lease table for slot ownership shared by all client sessions
+#[derive(Debug,Clone,Default)]
+pub struct SlotLocks {
+    leases : Arc<Mutex<HashMap<u16,SlotLease>>>,
+}
+
+impl SlotLocks {
+
+    pub fn new() -> SlotLocks {
+        SlotLocks { leases : Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Acquires or renews a lease for `client` on `slot`.
+    ///
+    /// A sole holder may switch between shared and exclusive mode, further clients may only join a shared lease.
+    pub fn acquire(&self, client : u16, slot : u16, mode : LockMode, timeout : Duration) -> Result<(),Error> {
+
+        if timeout.is_zero() {
+            return Err(Error::InvalidParameter);
+        }
+
+        let now = Instant::now();
+        let mut leases = self.leases.lock().expect("Could not get slot lock table");
+        let lease = leases.entry(slot).or_insert(SlotLease { mode : mode.clone(), holders : HashMap::new() });
+        lease.prune(now);
+
+        let is_sole_holder = lease.holders.is_empty() || (lease.holders.len() == 1 && lease.holders.contains_key(&client));
+
+        if !is_sole_holder && (lease.mode == LockMode::Exclusive || mode == LockMode::Exclusive) {
+            return Err(Error::SlotLocked);
+        }
+
+        lease.mode = mode;
+        lease.holders.insert(client, now + timeout);
+        Ok(())
+    }
+
+    pub fn release(&self, client : u16, slot : u16) {
+        let mut leases = self.leases.lock().expect("Could not get slot lock table");
+        if let Some(lease) = leases.get_mut(&slot) {
+            lease.holders.remove(&client);
+            if lease.holders.is_empty() {
+                leases.remove(&slot);
+            }
+        }
+    }
+
+    /// Releases every lease held by `client`, called when its session is closed
+    pub fn release_all(&self, client : u16) {
+        let mut leases = self.leases.lock().expect("Could not get slot lock table");
+        for lease in leases.values_mut() {
+            lease.holders.remove(&client);
+        }
+        leases.retain(|_, lease| !lease.holders.is_empty());
+    }
+
+    /// Returns false if another client holds an active lease on `slot` which `client` is not part of
+    pub fn is_permitted(&self, client : u16, slot : u16) -> bool {
+        let mut leases = self.leases.lock().expect("Could not get slot lock table");
+        match leases.get_mut(&slot) {
+            None => true,
+            Some(lease) => {
+                lease.prune(Instant::now());
+                lease.holders.is_empty() || lease.holders.contains_key(&client)
+            }
+        }
+    }
+
+    /// Returns true if the SDBP frame may change the module state.
+    ///
+    /// Only core descriptor, dummy, wait and notification reads are known to be read-only.
+    /// Custom classes are module specific and therefore always treated as mutating.
+    pub fn is_mutating(raw : &[u8]) -> bool {
+        if raw.len() < 2 || raw[0] != protocol::CLASS_ID {
+            return true;
+        }
+        !matches!(raw[1], protocol::classes::descriptor::ID
+            | protocol::classes::dummy::ID
+            | protocol::classes::wait::ID
+            | protocol::classes::notification::ID)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusive_lease_blocks_other_clients() {
+        let locks = SlotLocks::new();
+        assert!(locks.acquire(0x1001, 3, LockMode::Exclusive, Duration::from_secs(10)).is_ok());
+        assert!(locks.is_permitted(0x1001, 3));
+        assert!(!locks.is_permitted(0x1002, 3));
+        assert!(locks.is_permitted(0x1002, 4));
+        assert!(locks.acquire(0x1002, 3, LockMode::Shared, Duration::from_secs(10)).is_err());
+
+        locks.release_all(0x1001);
+        assert!(locks.is_permitted(0x1002, 3));
+    }
+
+    #[test]
+    fn shared_lease_allows_holders() {
+        let locks = SlotLocks::new();
+        assert!(locks.acquire(0x1001, 3, LockMode::Shared, Duration::from_secs(10)).is_ok());
+        assert!(locks.acquire(0x1002, 3, LockMode::Shared, Duration::from_secs(10)).is_ok());
+        assert!(locks.acquire(0x1002, 3, LockMode::Exclusive, Duration::from_secs(10)).is_err());
+        assert!(!locks.is_permitted(0x1003, 3));
+
+        locks.release(0x1001, 3);
+        assert!(locks.acquire(0x1002, 3, LockMode::Exclusive, Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn lease_expires() {
+        let locks = SlotLocks::new();
+        assert!(locks.acquire(0x1001, 3, LockMode::Exclusive, Duration::from_millis(10)).is_ok());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(locks.is_permitted(0x1002, 3));
+        assert!(locks.acquire(0x1002, 3, LockMode::Exclusive, Duration::from_secs(10)).is_ok());
+    }
+}
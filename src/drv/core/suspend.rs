@@ -0,0 +1,128 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use crossbeam_channel::Sender;
+
+/// Timeout used by `PowerHandle::suspend_all` and `PowerHandle::resume_all` per slot
+pub const DEFAULT_POWER_TIMEOUT : Duration = Duration::from_secs(2);
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum PowerCommand {
+    /// `MODE_SUSPEND`, keep-alives and notification reads stop until the slot is resumed
+    Suspend,
+    /// `MODE_RUN` followed by a descriptor refresh
+    Resume,
+}
+
+/// Outcome of a suspend or resume of a single slot
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SlotPowerResult {
+    pub slot : u16,
+    pub error : Option<String>,
+}
+
+impl SlotPowerResult {
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Results of a suspend or resume in the order the slots were handled
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PowerReport {
+    pub command : PowerCommand,
+    pub slots : Vec<SlotPowerResult>,
+}
+
+impl PowerReport {
+
+    pub fn is_ok(&self) -> bool {
+        self.slots.iter().all(|slot| slot.is_ok())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &SlotPowerResult> {
+        self.slots.iter().filter(|slot| !slot.is_ok())
+    }
+}
+
+/// Request handled by the power worker of the controller, see `Controller::power_handle`
+pub struct PowerRequest {
+    pub(crate) command : PowerCommand,
+    pub(crate) slots : Option<Vec<u16>>,
+    pub(crate) timeout : Duration,
+    pub(crate) reply : Sender<PowerReport>,
+}
+
+/// Suspends and resumes the modules of a running `Controller`, can be cloned into other threads
+///
+/// Slots are suspended in the given order, or by ascending slot number if no slots are given.
+/// Resuming without slots handles the suspended slots in reverse order, given slots are resumed
+/// from last to first. `timeout` applies to every single slot.
+#[derive(Clone)]
+pub struct PowerHandle {
+    tx : Sender<PowerRequest>,
+}
+
+impl PowerHandle {
+
+    pub(crate) fn new(tx : Sender<PowerRequest>) -> PowerHandle {
+        PowerHandle { tx }
+    }
+
+    pub fn suspend(&self, slots : Option<Vec<u16>>, timeout : Duration) -> Result<PowerReport,Error> {
+        self.request(PowerCommand::Suspend, slots, timeout)
+    }
+
+    pub fn resume(&self, slots : Option<Vec<u16>>, timeout : Duration) -> Result<PowerReport,Error> {
+        self.request(PowerCommand::Resume, slots, timeout)
+    }
+
+    pub fn suspend_all(&self) -> Result<PowerReport,Error> {
+        self.suspend(None, DEFAULT_POWER_TIMEOUT)
+    }
+
+    pub fn resume_all(&self) -> Result<PowerReport,Error> {
+        self.resume(None, DEFAULT_POWER_TIMEOUT)
+    }
+
+    fn request(&self, command : PowerCommand, slots : Option<Vec<u16>>, timeout : Duration) -> Result<PowerReport,Error> {
+        let (reply, rx) = crossbeam_channel::bounded(1);
+        self.tx.send(PowerRequest { command, slots, timeout, reply })
+            .map_err(|_| Error::new(ErrorKind::NotConnected, "Controller is stopped"))?;
+        rx.recv().map_err(|_| Error::new(ErrorKind::NotConnected, "Controller is stopped"))
+    }
+}
+
+/// Orders the slots of a request, `suspended` holds the suspended slots in the order they were suspended
+pub(crate) fn power_order(command : PowerCommand, slots : Option<Vec<u16>>, mut known : Vec<u16>, suspended : &[u16]) -> Vec<u16> {
+    known.sort_unstable();
+    match (command, slots) {
+        (PowerCommand::Suspend, Some(slots)) => slots,
+        (PowerCommand::Suspend, None) => known,
+        (PowerCommand::Resume, Some(slots)) => slots.into_iter().rev().collect(),
+        (PowerCommand::Resume, None) if !suspended.is_empty() => suspended.iter().rev().copied().collect(),
+        (PowerCommand::Resume, None) => known.into_iter().rev().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_reverses_the_suspend_order() {
+        let known = vec![3, 1, 2];
+        assert_eq!(power_order(PowerCommand::Suspend, None, known.clone(), &[]), vec![1, 2, 3]);
+        assert_eq!(power_order(PowerCommand::Suspend, Some(vec![2, 1]), known.clone(), &[]), vec![2, 1]);
+        assert_eq!(power_order(PowerCommand::Resume, None, known.clone(), &[2, 1]), vec![1, 2]);
+        assert_eq!(power_order(PowerCommand::Resume, None, known.clone(), &[]), vec![3, 2, 1]);
+        assert_eq!(power_order(PowerCommand::Resume, Some(vec![2, 3]), known, &[2]), vec![3, 2]);
+
+        let report = PowerReport { command : PowerCommand::Suspend, slots : vec![
+            SlotPowerResult { slot : 1, error : None },
+            SlotPowerResult { slot : 2, error : Some("timeout".to_string()) },
+        ]};
+        assert!(!report.is_ok());
+        assert_eq!(report.failed().map(|slot| slot.slot).collect::<Vec<u16>>(), vec![2]);
+    }
+}
@@ -1,44 +1,44 @@
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::path::PathBuf;
 use std::fs::{ create_dir_all};
 use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
 use crate::util::*;
 use crate::drv::core::*;
+use crate::drv::api::ModEvent;
 use std::io::{Error, ErrorKind};
 
-use std::collections::hash_map::Iter;
-use std::os::unix::net::UnixStream;
-
-// pub struct UdsServerBuilder {
-//
-//     meta : Option<DrvMeta>,
-//     com : Option<ComHandler>,
-//     stats:  Option<SharedStats>,
-// }
-
-// impl UdsServerBuilder {
-//
-//
-//
-//     fn start(self) -> Result<UdsServer,Error> {
-//
-//         if self.meta.is_none() ||
-//             self.stats.is_none() ||
-//             self.com.is_none() {
-//
-//             Err(Error::new(ErrorKind::InvalidInput,"Missing parameter"))
-//
-//         }
-//         Ok(UdsServer::start(self.meta.unwrap(),self.com.unwrap(),self.stats.unwrap()))
-//
-//     }
-// }
+use std::collections::hash_map::{Iter, IterMut};
+use crossbeam_channel::{Receiver, Select, Sender};
+use mio::{Events, Interest, Poll, Token, Waker};
+use mio::net::UnixListener;
+
+const LISTENER : Token = Token(0);
+const WAKER : Token = Token(1);
+
+const CLIENT_MASK : u16 = 0x1000;
+const CLIENT_ID_RANGE : u16 = 0x1000;
+const IDLE_TIMEOUT : Duration = Duration::from_millis(500);
+
+/// Settings of the client socket server
+#[derive(Debug,Clone)]
+pub struct UdsServerConfig {
+    /// Authorization policy for client commands
+    pub policy : AccessPolicy,
+    /// Maximum number of concurrent clients, further connections are closed immediately (at most 0x1000)
+    pub max_clients : usize,
+}
 
+impl Default for UdsServerConfig {
+    fn default() -> Self {
+        UdsServerConfig { policy : AccessPolicy::default(), max_clients : 64 }
+    }
+}
 
 pub struct UdsServer {
-    path: String,
     handle : ManagedThreadHandle<()>,
+    waker : Arc<Waker>,
 }
 
 
@@ -51,74 +51,172 @@ pub struct ClientMap {
 
 impl ClientMap {
 
-    fn inc_next(&mut self) {
-        self.next += 1;
-        if self.next > 0xFFF {
-            self.next = 0;
-        }
-    }
-
     pub fn new() -> ClientMap {
         ClientMap {next: 0, map: HashMap::new()}
     }
 
+    /// Returns the next unused client id, ids are handed out round robin
     pub fn get_next(&mut self) -> Result<u16,Error> {
 
-        let mut result = Err(Error::new(ErrorKind::AddrNotAvailable, "No Address available"));
-
-        if !self.map.contains_key(&self.next) {
-            result = Ok(self.next | 0x1000);
-            self.inc_next();
-        } else {
-
-            let mut addrs: Vec<_> = self.map.keys().collect();
-            addrs.push(&0);
-            addrs.push(&0xFFF);
-            addrs.sort_by(| a, b | { a.cmp(b)});
-
-            let mut i = 0;
-
-            if addrs.len() == 1 {
-                result = Ok((addrs[i] + 1) | 0x1000);
-                self.next = addrs[i]+1;
-            }
-
-            while i + 1 < addrs.len() -1 {
-                if addrs[i+1] - addrs[i] > 1 {
-                    result = Ok((addrs[i] + 1) | 0x1000);
-                    self.next = addrs[i]+1;
-                    break;
-                }
-                i += 1;
+        for _ in 0..CLIENT_ID_RANGE {
+            let id = self.next | CLIENT_MASK;
+            self.next = (self.next + 1) % CLIENT_ID_RANGE;
+            if !self.map.contains_key(&id) {
+                return Ok(id);
             }
         }
-        return result;
+        Err(Error::new(ErrorKind::AddrNotAvailable, "No Address available"))
     }
 
-    fn remove(&mut self, id: u16) {
-        self.map.remove(&id);
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn remove(&mut self, id: u16) -> Option<UdsSessionHandler> {
+        self.map.remove(&id)
     }
 
     fn insert(&mut self,id: u16, session: UdsSessionHandler) -> Option<UdsSessionHandler>{
         self.map.insert(id,session)
     }
 
-    fn get(&mut self, id: u16) -> Option<&UdsSessionHandler> {
-        self.map.get(&id)
+    fn get_mut(&mut self, id: u16) -> Option<&mut UdsSessionHandler> {
+        self.map.get_mut(&id)
+    }
+
+    fn iter_mut(&mut self) -> IterMut<'_, u16, UdsSessionHandler> {
+        self.map.iter_mut()
     }
 
     pub fn iter(&self) -> Iter<'_, u16, UdsSessionHandler> {
         self.map.iter()
     }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.map.values().filter_map(|session| session.next_deadline()).min()
+    }
+}
+
+impl Default for ClientMap {
+    fn default() -> Self {
+        ClientMap::new()
+    }
+}
+
+/// State of the server thread
+struct UdsServerLogic {
+    poll : Poll,
+    listener : UnixListener,
+    clients : ClientMap,
+    com : ComHandler,
+    dispatcher : Sender<PMsg>,
+    responses : Sender<PMsg>,
+    locks : SlotLocks,
+    config : UdsServerConfig,
+}
+
+impl UdsServerLogic {
+
+    fn accept(&mut self) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    warn!("Could not accept client: {}", err);
+                    break;
+                }
+            };
+
+            if self.clients.len() >= self.config.max_clients {
+                warn!("Rejected client, limit of {} clients reached", self.config.max_clients);
+                continue;
+            }
+
+            let peer = match PeerCredentials::from_stream(&stream) {
+                Ok(peer) => peer,
+                Err(err) => {
+                    warn!("Rejected client: {}", err);
+                    continue;
+                }
+            };
+
+            let id = match self.clients.get_next() {
+                Ok(id) => id,
+                Err(err) => {
+                    warn!("Rejected client: {}", err);
+                    continue;
+                }
+            };
+
+            let access = SessionAccess { locks : self.locks.clone(), peer, policy : self.config.policy.clone() };
+            let mut session = UdsSessionHandler::new(id, stream, access);
+            if let Err(err) = session.register(self.poll.registry()) {
+                warn!("Could not register client-{}: {}", id, err);
+                continue;
+            }
+
+            self.com.register_client(id, self.responses.clone());
+            self.clients.insert(id, session);
+        }
+    }
+
+    fn close(&mut self, id : u16) {
+        if let Some(mut session) = self.clients.remove(id) {
+            debug!("Unregister Client: {}", id);
+            self.com.unregister_client(id);
+            session.close(self.poll.registry());
+        }
+    }
+
+    fn close_all(&mut self) {
+        let ids : Vec<u16> = self.clients.iter().map(|(id, _)| *id).collect();
+        for id in ids {
+            self.close(id);
+        }
+    }
 }
 
 
 impl UdsServer {
 
-    fn uds_server(ctl_pair : ChannelPair<ManagedThreadState>, meta : DrvMeta, com : ComHandler,stats : SharedStats) {
+    /// Forwards device responses to the event loop and wakes it up on responses and stats changes
+    fn bridge(ctl_pair : ChannelPair<ManagedThreadState>, rx : Receiver<PMsg>, tx : Sender<PMsg>, mut watcher : Watcher<Stats>, waker : Arc<Waker>) {
+
+        let mut stopped = false;
+        let change_rx = watcher.receiver().clone();
+        while !stopped {
+            let mut select = Select::new();
+            let responses = select.recv(&rx);
+            let changes = select.recv(&change_rx);
+
+            if let Ok(operation) = select.select_timeout(Duration::from_millis(100)) {
+                match operation.index() {
+                    index if index == responses => {
+                        if let Ok(msg) = operation.recv(&rx) {
+                            let _ = tx.send(msg);
+                        }
+                    },
+                    index if index == changes => {
+                        let _ = operation.recv(&change_rx);
+                        watcher.latest();
+                    },
+                    _ => (),
+                }
+                let _ = waker.wake();
+            }
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+        }
+    }
+
+    fn uds_server(ctl_pair : ChannelPair<ManagedThreadState>, meta : DrvMeta, com : ComHandler, stats : SharedStats, config : UdsServerConfig, poll : Poll, waker : Arc<Waker>) {
 
         let mut stopped = false;
-        let mut clients = ClientMap::new();
         let thread_name = std::thread::current().name().expect("Could not get tread name").to_string();
 
         info!("Started {}",thread_name);
@@ -136,70 +234,256 @@ impl UdsServer {
 
         debug!("Listening @ {:?}",path);
 
-        let chn_result = crossbeam_channel::unbounded();
-
         let uds = UnixDomainSocket::bind(path.clone()).expect("Could not bind UDS socket!");
-        let _ = uds.get_listener().set_nonblocking(false);
+        let listener = uds.get_listener().try_clone().expect("Could not clone UDS listener!");
+        listener.set_nonblocking(true).expect("Could not set UDS listener non-blocking!");
 
         let meta = std::fs::metadata(path.clone()).expect("Could not read socket metadata!");
         let mut perm = meta.permissions();
         perm.set_mode(0o770);
         std::fs::set_permissions(path.clone(),perm).expect("Failed setting socket permissions!");
 
-        for stream in uds.get_listener().incoming() {
-            match stream {
-                Ok(stream) => {
-                    /* connection succeeded */
-                    let id = clients.get_next().expect("Could not get new client id!");
-                    let pair = com.register_new_client(id);
-                    let handle = UdsSessionHandler::start(id, pair, stream, chn_result.0.clone(), stats.clone());
-                    clients.insert(id, handle);
+        let (response_tx, response_rx) = crossbeam_channel::unbounded();
+        let (loop_tx, loop_rx) = crossbeam_channel::unbounded();
+
+        let mut logic = UdsServerLogic {
+            poll,
+            listener : UnixListener::from_std(listener),
+            clients : ClientMap::new(),
+            dispatcher : com.client_source(),
+            com,
+            responses : response_tx,
+            locks : SlotLocks::new(),
+            config,
+        };
+        logic.poll.registry().register(&mut logic.listener, LISTENER, Interest::READABLE).expect("Could not register UDS listener!");
+
+        let bridge_waker = waker.clone();
+        let bridge_watcher = stats.subscribe();
+        let bridge = spawn("UdsBridge".to_string(), move |ctl_pair| UdsServer::bridge(ctl_pair, response_rx, loop_tx, bridge_watcher, bridge_waker));
+
+        let mut watcher = stats.subscribe();
+        let mut snapshot = watcher.latest();
+        let mut events = Events::with_capacity(128);
+
+        while !stopped {
+
+            let timeout = match logic.clients.next_deadline() {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()).min(IDLE_TIMEOUT),
+                None => IDLE_TIMEOUT,
+            };
 
+            if let Err(err) = logic.poll.poll(&mut events, Some(timeout)) {
+                if err.kind() != ErrorKind::Interrupted {
+                    error!("Could not poll client sockets: {}", err);
+                    break;
                 }
-               _ => trace!("Empty stream handle!"),
             }
 
-            let result = chn_result.1.recv_timeout(Duration::from_millis(10));
-            match result {
-                Ok(value) => {
-                    debug!("Unregister Client: {:?}",value);
-                    com.unregister_client(value.get_id());
-                    let t = clients.get(value.get_id());
-                    match t  {
-                        None => {
-                           warn!("Could not stop UdsSessionHandler!")
-                        }
-                        Some(thread) => {
-                            thread.stop(Duration::from_millis(1000));
+            let mut closed = Vec::new();
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => logic.accept(),
+                    WAKER => (),
+                    Token(token) => {
+                        let id = token as u16;
+                        if let Some(session) = logic.clients.get_mut(id) {
+                            let mut result = Ok(());
+                            if event.is_readable() || event.is_read_closed() {
+                                result = session.on_readable();
+                            }
+                            if result.is_ok() && event.is_writable() {
+                                result = session.on_writable();
+                            }
+                            if let Err(err) = result {
+                                trace!("client-{} - {}", id, err);
+                                closed.push(id);
+                            }
                         }
-                    };
-                    clients.remove(value.get_id());
-                },
-                _ =>  trace!("Result is empty!"),
-            };
+                    }
+                }
+            }
 
-            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
-            if stopped {
-                break;
+            while let Ok(msg) = loop_rx.try_recv() {
+                match logic.clients.get_mut(msg.get_dst()) {
+                    Some(session) => session.on_device_response(msg),
+                    None => trace!("Dropped response for disconnected client {}", msg.get_dst()),
+                }
+            }
+
+            if watcher.has_changed() {
+                let previous = std::mem::replace(&mut snapshot, watcher.latest());
+                let version = watcher.version() as u32;
+                trace!("Changed Stats (version {}): \n{:?}", version, snapshot);
+
+                // Changes published in between are merged, the events describe the difference of the snapshots.
+                // Link updates leave the device list as it is and are not announced.
+                let mut events = ModEvent::diff(previous.devices(), snapshot.devices(), version);
+                if !events.is_empty() {
+                    events.push(ModEvent::device_list_changed(version));
+                }
+                for (_, session) in logic.clients.iter_mut() {
+                    for event in &events {
+                        session.push_event(event);
+                    }
+                }
+            }
+
+            let now = Instant::now();
+            let registry = logic.poll.registry();
+            for (id, session) in logic.clients.iter_mut() {
+                if closed.contains(id) {
+                    continue;
+                }
+                session.check_timeout(now);
+                session.process(&snapshot, &logic.dispatcher);
+                if let Err(err) = session.on_writable().and_then(|_| session.update_interest(registry)) {
+                    trace!("client-{} - {}", id, err);
+                    closed.push(*id);
+                }
+            }
+
+            for id in closed {
+                logic.close(id);
             }
-        }
 
-        for t in clients.iter() {
-            t.1.stop(Duration::from_millis(1000));
+            // Acknowledged after the cleanup, so the socket is gone once `stop` returns
+            stopped = matches!(ctl_pair.rx().try_recv(), Ok(ManagedThreadState::STOPPED));
         }
 
+        logic.close_all();
+        let _ = bridge.stop(Duration::from_millis(500));
+        drop(uds);
+
         info!("Stopped {}",thread_name);
+        let _ = ctl_pair.tx().send(ManagedThreadState::OK);
     }
 
     pub fn start(meta: DrvMeta, com : ComHandler,stats : SharedStats) -> UdsServer {
-        let path = meta.socket().clone();
-        let handle = spawn( "UdsHandler".to_string(),move | ctl_pair| UdsServer::uds_server(ctl_pair, meta, com, stats));
-        UdsServer { path: format!("{}",path), handle}
+        UdsServer::start_with_config(meta, com, stats, UdsServerConfig::default())
+    }
+
+    /// Starts the server, client commands are authorized against `config.policy`
+    pub fn start_with_config(meta: DrvMeta, com : ComHandler,stats : SharedStats, config : UdsServerConfig) -> UdsServer {
+        let mut config = config;
+        config.max_clients = config.max_clients.min(CLIENT_ID_RANGE as usize);
+
+        let poll = Poll::new().expect("Could not create UDS poll instance!");
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER).expect("Could not create UDS waker!"));
+        let thread_waker = waker.clone();
+        let handle = spawn( "UdsHandler".to_string(),move | ctl_pair| UdsServer::uds_server(ctl_pair, meta, com, stats, config, poll, thread_waker));
+        UdsServer { handle, waker }
     }
 
     pub fn stop(&self, dur : Duration) {
-        let _ = self.handle.stop(dur);
-        let _ = UnixStream::connect(&self.path); // Trigger wakeup
+        if self.handle.chn.tx().send(ManagedThreadState::STOPPED).is_err() {
+            trace!("UdsHandler already stopped");
+            return;
+        }
+        let _ = self.waker.wake();
+
+        match self.handle.chn.rx().recv_timeout(dur) {
+            Ok(ManagedThreadState::OK) => debug!("Successfully stopped UdsHandler"),
+            _ => warn!("Cannot stop UdsHandler"),
+        };
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+    use crate::datatypes::{AdvancedVersion, Descriptor, Version};
+    use crate::drv::api::{EventType, Manager};
+
+    fn server(name : &str, max_clients : usize) -> (UdsServer, Receiver<IntUdsEvent>, PathBuf, SharedStats) {
+        let path = std::env::temp_dir().join(format!("sdbp-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (uds_tx, uds_rx) = crossbeam_channel::unbounded();
+        let (dev_tx, _) = crossbeam_channel::unbounded();
+        let com = ComHandler::new(uds_tx, dev_tx, crossbeam_channel::unbounded().0, crossbeam_channel::unbounded().0);
+        let stats = SharedStats::new(Stats::new("test".to_string(), Version::new(1, 0, 0), Version::new(1, 0, 0)));
+        let meta = DrvMeta::new("test".to_string(), "test".to_string(), path.to_string_lossy().to_string());
+        let config = UdsServerConfig { policy : AccessPolicy::allow_all(), max_clients };
+
+        let server = UdsServer::start_with_config(meta, com, stats.clone(), config);
+        let start = Instant::now();
+        while !path.exists() && start.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        (server, uds_rx, path, stats)
+    }
+
+    fn next_state(rx : &Receiver<IntUdsEvent>) -> (u16, UdsEventState) {
+        let event = rx.recv_timeout(Duration::from_secs(2)).expect("No client event");
+        (event.id, event.evt_type)
+    }
+
+    #[test]
+    fn clients_above_the_limit_are_rejected_and_closed_sessions_reaped() {
+        let (server, events, path, _) = server("limit", 1);
+
+        let first = UnixStream::connect(&path).unwrap();
+        let (id, state) = next_state(&events);
+        assert_eq!(state, UdsEventState::CONNECTED);
+
+        // The second client is closed right after the accept
+        let mut second = UnixStream::connect(&path).unwrap();
+        second.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        assert_eq!(second.read(&mut [0u8; 16]).unwrap(), 0);
+        assert!(events.try_recv().is_err());
+
+        drop(first);
+        let (closed, state) = next_state(&events);
+        assert_eq!(closed, id);
+        assert_eq!(state, UdsEventState::DISCONNECTED);
+
+        // The freed slot accepts a new client
+        let _third = UnixStream::connect(&path).unwrap();
+        assert_eq!(next_state(&events).1, UdsEventState::CONNECTED);
+
+        server.stop(Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stop_returns_without_a_client() {
+        let (server, _events, path, _) = server("stop", 4);
+        assert!(path.exists());
+        std::thread::sleep(Duration::from_millis(50));
+
+        // The waker interrupts the idle poll, no connection is needed to notice the stop
+        let start = Instant::now();
+        server.stop(Duration::from_secs(5));
+        assert!(start.elapsed() < IDLE_TIMEOUT);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn link_updates_are_not_announced_as_device_list_changes() {
+        let (server, _events, path, stats) = server("links", 4);
+        let mut manager = Manager::new(path.to_string_lossy().to_string(), Some(Duration::from_millis(100))).unwrap();
+        manager.subscribe_events(true).unwrap();
+
+        stats.update(|stats| stats.set_link(1, Some(LinkSettings { frame_size : 64, sclk_speed : 1000, step_downs : 0 })));
+        std::thread::sleep(Duration::from_millis(200));
+
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_adr(1);
+        desc.set_product_name("IO Module".to_string());
+        desc.set_vendor_name("Noreya".to_string());
+        desc.set_vendor_product_id("io".to_string());
+        desc.set_serial("0001".to_string());
+        desc.set_fw_version(AdvancedVersion::new('S', 1, 0, 0));
+        desc.set_bootloader_state("not supported".to_string());
+        let version = stats.update(|stats| stats.get_devices().push(desc));
+
+        // The first event is the hotplug of the device, the link update was skipped
+        assert_eq!(manager.next_event().unwrap().event_type, EventType::DeviceAdded);
+        let changed = manager.next_event().unwrap();
+        assert_eq!((changed.event_type, changed.list_version), (EventType::DeviceListChanged, version as u32));
+
+        server.stop(Duration::from_secs(2));
+    }
+}
@@ -1,149 +1,427 @@
-use std::time::Duration;
-use std::os::unix::net::{UnixStream};
-use std::io::{ErrorKind};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use std::io::{Read, Write, Error, ErrorKind};
 use crossbeam_channel::Sender;
+use mio::{Interest, Registry, Token};
+use mio::net::UnixStream;
 
-use crate::util::*;
-use crate::drv::api::{ModApi, Command, TlvValue, Tag, IntoBytes};
-use crate::drv::core::{PMsg, SharedStats};
+use crate::drv::api::{ModApi, ModEvent, Command, Request, TlvValue, Tag, IntoBytes};
+use crate::drv::core::{PMsg, Stats, SlotLocks, LockMode, PeerCredentials, AccessPolicy, CommandClass};
 use crate::drv::api::Response;
-use crate::drv::api::Error;
-
-#[derive(Debug)]
-pub enum UdsSessionStatus {
-    DISCONNECTED
+use crate::drv::api::Error as MdError;
+use crate::sdbp::response::core::transaction_error::TransactionError;
+
+const MAX_FRAME_LENGTH : usize = 4096;
+const DEVICE_TIMEOUT : Duration = Duration::from_secs(5);
+/// Requests a client may queue while one is in flight, the session is closed above
+const MAX_QUEUED_REQUESTS : usize = 64;
+/// Output a client may leave unread, e.g. events of a subscribed client, the session is closed above
+const MAX_TX_BUFFER : usize = 1024 * 1024;
+
+/// Ownership and authorization state of a client session
+pub struct SessionAccess {
+    pub locks : SlotLocks,
+    pub peer : PeerCredentials,
+    pub policy : AccessPolicy,
 }
 
-#[derive(Debug)]
-pub struct UdsSessionResult {
+/// Device request which is waiting for the response of the dispatcher
+struct PendingRequest {
+    dev_adr : u16,
+    /// Tells the response apart from late responses to timed out requests
+    seq : u32,
+    deadline : Instant,
+}
 
+/// State of a single client connection driven by the `UdsServer` event loop.
+///
+/// Requests are answered in order, a session has at most one device request in flight.
+/// Sessions which queue too many requests or do not read their output are closed.
+pub struct UdsSessionHandler {
     id : u16,
-    status : UdsSessionStatus,
+    stream : UnixStream,
+    access : SessionAccess,
+    rx_buffer : Vec<u8>,
+    tx_buffer : Vec<u8>,
+    requests : VecDeque<Vec<u8>>,
+    pending : Option<PendingRequest>,
+    next_seq : u32,
+    /// Output exceeded `MAX_TX_BUFFER`
+    overflowed : bool,
+    writable : bool,
+    subscribed : bool,
 }
 
-impl UdsSessionResult {
+impl UdsSessionHandler {
 
-    pub fn disconnected(id : u16) -> UdsSessionResult {
-           UdsSessionResult {status : UdsSessionStatus::DISCONNECTED,id}
+    pub fn new(id : u16, stream : UnixStream, access : SessionAccess) -> UdsSessionHandler {
+        debug!("Started client-{} for pid {} (uid {}, gid {})", id, access.peer.pid, access.peer.uid, access.peer.gid);
+        UdsSessionHandler {
+            id,
+            stream,
+            access,
+            rx_buffer : Vec::new(),
+            tx_buffer : Vec::new(),
+            requests : VecDeque::new(),
+            pending : None,
+            next_seq : 0,
+            overflowed : false,
+            writable : false,
+            subscribed : false,
+        }
     }
 
-    pub fn get_status(&self) -> &UdsSessionStatus {
-        &self.status
+    pub fn get_id(&self) -> u16 {
+        self.id
     }
 
-    pub fn get_id(&self) -> u16{
-        self.id
+    pub fn register(&mut self, registry : &Registry) -> Result<(),Error> {
+        registry.register(&mut self.stream, Token(self.id as usize), Interest::READABLE)
     }
-}
 
+    /// Deregisters the stream and releases all slot leases of the session
+    pub fn close(&mut self, registry : &Registry) {
+        let _ = registry.deregister(&mut self.stream);
+        self.access.locks.release_all(self.id);
+        debug!("Stopped client-{}", self.id);
+    }
 
-pub type FuncUdsSessionTask = fn(ctl_pair : ChannelPair<ManagedThreadState>, nr : u16, data_pair : ChannelPair<PMsg>, stream : UnixStream, chn_result : Sender<UdsSessionResult>, stats : SharedStats);
+    /// Splits the next length prefixed frame off `buffer`
+    fn take_frame(buffer : &mut Vec<u8>) -> Result<Option<Vec<u8>>,Error> {
+        if buffer.len() < 4 {
+            return Ok(None);
+        }
 
+        let length = u32::from_ne_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+        if length > MAX_FRAME_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Header length out of range: {}", length)));
+        }
+        if buffer.len() < 4 + length {
+            return Ok(None);
+        }
 
-pub struct UdsSessionHandler {
-    handle : ManagedThreadHandle<()>
-}
+        let frame = buffer[4..4 + length].to_vec();
+        buffer.drain(..4 + length);
+        Ok(Some(frame))
+    }
 
-#[allow(unused_assignments,unused_variables)]
-impl UdsSessionHandler {
+    /// Reads everything available on the socket, fails if the peer closed the connection
+    pub fn on_readable(&mut self) -> Result<(),Error> {
+        let mut buffer = [0u8; MAX_FRAME_LENGTH];
 
-    fn task(ctl_pair : ChannelPair<ManagedThreadState>, nr : u16, data_pair : ChannelPair<PMsg>, stream : UnixStream, chn_result : Sender<UdsSessionResult>, stats : SharedStats) {
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(Error::new(ErrorKind::ConnectionAborted, "Socket closed")),
+                Ok(len) => self.rx_buffer.extend_from_slice(&buffer[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
 
-        let stopped = false;
-        let mut shared = stats;
-        let mut _stats  = shared.read();
-        let thread_name = std::thread::current().name().expect("Could not get tread name").to_string();
-        debug!("Started {}",thread_name);
-        trace!("Stats: {}",_stats);
+            // Split after every read, so the buffer never holds more than a frame and a read
+            while let Some(frame) = UdsSessionHandler::take_frame(&mut self.rx_buffer)? {
+                if self.requests.len() >= MAX_QUEUED_REQUESTS {
+                    return Err(Error::new(ErrorKind::OutOfMemory, format!("More than {} queued requests", MAX_QUEUED_REQUESTS)));
+                }
+                self.requests.push_back(frame);
+            }
+        }
+        Ok(())
+    }
 
-        let mut reader = UnixStreamReader::from_unix_stream(stream,Some(Duration::from_millis(500)));
+    /// Writes as much of the queued output as the socket accepts, fails if the client does not read its output
+    pub fn on_writable(&mut self) -> Result<(),Error> {
+        if self.overflowed {
+            return Err(Error::new(ErrorKind::OutOfMemory, format!("More than {} bytes of unread output", MAX_TX_BUFFER)));
+        }
+        while !self.tx_buffer.is_empty() {
+            match self.stream.write(&self.tx_buffer) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "Socket closed")),
+                Ok(len) => { self.tx_buffer.drain(..len); },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
 
-        loop {
+    /// Requests write readiness only while output is queued
+    pub fn update_interest(&mut self, registry : &Registry) -> Result<(),Error> {
+        let writable = !self.tx_buffer.is_empty();
+        if writable == self.writable {
+            return Ok(());
+        }
+
+        let interest = if writable { Interest::READABLE | Interest::WRITABLE } else { Interest::READABLE };
+        registry.reregister(&mut self.stream, Token(self.id as usize), interest)?;
+        self.writable = writable;
+        Ok(())
+    }
+
+    fn queue_response(&mut self, response : &Response) {
+        let msg = response.to_bytes();
+        if self.overflowed || self.tx_buffer.len() + 4 + msg.len() > MAX_TX_BUFFER {
+            self.overflowed = true;
+            return;
+        }
+        self.tx_buffer.extend_from_slice(&(msg.len() as u32).to_ne_bytes());
+        self.tx_buffer.extend_from_slice(msg);
+    }
+
+    /// Handles queued requests until one has to wait for a device
+    pub fn process(&mut self, stats : &Stats, dispatcher : &Sender<PMsg>) {
+        while self.pending.is_none() {
+            let input = match self.requests.pop_front() {
+                None => break,
+                Some(value) => value,
+            };
+
+            if let Some(response) = self.handle_request(&input, stats, dispatcher) {
+                self.queue_response(&response);
+            }
+        }
+    }
 
-            let raw_input = reader.read_msg();
-
-            if let Err(err) = raw_input {
-
-                match err.kind() {
-                    ErrorKind::TimedOut => (),
-                    ErrorKind::ConnectionAborted => { trace!("{} - Socket closed",thread_name); break },
-                    _ => (),
-                };
-            } else if let Ok(input) = raw_input {
-
-                //info!("Received:  {:?}", request.as_slice());
-               let command = ModApi::parse(&input);
-                trace!("Received Command: {:?}",command.0);
-               let response =  match command {
-                   (Some(Command::Info),Some(request)) => ModApi::info(_stats.get_version(), _stats.get_sdbpk_version()),
-                   (Some(Command::GetDeviceList),Some(request))  => ModApi::get_device_list(_stats.get_devices(),request.get_payload()),
-                   (Some(Command::GetDescriptor),Some(request))  => ModApi::get_descriptor(_stats.get_devices(),request.get_payload()),
-                   (Some(Command::Device),Some(request))  => {
-
-                       let _ = data_pair.tx().send(PMsg::create(nr, request.get_dev_id(),Ok(request.get_payload().to_vec())));
-                       let result = data_pair.rx().recv_timeout(Duration::from_secs(5));
-
-                       let response = match result {
-
-                           Err(_err) => {
-                               Response::new_error(Error::DeviceNotConnected)
-                           },
-
-                           Ok(value) => {
-                               let mut response = Response::new_empty_response();
-                               let mut tlv = TlvValue::new();
-
-                               let msg = match value.get_msg() {
-                                   None => {Response::new_error(Error::DeviceNotConnected)}
-                                   Some(val) => {
-                                       tlv.push(Tag::Response,TlvValue::from(val));
-                                       response.append_bytes(tlv.into_bytes().as_slice());
-                                       response
-                                   }
-                               };
-                               msg
-                           }
-                       };
-                       response
-                    },
-                    _ =>  Response::new_error(Error::UnknownCommand)
-                };
-                let _ = reader.write_msg(response.to_bytes());
+    /// Forwards a device response, responses of timed out requests are dropped
+    pub fn on_device_response(&mut self, msg : PMsg) {
+        match &self.pending {
+            Some(pending) if pending.dev_adr == msg.get_src() && pending.seq == msg.get_seq() => (),
+            _ => {
+                trace!("client-{} dropped stale response {} from {}", self.id, msg.get_seq(), msg.get_src());
+                return;
             }
+        };
+        self.pending = None;
+
+        let response = match msg.get_msg() {
+            None => match msg.get_error().and_then(TransactionError::from_io_error) {
+                Some(err) => {
+                    let mut response = Response::new_error(MdError::TransactionError);
+                    response.append_byte(err.code);
+                    response
+                }
+                None => Response::new_error(MdError::DeviceNotConnected),
+            },
+            Some(val) => {
+                let mut response = Response::new_empty_response();
+                let mut tlv = TlvValue::new();
+                tlv.push(Tag::Response,TlvValue::from(val));
+                response.append_bytes(tlv.into_bytes().as_slice());
+                response
+            }
+        };
+        self.queue_response(&response);
+    }
+
+    /// Pushes an event if the client subscribed to events
+    pub fn push_event(&mut self, event : &ModEvent) {
+        if self.subscribed {
+            self.queue_response(&event.to_response());
+        }
+    }
 
-            if shared.shared().get_timestamp() > _stats.get_timestamp() {
-                trace!("Changed Stats: \n{:?}", _stats);
-                _stats = shared.read();
+    /// Answers a device request with `DeviceNotConnected` if it was not answered in time
+    pub fn check_timeout(&mut self, now : Instant) {
+        if let Some(pending) = &self.pending {
+            if pending.deadline <= now {
+                debug!("client-{} request to {} timed out", self.id, pending.dev_adr);
+                self.pending = None;
+                self.queue_response(&Response::new_error(MdError::DeviceNotConnected));
             }
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.deadline)
+    }
+
+    fn lock_slot(locks : &SlotLocks, nr : u16, payload : &[u8]) -> Response {
 
-            if !ctl_pair.rx().is_empty() {
-                match ctl_pair.rx().recv() {
-                    Ok(val) => {
-                        if val == ManagedThreadState::STOPPED {
-                            let _ = ctl_pair.tx().send(ManagedThreadState::OK);
-                            break;
-                        }
-                    }
-                    Err(_) => {
-                        warn!("Could not receive thread state!");
-                    }
-                };
+        if payload.len() != 7 {
+            trace!("lock_slot - Invalid Length");
+            return Response::new_error(MdError::InvalidLength)
+        }
+
+        let mode = match payload[0] {
+            0 => LockMode::Shared,
+            1 => LockMode::Exclusive,
+            _ => {
+                trace!("lock_slot - Invalid Parameter");
+                return Response::new_error(MdError::InvalidParameter)
+            }
+        };
+
+        let dev_adr = u16::from_be_bytes([payload[1], payload[2]]);
+        let timeout = u32::from_be_bytes([payload[3], payload[4], payload[5], payload[6]]);
+
+        match locks.acquire(nr, dev_adr, mode.clone(), Duration::from_millis(timeout as u64)) {
+            Ok(_) => {
+                debug!("Client {} locked slot {} ({:?}, {} ms)", nr, dev_adr, mode, timeout);
+                Response::new_empty_response()
             }
+            Err(err) => Response::new_error(err),
         }
-        debug!("Stopped {}",thread_name);
-        let _ = chn_result.send(UdsSessionResult::disconnected(nr));
     }
 
-    pub fn start(nr : u16, pair : ChannelPair<PMsg>, stream : UnixStream, chn_result: Sender<UdsSessionResult>, stats : SharedStats) -> UdsSessionHandler {
+    fn unlock_slot(locks : &SlotLocks, nr : u16, payload : &[u8]) -> Response {
 
+        if payload.len() != 2 {
+            trace!("unlock_slot - Invalid Length");
+            return Response::new_error(MdError::InvalidLength)
+        }
 
-        let func = UdsSessionHandler::task;
-        let handle = spawn(format!("client-{}",nr).to_string(),move |ctl_pair| func(ctl_pair, nr, pair, stream, chn_result, stats));
-        UdsSessionHandler {handle}
+        let dev_adr = u16::from_be_bytes([payload[0], payload[1]]);
+        locks.release(nr, dev_adr);
+        debug!("Client {} unlocked slot {}", nr, dev_adr);
+        Response::new_empty_response()
     }
 
-    pub fn stop(&self, dur : Duration) {
-        let _ = self.handle.stop(dur);
+    fn subscribe_events(&mut self, payload : &[u8]) -> Response {
+
+        if payload.len() != 1 {
+            trace!("subscribe_events - Invalid Length");
+            return Response::new_error(MdError::InvalidLength)
+        }
+        if payload[0] > 1 {
+            trace!("subscribe_events - Invalid Parameter");
+            return Response::new_error(MdError::InvalidParameter)
+        }
+
+        self.subscribed = payload[0] == 1;
+        debug!("Client {} {} events", self.id, if self.subscribed { "subscribed to" } else { "unsubscribed from" });
+        Response::new_empty_response()
+    }
+
+    fn is_permitted(policy : &AccessPolicy, peer : &PeerCredentials, stats : &Stats, command : &(Option<Command>,Option<Request>)) -> bool {
+        let class = match command {
+            (Some(Command::Device),Some(request)) => {
+                let module = stats.devices().iter().find(|device| device.adr() == request.get_dev_id());
+                policy.classify(module.map(|device| device.vendor_product_id().as_str()), request.get_payload())
+            },
+            (Some(Command::LockSlot),_) | (Some(Command::UnlockSlot),_) => return policy.may_control(peer),
+            _ => CommandClass::ReadOnly,
+        };
+
+        if policy.is_allowed(peer, &class) {
+            return true;
+        }
+        warn!("Denied {:?} command {:?} for pid {} (uid {}, gid {})", class, command.0, peer.pid, peer.uid, peer.gid);
+        false
+    }
+
+    /// Returns the response of a request or `None` if it was forwarded to a device
+    fn handle_request(&mut self, input : &[u8], stats : &Stats, dispatcher : &Sender<PMsg>) -> Option<Response> {
+
+        let nr = self.id;
+        let SessionAccess { locks, peer, policy } = &self.access;
+
+        let command = ModApi::parse(input);
+        trace!("Received Command: {:?}",command.0);
+        let permitted = UdsSessionHandler::is_permitted(policy, peer, stats, &command);
+
+        let response = match command {
+            _ if !permitted => Response::new_error(MdError::PermissionDenied),
+            (Some(Command::Info),Some(_request)) => ModApi::info(stats.get_version(), stats.get_sdbpk_version()),
+            (Some(Command::GetDeviceList),Some(request))  => ModApi::get_device_list(stats.devices(),request.get_payload()),
+            (Some(Command::GetDescriptor),Some(request))  => ModApi::get_descriptor(stats.devices(),request.get_payload()),
+            (Some(Command::SubscribeEvents),Some(request))  => return Some(self.subscribe_events(request.get_payload())),
+            (Some(Command::LockSlot),Some(request))  => UdsSessionHandler::lock_slot(locks, nr, request.get_payload()),
+            (Some(Command::UnlockSlot),Some(request))  => UdsSessionHandler::unlock_slot(locks, nr, request.get_payload()),
+            (Some(Command::Device),Some(request)) if SlotLocks::is_mutating(request.get_payload()) && !locks.is_permitted(nr, request.get_dev_id()) => {
+                debug!("Client {} rejected, slot {} is locked", nr, request.get_dev_id());
+                Response::new_error(MdError::SlotLocked)
+            },
+            (Some(Command::Device),Some(request))  => {
+                let seq = self.next_seq;
+                self.next_seq = self.next_seq.wrapping_add(1);
+                if dispatcher.send(PMsg::create(nr, request.get_dev_id(),Ok(request.get_payload().to_vec())).with_seq(seq)).is_err() {
+                    return Some(Response::new_error(MdError::DeviceNotConnected));
+                }
+                self.pending = Some(PendingRequest { dev_adr : request.get_dev_id(), seq, deadline : Instant::now() + DEVICE_TIMEOUT });
+                return None;
+            },
+            _ =>  Response::new_error(MdError::UnknownCommand)
+        };
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatypes::Version;
+
+    fn session() -> (UdsSessionHandler, UnixStream) {
+        let (stream, peer) = UnixStream::pair().unwrap();
+        let credentials = PeerCredentials { pid : 1, uid : 0, gid : 0, groups : vec![0] };
+        let access = SessionAccess { locks : SlotLocks::new(), peer : credentials, policy : AccessPolicy::allow_all() };
+        (UdsSessionHandler::new(0x1000, stream, access), peer)
+    }
+
+    #[test]
+    fn sessions_exceeding_the_limits_fail() {
+        let (mut sender, mut peer) = session();
+        let request = Request::new_without_payload(0x0000, Command::Info as u16);
+        let mut frame = (request.to_bytes().len() as u32).to_ne_bytes().to_vec();
+        frame.extend_from_slice(request.to_bytes());
+        for _ in 0..MAX_QUEUED_REQUESTS {
+            peer.write_all(&frame).unwrap();
+        }
+        sender.on_readable().unwrap();
+        assert_eq!(sender.requests.len(), MAX_QUEUED_REQUESTS);
+        peer.write_all(&frame).unwrap();
+        assert_eq!(sender.on_readable().unwrap_err().kind(), ErrorKind::OutOfMemory);
+
+        let (mut reader, _peer) = session();
+        reader.subscribed = true;
+        let event = ModEvent::device_list_changed(1);
+        while !reader.overflowed {
+            reader.push_event(&event);
+        }
+        assert!(reader.tx_buffer.len() <= MAX_TX_BUFFER);
+        assert_eq!(reader.on_writable().unwrap_err().kind(), ErrorKind::OutOfMemory);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn late_responses_of_timed_out_requests_are_dropped() {
+        let (mut session, _peer) = session();
+        let stats = Stats::new("test".to_string(), Version::new(1, 0, 0), Version::new(1, 0, 0));
+        let (dispatcher, forwarded) = crossbeam_channel::unbounded();
+        let request = Request::new_from_bytes(0x0001, Command::Device as u16, &[0x01, 0x02, 0x03]);
+        session.requests.push_back(request.to_bytes().to_vec());
+        session.requests.push_back(request.to_bytes().to_vec());
+
+        session.process(&stats, &dispatcher);
+        let first = forwarded.try_recv().unwrap();
+        session.check_timeout(Instant::now() + DEVICE_TIMEOUT);
+        session.process(&stats, &dispatcher);
+        let second = forwarded.try_recv().unwrap();
+        assert_ne!(first.get_seq(), second.get_seq());
+
+        let queued = session.tx_buffer.len();
+        session.on_device_response(first.reply(Ok(vec![0x01])));
+        assert_eq!(session.tx_buffer.len(), queued);
+        assert!(session.pending.is_some());
+        session.on_device_response(second.reply(Ok(vec![0x02])));
+        assert!(session.pending.is_none());
+        assert!(session.tx_buffer.len() > queued);
+    }
+
+    #[test]
+    fn frames_are_split_from_partial_reads() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&3u32.to_ne_bytes());
+        buffer.extend_from_slice(&[1, 2]);
+        assert!(UdsSessionHandler::take_frame(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(&[3]);
+        buffer.extend_from_slice(&0u32.to_ne_bytes());
+        assert_eq!(UdsSessionHandler::take_frame(&mut buffer).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(UdsSessionHandler::take_frame(&mut buffer).unwrap(), Some(vec![]));
+        assert!(buffer.is_empty());
+
+        buffer.extend_from_slice(&5000u32.to_ne_bytes());
+        assert!(UdsSessionHandler::take_frame(&mut buffer).is_err());
+    }
+}
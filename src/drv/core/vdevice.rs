@@ -1,23 +1,162 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use crate::util::*;
-use crate::drv::core::{PMsg, SharedStats};
+use crate::datatypes::{AdvancedVersion, Descriptor};
+use crate::drv::core::{ComHandler, PMsg, SharedStats};
+use crate::sdbp::request::core::protocol;
 
+const VIRTUAL_DEVICE_MASK : u16 = 0x2000;
+/// Operation code used by every class to report errors
+const ERROR : u8 = 0x01;
+const COMMAND_INVALID : u8 = 0x01;
+const WRONG_LENGTH : u8 = 0x02;
 
-pub type FuncVirtualDeviceHandler = fn (u16, ChannelPair<ManagedThreadState>,ChannelPair<PMsg>,SharedStats);
+pub type FuncFrameCallback = Box<dyn FnMut(&[u8]) -> Vec<u8> + Send>;
 
+/// Maps SDBP request frames of a virtual device to callbacks.
+///
+/// Callbacks are keyed by class id, class and operation code and get the complete request frame.
+/// Unknown requests are passed to the `otherwise` callback or answered with the `ERROR` operation of the requested class.
+#[derive(Default)]
+pub struct FrameHandler {
+    routes : HashMap<(u8,u8,u8),FuncFrameCallback>,
+    fallback : Option<FuncFrameCallback>,
+}
+
+impl FrameHandler {
+
+    pub fn new() -> FrameHandler {
+        FrameHandler { routes : HashMap::new(), fallback : None }
+    }
+
+    pub fn on<F>(mut self, class_id : u8, class : u8, operation_code : u8, callback : F) -> FrameHandler
+        where F : FnMut(&[u8]) -> Vec<u8> + Send + 'static {
+        self.routes.insert((class_id, class, operation_code), Box::new(callback));
+        self
+    }
+
+    /// Sets the callback for all requests without a route
+    pub fn otherwise<F>(mut self, callback : F) -> FrameHandler
+        where F : FnMut(&[u8]) -> Vec<u8> + Send + 'static {
+        self.fallback = Some(Box::new(callback));
+        self
+    }
+
+    /// `COMMAND_INVALID` error frame of the class of `raw`, which has to hold at least the class id and class
+    pub fn invalid_command(raw : &[u8]) -> Vec<u8> {
+        vec![raw[0], raw[1], ERROR, COMMAND_INVALID]
+    }
+
+    /// Returns the response frame for `raw`
+    pub fn handle(&mut self, raw : &[u8]) -> Vec<u8> {
+        if raw.len() < 3 {
+            return vec![protocol::CLASS_ID, protocol::classes::transaction_error::ID, WRONG_LENGTH];
+        }
+
+        match (self.routes.get_mut(&(raw[0], raw[1], raw[2])), &mut self.fallback) {
+            (Some(callback), _) => callback(raw),
+            (None, Some(fallback)) => fallback(raw),
+            (None, None) => FrameHandler::invalid_command(raw),
+        }
+    }
+}
+
+/// Thread serving the requests of a virtual device, see `Controller::start_virtual_device`
 pub struct VirtualDeviceThread {
+    id : u16,
     handle : ManagedThreadHandle<()>,
+    com : ComHandler,
+    shared : SharedStats,
 }
 
 impl VirtualDeviceThread{
 
-    pub fn start(name:String,id : u16,dev_chn: ChannelPair<PMsg>,func : FuncVirtualDeviceHandler, shared : SharedStats ) -> VirtualDeviceThread{
+    /// Creates the descriptor of a software device, the address is moved into the virtual device range.
+    ///
+    /// Names are sent as TLV strings and need at least two characters.
+    pub fn descriptor(id : u16, product_name : &str, vendor_name : &str) -> Descriptor {
+        let id = id | VIRTUAL_DEVICE_MASK;
+        let mut desc = Descriptor::new(format!("virtual/slot{}", id).into());
+        desc.set_adr(id);
+        desc.set_product_name(product_name.to_string());
+        desc.set_vendor_name(vendor_name.to_string());
+        desc.set_vendor_product_id("virtual".to_string());
+        desc.set_serial(format!("virtual-{}", id & !VIRTUAL_DEVICE_MASK));
+        desc.set_fw_version(AdvancedVersion::new('S', 1, 0, 0));
+        desc.set_bootloader_state("not supported".to_string());
+        desc.set_max_frame_size(4096);
+        desc.set_device_session(format!("VIRTUAL{:04X}", id));
+        desc
+    }
+
+    fn task(id : u16, ctl_pair : ChannelPair<ManagedThreadState>, dev_chn : ChannelPair<PMsg>, mut handler : FrameHandler) {
+        let mut stopped = false;
+        debug!("Started virtual device {}", id);
 
-        let handle = spawn(name,move |ctl_chn| func(id,ctl_chn,dev_chn, shared));
-        VirtualDeviceThread { handle }
+        while !stopped {
+            if let Ok(msg) = dev_chn.rx().recv_timeout(Duration::from_millis(100)) {
+                let response = match msg.get_msg() {
+                    Some(raw) => Ok(handler.handle(raw.as_slice())),
+                    None => Err(std::io::Error::from(std::io::ErrorKind::InvalidData)),
+                };
+                if dev_chn.tx().send(msg.reply(response)).is_err() {
+                    trace!("Virtual device {} could not answer client {}", id, msg.get_src());
+                }
+            }
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+        }
+        debug!("Stopped virtual device {}", id);
     }
 
+    pub fn start(desc : Descriptor, com : ComHandler, shared : SharedStats, handler : FrameHandler) -> VirtualDeviceThread {
+
+        let id = desc.adr();
+        let dev_chn = com.register_new_device(id);
+
+        shared.update(|stats| {
+            stats.get_devices().retain(|device| device.adr() != id);
+            stats.get_devices().push(desc);
+        });
+
+        let handle = spawn(format!("vdev-{}", id), move |ctl_chn| VirtualDeviceThread::task(id, ctl_chn, dev_chn, handler));
+        VirtualDeviceThread { id, handle, com, shared }
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Stops the thread and removes the device from the device list
     pub fn stop(&self,timout: Duration){
         let _  = self.handle.stop(timout);
+        self.com.unregister_device(self.id);
+
+        self.shared.update(|stats| stats.get_devices().retain(|device| device.adr() != self.id));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_handler_routes_by_operation() {
+        let mut handler = FrameHandler::new()
+            .on(0x03, 0x02, 0x05, |raw| vec![raw[0], raw[1], raw[2], 0x2A]);
+
+        assert_eq!(handler.handle(&[0x03, 0x02, 0x05, 0x00]), vec![0x03, 0x02, 0x05, 0x2A]);
+        assert_eq!(handler.handle(&[0x03, 0x02, 0x06]), vec![0x03, 0x02, ERROR, COMMAND_INVALID]);
+        assert_eq!(handler.handle(&[0x03]), vec![0x01, 0x01, WRONG_LENGTH]);
+
+        let mut handler = handler.otherwise(|raw| raw.to_vec());
+        assert_eq!(handler.handle(&[0x03, 0x02, 0x06]), vec![0x03, 0x02, 0x06]);
+    }
+
+    #[test]
+    fn virtual_descriptor_uses_virtual_range() {
+        let desc = VirtualDeviceThread::descriptor(3, "All Fans", "Noreya");
+        assert_eq!(desc.adr(), 0x2003);
+        assert!(PMsg::is_virtual_device(desc.adr()));
+        assert!(PMsg::is_device(desc.adr()));
+    }
+}
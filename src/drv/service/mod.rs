@@ -1,2 +1 @@
-mod notification_handler;
 pub mod service;
\ No newline at end of file
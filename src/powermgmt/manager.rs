@@ -45,11 +45,11 @@ pub struct PowerFinishRequest {
 }
 
 impl PowerFinishRequest {
-    fn new() -> PowerFinishRequest {
+    fn new(action_done : bool) -> PowerFinishRequest {
         PowerFinishRequest {
             protocol_version : "1.0.0".to_string(),
             msg_type : "request".to_string(),
-            action_done : true,
+            action_done,
         }
     }
 }
@@ -111,7 +111,16 @@ impl PowerManager {
 
 
     pub fn finish_request(&mut self)  -> Result<PowerFinishResponse,String> {
-        let tmp = PowerFinishRequest::new();
+        self.send_finish(true)
+    }
+
+    /// Tells the power management that the requested action was not done, the budget is released
+    pub fn cancel_request(&mut self)  -> Result<PowerFinishResponse,String> {
+        self.send_finish(false)
+    }
+
+    fn send_finish(&mut self, action_done : bool)  -> Result<PowerFinishResponse,String> {
+        let tmp = PowerFinishRequest::new(action_done);
         let string = serde_json::to_string(&tmp).expect("Could not convert struct to string");
 
         match self.com.write_msg(string.as_bytes()) {
@@ -0,0 +1,236 @@
+//! Calibration and scaling of IO module analog inputs
+//!
+//! A `ModuleCalibration` holds a `PinCalibration` per pin: an optional correction of the raw
+//! value, a linear mapping to engineering units and clamping. Calibrations are stored per
+//! module serial number by the `CalibrationStore`, so they follow a module into another slot.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::datatypes::Descriptor;
+use crate::sdbp::response::custom::io::input::{PinValue, PinValues};
+
+/// Correction of the raw value, in the unit the module reports (mV or mA)
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Correction {
+    /// `raw` values were measured while `actual` was applied, values in between are interpolated
+    TwoPoint { raw : [f64; 2], actual : [f64; 2] },
+    /// `coefficients[0] + coefficients[1] * x + coefficients[2] * x² + ...`
+    Polynomial { coefficients : Vec<f64> },
+}
+
+impl Correction {
+
+    pub fn apply(&self, raw : f64) -> f64 {
+        match self {
+            Correction::TwoPoint { raw : [raw0, raw1], actual : [actual0, actual1] } => {
+                actual0 + (raw - raw0) * (actual1 - actual0) / (raw1 - raw0)
+            }
+            Correction::Polynomial { coefficients } => coefficients.iter().rev().fold(0.0, |sum, coefficient| sum * raw + coefficient),
+        }
+    }
+}
+
+/// Linear mapping of the corrected value to engineering units, e.g. 4-20 mA to 0-10 bar
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Scaling {
+    pub input : [f64; 2],
+    pub output : [f64; 2],
+    pub unit : String,
+}
+
+#[derive(Debug,Clone,Default,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PinCalibration {
+    #[serde(default)]
+    pub correction : Option<Correction>,
+    #[serde(default)]
+    pub scaling : Option<Scaling>,
+    /// `[min, max]` of the result
+    #[serde(default)]
+    pub clamp : Option<[f64; 2]>,
+}
+
+/// Calibrated value of an analog pin
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CalibratedValue {
+    pub pin : u8,
+    pub raw : PinValue,
+    pub value : f64,
+    pub unit : String,
+    /// The value was outside the clamp range
+    pub clamped : bool,
+}
+
+impl PinCalibration {
+
+    pub fn validate(&self) -> Result<(),Error> {
+        let invalid = |msg : &str| Err(Error::new(ErrorKind::InvalidInput, msg.to_string()));
+        match &self.correction {
+            Some(Correction::TwoPoint { raw, actual }) if raw[0] == raw[1] || !raw.iter().chain(actual).all(|value| value.is_finite()) => {
+                return invalid("Two point correction needs two different finite raw values");
+            }
+            Some(Correction::Polynomial { coefficients }) if coefficients.is_empty() || !coefficients.iter().all(|value| value.is_finite()) => {
+                return invalid("Polynomial correction needs finite coefficients");
+            }
+            _ => (),
+        }
+        if let Some(scaling) = &self.scaling {
+            if scaling.input[0] == scaling.input[1] || !scaling.input.iter().chain(&scaling.output).all(|value| value.is_finite()) {
+                return invalid("Scaling needs two different finite input values");
+            }
+        }
+        if let Some([min, max]) = self.clamp {
+            if min.is_nan() || max.is_nan() || min > max {
+                return invalid("Clamp minimum above maximum");
+            }
+        }
+        Ok(())
+    }
+
+    /// Corrects, scales and clamps `raw`, returns the value and whether it was clamped
+    pub fn apply(&self, raw : f64) -> (f64, bool) {
+        let corrected = self.correction.as_ref().map(|correction| correction.apply(raw)).unwrap_or(raw);
+        let scaled = match &self.scaling {
+            Some(Scaling { input : [in0, in1], output : [out0, out1], .. }) => out0 + (corrected - in0) * (out1 - out0) / (in1 - in0),
+            None => corrected,
+        };
+        match self.clamp {
+            Some([min, _]) if scaled < min => (min, true),
+            Some([_, max]) if scaled > max => (max, true),
+            _ => (scaled, false),
+        }
+    }
+}
+
+/// Calibration of the pins of a single module, identified by its serial number
+#[derive(Debug,Clone,Default,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ModuleCalibration {
+    pub serial : String,
+    #[serde(default)]
+    pub pins : BTreeMap<u8,PinCalibration>,
+}
+
+impl ModuleCalibration {
+
+    pub fn new(serial : &str) -> ModuleCalibration {
+        ModuleCalibration { serial : serial.to_string(), pins : BTreeMap::new() }
+    }
+
+    pub fn pin(mut self, pin : u8, calibration : PinCalibration) -> ModuleCalibration {
+        self.pins.insert(pin, calibration);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(),Error> {
+        for (pin, calibration) in &self.pins {
+            calibration.validate().map_err(|err| Error::new(err.kind(), format!("Pin {}: {}", pin, err)))?;
+        }
+        Ok(())
+    }
+
+    /// Calibrates a voltage or current value, `None` for other pin types
+    ///
+    /// Pins without calibration report the raw value in `mV` or `mA`.
+    pub fn apply(&self, values : &PinValues) -> Option<CalibratedValue> {
+        let (raw, unit) = match values.typed()? {
+            value @ PinValue::Voltage(_) => (value, "mV"),
+            value @ PinValue::Current(_) => (value, "mA"),
+            _ => return None,
+        };
+        let calibration = self.pins.get(&values.pin);
+        let (value, clamped) = calibration.map(|calibration| calibration.apply(raw.as_f64())).unwrap_or((raw.as_f64(), false));
+        let unit = calibration.and_then(|calibration| calibration.scaling.as_ref()).map(|scaling| scaling.unit.as_str()).unwrap_or(unit);
+        Some(CalibratedValue { pin : values.pin, raw, value, unit : unit.to_string(), clamped })
+    }
+}
+
+/// Directory with one `<hex encoded serial>.json` file per calibrated module
+pub struct CalibrationStore {
+    dir : PathBuf,
+}
+
+impl CalibrationStore {
+
+    pub fn new(dir : &Path) -> CalibrationStore {
+        CalibrationStore { dir : dir.to_path_buf() }
+    }
+
+    /// Serials may hold any character, the hex encoding keeps the file names distinct and valid
+    fn path(&self, serial : &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hex::encode(serial)))
+    }
+
+    /// Calibration of the module with `serial`, `None` if it was never calibrated
+    pub fn load(&self, serial : &str) -> Result<Option<ModuleCalibration>,Error> {
+        let path = self.path(serial);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let calibration : ModuleCalibration = serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Invalid calibration {:?}: {}", path, err)))?;
+        calibration.validate()?;
+        if calibration.serial != serial {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Calibration {:?} belongs to serial {}", path, calibration.serial)));
+        }
+        Ok(Some(calibration))
+    }
+
+    /// Calibration of the module in a slot, found by the serial number of its descriptor
+    pub fn for_device(&self, desc : &Descriptor) -> Result<Option<ModuleCalibration>,Error> {
+        self.load(desc.serial())
+    }
+
+    pub fn save(&self, calibration : &ModuleCalibration) -> Result<(),Error> {
+        calibration.validate()?;
+        fs::create_dir_all(&self.dir)?;
+        let content = serde_json::to_vec_pretty(calibration).map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        let path = self.path(&calibration.serial);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current(pin : u8, ma : u16) -> PinValues {
+        let mut values = PinValues::new_empty();
+        values.set_id(pin);
+        values.set_current(&ma.to_be_bytes());
+        values
+    }
+
+    #[test]
+    fn pressure_sensor_is_scaled_and_follows_the_serial() {
+        let pressure = PinCalibration {
+            correction : Some(Correction::TwoPoint { raw : [5.0, 21.0], actual : [4.0, 20.0] }),
+            scaling : Some(Scaling { input : [4.0, 20.0], output : [0.0, 10.0], unit : "bar".to_string() }),
+            clamp : Some([0.0, 10.0]),
+        };
+        let calibration = ModuleCalibration::new("SN/0001").pin(2, pressure);
+
+        let value = calibration.apply(&current(2, 13)).unwrap();
+        assert_eq!((value.value, value.unit.as_str(), value.clamped), (5.0, "bar", false));
+        let value = calibration.apply(&current(2, 2)).unwrap();
+        assert_eq!((value.value, value.clamped), (0.0, true));
+        assert_eq!(calibration.apply(&current(1, 7)).unwrap().unit, "mA");
+
+        let polynomial = Correction::Polynomial { coefficients : vec![1.0, 2.0, 0.5] };
+        assert_eq!(polynomial.apply(2.0), 7.0);
+        assert!(PinCalibration { clamp : Some([1.0, 0.0]), ..PinCalibration::default() }.validate().is_err());
+
+        let dir = std::env::temp_dir().join(format!("sdbp_calibration_{}", std::process::id()));
+        let store = CalibrationStore::new(&dir);
+        store.save(&calibration).unwrap();
+        store.save(&ModuleCalibration::new("SN_0001")).unwrap();
+        assert_eq!(store.load("SN/0001").unwrap(), Some(calibration));
+        assert_eq!(store.load("SN0002").unwrap(), None);
+        assert_eq!(store.load("SN_0001").unwrap(), Some(ModuleCalibration::new("SN_0001")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,606 @@
+use std::fmt;
+
+use crate::sdbp::request::core::protocol as core;
+#[cfg(feature = "io")]
+use crate::sdbp::request::custom::io::protocol as io;
+#[cfg(feature = "power")]
+use crate::sdbp::request::custom::power::protocol as power;
+#[cfg(feature = "bmc")]
+use crate::sdbp::request::custom::bmc::protocol as bmc;
+
+/// Direction of a dissected frame
+#[derive(Debug,Clone,Copy,PartialEq,serde::Serialize,serde::Deserialize)]
+pub enum FrameDirection {
+    Request,
+    Response,
+}
+
+/// Protocol of the custom classes (class id `0x03`), which can not be told apart by the frame itself
+#[derive(Debug,Clone,Copy,PartialEq,Default,serde::Serialize,serde::Deserialize)]
+pub enum ModuleProtocol {
+    /// Only the core classes are decoded
+    #[default]
+    Generic,
+    Io,
+    Power,
+    Bmc,
+}
+
+#[derive(Debug,Clone,PartialEq,serde::Serialize,serde::Deserialize)]
+pub struct FrameField {
+    pub name : String,
+    pub value : String,
+}
+
+#[derive(Debug,Clone,PartialEq,serde::Serialize,serde::Deserialize)]
+pub struct FrameStatus {
+    pub code : u8,
+    pub meaning : String,
+}
+
+/// Human readable decode of a SDBP frame, see `Dissector`
+#[derive(Debug,Clone,PartialEq,serde::Serialize,serde::Deserialize)]
+pub struct DissectedFrame {
+    pub direction : FrameDirection,
+    pub class_id : Option<u8>,
+    pub class : Option<u8>,
+    pub operation_code : Option<u8>,
+    pub protocol_name : String,
+    pub class_name : String,
+    pub operation_name : String,
+    pub status : Option<FrameStatus>,
+    pub fields : Vec<FrameField>,
+    /// Problems found while decoding, e.g. truncated frames or unknown codes
+    pub notes : Vec<String>,
+}
+
+impl DissectedFrame {
+
+    fn field(&mut self, name : &str, value : String) {
+        self.fields.push(FrameField { name : name.to_string(), value });
+    }
+}
+
+impl fmt::Display for DissectedFrame {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            FrameDirection::Request => "req",
+            FrameDirection::Response => "rsp",
+        };
+        write!(fmt, "{} {}.{}.{}", direction, self.protocol_name, self.class_name, self.operation_name)?;
+
+        if let Some(status) = &self.status {
+            write!(fmt, " status=0x{:02x} ({})", status.code, status.meaning)?;
+        }
+        for field in &self.fields {
+            write!(fmt, " {}={}", field.name, field.value)?;
+        }
+        for note in &self.notes {
+            write!(fmt, " [{}]", note)?;
+        }
+        Ok(())
+    }
+}
+
+fn unknown(code : u8) -> String {
+    format!("UNKNOWN(0x{:02x})", code)
+}
+
+fn hex(data : &[u8]) -> String {
+    data.iter().map(|value| format!("{:02x}", value)).collect::<Vec<String>>().join(" ")
+}
+
+/// Printable strings are shown as text, everything else as hex
+fn text_or_hex(data : &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.is_empty() && text.chars().all(|value| !value.is_control()) => format!("\"{}\"", text),
+        _ => hex(data),
+    }
+}
+
+fn be_value(data : &[u8]) -> Option<u32> {
+    match data.len() {
+        1 => Some(data[0] as u32),
+        2 => Some(u16::from_be_bytes([data[0], data[1]]) as u32),
+        4 => Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+        _ => None,
+    }
+}
+
+/// Decodes SDBP request and response frames for logs and trace tooling.
+///
+/// Names are taken from the `protocol` modules of the core and the enabled custom protocols,
+/// unknown classes, operations and codes are reported instead of failing.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct Dissector {
+    module : ModuleProtocol,
+}
+
+impl Dissector {
+
+    pub fn new(module : ModuleProtocol) -> Dissector {
+        Dissector { module }
+    }
+
+    pub fn request(&self, raw : &[u8]) -> DissectedFrame {
+        self.dissect(FrameDirection::Request, raw)
+    }
+
+    pub fn response(&self, raw : &[u8]) -> DissectedFrame {
+        self.dissect(FrameDirection::Response, raw)
+    }
+
+    pub fn dissect(&self, direction : FrameDirection, raw : &[u8]) -> DissectedFrame {
+        let mut frame = DissectedFrame {
+            direction,
+            class_id : raw.first().copied(),
+            class : raw.get(1).copied(),
+            operation_code : raw.get(2).copied(),
+            protocol_name : raw.first().map(|value| unknown(*value)).unwrap_or_default(),
+            class_name : String::new(),
+            operation_name : String::new(),
+            status : None,
+            fields : Vec::new(),
+            notes : Vec::new(),
+        };
+
+        let (class_id, class) = match (frame.class_id, frame.class) {
+            (Some(class_id), Some(class)) => (class_id, class),
+            _ => {
+                frame.notes.push(format!("truncated frame ({} bytes)", raw.len()));
+                if !raw.is_empty() {
+                    frame.field("data", hex(raw));
+                }
+                return frame;
+            }
+        };
+
+        frame.protocol_name = self.protocol_name(class_id).map(|name| name.to_string()).unwrap_or_else(|| unknown(class_id));
+        frame.class_name = self.class_name(class_id, class).map(|name| name.to_string()).unwrap_or_else(|| unknown(class));
+
+        // Transaction errors carry the return code in place of the operation code
+        if class_id == core::CLASS_ID && class == core::classes::transaction_error::ID {
+            frame.operation_name = "ERROR".to_string();
+            if let Some(code) = frame.operation_code {
+                frame.status = Some(FrameStatus { code, meaning : Dissector::core_error(class, code) });
+            }
+            if raw.len() > 3 {
+                frame.field("data", hex(&raw[3..]));
+            }
+            return frame;
+        }
+
+        let operation_code = match frame.operation_code {
+            Some(value) => value,
+            None => {
+                frame.notes.push("truncated frame, operation code missing".to_string());
+                return frame;
+            }
+        };
+
+        frame.operation_name = match self.operation_name(class_id, class, operation_code) {
+            Some(name) => name.to_string(),
+            None => {
+                frame.notes.push("unknown operation".to_string());
+                unknown(operation_code)
+            }
+        };
+
+        let mut payload = &raw[3..];
+        if direction == FrameDirection::Response && operation_code == 0x01 {
+            match payload.first() {
+                Some(code) => {
+                    frame.status = Some(FrameStatus { code : *code, meaning : self.error_meaning(class_id, class, *code) });
+                    payload = &payload[1..];
+                },
+                None => frame.notes.push("error code missing".to_string()),
+            }
+        } else if direction == FrameDirection::Response && self.has_status(class_id, class, operation_code) {
+            match payload.first() {
+                Some(code) => {
+                    frame.status = Some(FrameStatus { code : *code, meaning : self.status_meaning(class_id, class, *code) });
+                    payload = &payload[1..];
+                },
+                None => frame.notes.push("status missing".to_string()),
+            }
+        }
+
+        self.decode_fields(&mut frame, class_id, class, operation_code, payload);
+        frame
+    }
+
+    fn protocol_name(&self, class_id : u8) -> Option<&'static str> {
+        match class_id {
+            core::CLASS_ID => Some("core"),
+            0x03 => match self.module {
+                ModuleProtocol::Generic => Some("custom"),
+                ModuleProtocol::Io => Some("io"),
+                ModuleProtocol::Power => Some("power"),
+                ModuleProtocol::Bmc => Some("bmc"),
+            },
+            _ => None,
+        }
+    }
+
+    fn class_name(&self, class_id : u8, class : u8) -> Option<&'static str> {
+        if class_id == core::CLASS_ID {
+            return match class {
+                core::classes::transaction_error::ID => Some("transaction_error"),
+                core::classes::descriptor::ID => Some("descriptor"),
+                core::classes::control::ID => Some("control"),
+                core::classes::dummy::ID => Some("dummy"),
+                core::classes::wait::ID => Some("wait"),
+                core::classes::notification::ID => Some("notification"),
+                #[cfg(feature = "firmware-experimental")]
+                core::classes::bootloader::ID => Some("bootloader"),
+                _ => None,
+            };
+        }
+
+        match self.module {
+            #[cfg(feature = "io")]
+            ModuleProtocol::Io if class_id == io::CLASS_ID => match class {
+                io::classes::input_class::ID => Some("input"),
+                io::classes::output_class::ID => Some("output"),
+                io::classes::power_management_class::ID => Some("power_management"),
+                _ => None,
+            },
+            #[cfg(feature = "power")]
+            ModuleProtocol::Power if class_id == power::CLASS_ID => match class {
+                power::classes::power_class::ID => Some("power"),
+                power::classes::temperature_control_class::ID => Some("temperature_control"),
+                _ => None,
+            },
+            #[cfg(feature = "bmc")]
+            ModuleProtocol::Bmc if class_id == bmc::CLASS_ID => match class {
+                bmc::classes::input::ID => Some("input"),
+                bmc::classes::usbhub::ID => Some("usbhub"),
+                bmc::classes::watchdog::ID => Some("watchdog"),
+                bmc::classes::cmc::ID => Some("cmc"),
+                bmc::classes::buzzer::ID => Some("buzzer"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn operation_name(&self, class_id : u8, class : u8, operation_code : u8) -> Option<&'static str> {
+        self.class_name(class_id, class)?;
+        match operation_code {
+            0x00 => return Some("RFU"),
+            // Every class uses operation code 0x01 to report errors
+            0x01 => return Some("ERROR"),
+            _ => (),
+        }
+
+        if class_id == core::CLASS_ID {
+            use core::classes::*;
+            return match (class, operation_code) {
+                (descriptor::ID, descriptor::operation_code::VENDOR_PRODUCT_ID) => Some("VENDOR_PRODUCT_ID"),
+                (descriptor::ID, descriptor::operation_code::SERIAL_CODE) => Some("SERIAL_CODE"),
+                (descriptor::ID, descriptor::operation_code::FW_VERSION) => Some("FW_VERSION"),
+                (descriptor::ID, descriptor::operation_code::HW_VERSION) => Some("HW_VERSION"),
+                (descriptor::ID, descriptor::operation_code::MAX_SLCK_SPEED) => Some("MAX_SCLK_SPEED"),
+                (descriptor::ID, descriptor::operation_code::MAX_FRAME_SIZE) => Some("MAX_FRAME_SIZE"),
+                (descriptor::ID, descriptor::operation_code::PROTOCOL_VERSION) => Some("PROTOCOL_VERSION"),
+                (descriptor::ID, descriptor::operation_code::VENDOR_NAME) => Some("VENDOR_NAME"),
+                (descriptor::ID, descriptor::operation_code::PRODUCT_NAME) => Some("PRODUCT_NAME"),
+                (descriptor::ID, descriptor::operation_code::BOOTLOADER_STATE) => Some("BOOTLOADER_STATE"),
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_3V3) => Some("MAX_POWER_3V3"),
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_5V) => Some("MAX_POWER_5V"),
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_12V) => Some("MAX_POWER_12V"),
+                (control::ID, control::operation_code::MODE_SUSPEND) => Some("MODE_SUSPEND"),
+                (control::ID, control::operation_code::MODE_RUN) => Some("MODE_RUN"),
+                (control::ID, control::operation_code::MODE_BOOTLOADER) => Some("MODE_BOOTLOADER"),
+                (control::ID, control::operation_code::SYSTEM_RESET) => Some("SYSTEM_RESET"),
+                (control::ID, control::operation_code::FACTORY_RESET) => Some("FACTORY_RESET"),
+                (control::ID, control::operation_code::SET_FRAME_SIZE) => Some("SET_FRAME_SIZE"),
+                (control::ID, control::operation_code::SET_SCLK_SPEED) => Some("SET_SCLK_SPEED"),
+                (control::ID, control::operation_code::UPDATE_DESCRIPTOR) => Some("UPDATE_DESCRIPTOR"),
+                (dummy::ID, dummy::operation_code::DUMMY) => Some("DUMMY"),
+                (wait::ID, wait::operation_code::WAIT) => Some("WAIT"),
+                (notification::ID, notification::operation_code::GET_NOTIFICATION) => Some("GET_NOTIFICATION"),
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::GET_INFO) => Some("GET_INFO"),
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::ERASE) => Some("ERASE"),
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::WRITE) => Some("WRITE"),
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::VERIFY) => Some("VERIFY"),
+                _ => None,
+            };
+        }
+
+        match self.module {
+            #[cfg(feature = "io")]
+            ModuleProtocol::Io => {
+                use io::classes::*;
+                match (class, operation_code) {
+                    (input_class::ID, input_class::operation_code::SET_INPUT_MODE) => Some("SET_INPUT_MODE"),
+                    (input_class::ID, input_class::operation_code::SET_ANALOG_THRESHOLD) => Some("SET_ANALOG_THRESHOLD"),
+                    (input_class::ID, input_class::operation_code::SET_DIGITAL_INTERRUPT) => Some("SET_DIGITAL_INTERRUPT"),
+                    (input_class::ID, input_class::operation_code::SET_DIGITAL_COUNTER) => Some("SET_DIGITAL_COUNTER"),
+                    (input_class::ID, input_class::operation_code::GET_VALUES) => Some("GET_VALUES"),
+                    (input_class::ID, input_class::operation_code::GET_CURRENT_VALUES) => Some("GET_CURRENT_VALUES"),
+                    (output_class::ID, output_class::operation_code::SET_OUTPUT) => Some("SET_OUTPUT"),
+                    (power_management_class::ID, power_management_class::operation_code::SET_POWER_CONFIG) => Some("SET_POWER_CONFIG"),
+                    (power_management_class::ID, power_management_class::operation_code::TEST_POWER_CONFIG) => Some("TEST_POWER_CONFIG"),
+                    _ => None,
+                }
+            },
+            #[cfg(feature = "power")]
+            ModuleProtocol::Power => {
+                use power::classes::*;
+                match (class, operation_code) {
+                    (power_class::ID, power_class::operation_code::SOURCE) => Some("SOURCE"),
+                    (power_class::ID, power_class::operation_code::CURRENT_LIMIT) => Some("CURRENT_LIMIT"),
+                    (power_class::ID, power_class::operation_code::VOLTAGE_CURRENT_STATUS) => Some("VOLTAGE_CURRENT_STATUS"),
+                    (power_class::ID, power_class::operation_code::OPP_CNT_STATUS) => Some("OPP_CNT_STATUS"),
+                    (temperature_control_class::ID, temperature_control_class::operation_code::TEMPERATURE_SENSOR) => Some("TEMPERATURE_SENSOR"),
+                    (temperature_control_class::ID, temperature_control_class::operation_code::FAN_STATUS) => Some("FAN_STATUS"),
+                    (temperature_control_class::ID, temperature_control_class::operation_code::FAN_CONTROL) => Some("FAN_CONTROL"),
+                    (temperature_control_class::ID, temperature_control_class::operation_code::FAN_RPM) => Some("FAN_RPM"),
+                    (temperature_control_class::ID, temperature_control_class::operation_code::FAN_RPM_CONTROL) => Some("FAN_RPM_CONTROL"),
+                    _ => None,
+                }
+            },
+            #[cfg(feature = "bmc")]
+            ModuleProtocol::Bmc => {
+                use bmc::classes::*;
+                match (class, operation_code) {
+                    (input::ID, input::operation_code::GET_VOLTAGE) => Some("GET_VOLTAGE"),
+                    (buzzer::ID, buzzer::operation_code::MODE_BUZZER) => Some("MODE_BUZZER"),
+                    (watchdog::ID, watchdog::operation_code::ENABLE_TIMEOUT) => Some("ENABLE_TIMEOUT"),
+                    (watchdog::ID, watchdog::operation_code::DISABLE_TIMEOUT) => Some("DISABLE_TIMEOUT"),
+                    (watchdog::ID, watchdog::operation_code::GET_TIMEOUT) => Some("GET_TIMEOUT"),
+                    (watchdog::ID, watchdog::operation_code::GET_TIME_LEFT) => Some("GET_TIME_LEFT"),
+                    (watchdog::ID, watchdog::operation_code::ALIVE) => Some("ALIVE"),
+                    (watchdog::ID, watchdog::operation_code::SAVE_CONFIG) => Some("SAVE_CONFIG"),
+                    (watchdog::ID, watchdog::operation_code::SET_SHUTDOWN_TIMEOUT) => Some("SET_SHUTDOWN_TIMEOUT"),
+                    (watchdog::ID, watchdog::operation_code::GET_SHUTDOWN_TIMEOUT) => Some("GET_SHUTDOWN_TIMEOUT"),
+                    (watchdog::ID, watchdog::operation_code::SW_SHUTDOWN) => Some("SW_SHUTDOWN"),
+                    (watchdog::ID, watchdog::operation_code::EMERGENCY_MODE_STATE) => Some("EMERGENCY_MODE_STATE"),
+                    (cmc::ID, cmc::operation_code::CTL_USBBOOT) => Some("CTL_USBBOOT"),
+                    (usbhub::ID, usbhub::operation_code::GET_HUB_STATE) => Some("GET_HUB_STATE"),
+                    (usbhub::ID, usbhub::operation_code::SET_HUB_STATE) => Some("SET_HUB_STATE"),
+                    (usbhub::ID, usbhub::operation_code::GET_USB_SLOT_STATE) => Some("GET_USB_SLOT_STATE"),
+                    (usbhub::ID, usbhub::operation_code::SET_USB_SLOT_STATE) => Some("SET_USB_SLOT_STATE"),
+                    (usbhub::ID, usbhub::operation_code::HUB_RESET) => Some("HUB_RESET"),
+                    (usbhub::ID, usbhub::operation_code::GET_PORT_MAPPING) => Some("GET_PORT_MAPPING"),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Codes of the core classes, every class is decoded with its own return codes only.
+    ///
+    /// Transaction error codes have no specified meaning and stay unknown.
+    fn core_error(class : u8, code : u8) -> String {
+        use core::classes::*;
+        match (class, code) {
+            (notification::ID, notification::return_code::COMMAND_INVALID) => "command invalid".to_string(),
+            (notification::ID, notification::return_code::WRONG_LENGTH) => "wrong length".to_string(),
+            (notification::ID, notification::return_code::NO_NOTIFICATION_PENDING) => "no notification pending".to_string(),
+            #[cfg(feature = "firmware-experimental")]
+            (bootloader::ID, bootloader::return_code::COMMAND_INVALID) => "command invalid".to_string(),
+            #[cfg(feature = "firmware-experimental")]
+            (bootloader::ID, bootloader::return_code::WRONG_LENGTH) => "wrong length".to_string(),
+            #[cfg(feature = "firmware-experimental")]
+            (bootloader::ID, bootloader::return_code::INVALID_ADDRESS) => "invalid address".to_string(),
+            #[cfg(feature = "firmware-experimental")]
+            (bootloader::ID, bootloader::return_code::FLASH_ERROR) => "flash error".to_string(),
+            #[cfg(feature = "firmware-experimental")]
+            (bootloader::ID, bootloader::return_code::NOT_ERASED) => "not erased".to_string(),
+            _ => unknown(code),
+        }
+    }
+
+    /// Meaning of the code of an `ERROR` response
+    fn error_meaning(&self, class_id : u8, class : u8, code : u8) -> String {
+        if class_id != core::CLASS_ID {
+            return self.status_meaning(class_id, class, code);
+        }
+        Dissector::core_error(class, code)
+    }
+
+    /// Responses which start with a status byte
+    fn has_status(&self, class_id : u8, class : u8, _operation_code : u8) -> bool {
+        if class_id == core::CLASS_ID {
+            #[cfg(feature = "firmware-experimental")]
+            if class == core::classes::bootloader::ID {
+                return true;
+            }
+            return class == core::classes::control::ID;
+        }
+        match self.module {
+            ModuleProtocol::Io | ModuleProtocol::Bmc => self.class_name(class_id, class).is_some(),
+            _ => false,
+        }
+    }
+
+    #[cfg_attr(not(feature = "io"), allow(unused_variables))]
+    fn status_meaning(&self, class_id : u8, class : u8, code : u8) -> String {
+        if code == 0x00 {
+            return "success".to_string();
+        }
+        if class_id == core::CLASS_ID {
+            return Dissector::core_error(class, code);
+        }
+
+        let meaning : Option<&str> = match self.module {
+            #[cfg(feature = "io")]
+            ModuleProtocol::Io => {
+                use io::classes::*;
+                match (class, code) {
+                    (_, input_class::error_code::COMMAND_INVALID) => Some("command invalid"),
+                    (_, input_class::error_code::WRONG_LENGTH) => Some("wrong length"),
+                    (input_class::ID, input_class::error_code::INVALID_MODE) => Some("invalid mode"),
+                    (input_class::ID, input_class::error_code::PIN_OUT_OF_RANGE) => Some("pin out of range"),
+                    (input_class::ID, input_class::error_code::INVALID_DIRECTION) => Some("invalid direction"),
+                    (input_class::ID, input_class::error_code::INVALID_VALUE) => Some("invalid value"),
+                    (output_class::ID, output_class::error_code::INVALID_MODE) => Some("invalid mode"),
+                    (output_class::ID, output_class::error_code::PIN_OUT_OF_RANGE) => Some("pin out of range"),
+                    (output_class::ID, output_class::error_code::INVALID_VALUE) => Some("invalid value"),
+                    (output_class::ID, output_class::error_code::POWER_CONFIG_MISSING) => Some("power config missing"),
+                    (output_class::ID, output_class::error_code::EXTERNAL_VOLTAGE) => Some("external voltage"),
+                    (power_management_class::ID, power_management_class::error_code::INVALID_RAIL) => Some("invalid rail"),
+                    (power_management_class::ID, power_management_class::error_code::INVALID_VALUE) => Some("invalid value"),
+                    (power_management_class::ID, power_management_class::error_code::INVALID_MODE) => Some("invalid mode"),
+                    _ => None,
+                }
+            },
+            #[cfg(feature = "bmc")]
+            ModuleProtocol::Bmc => bmc::classes::return_code::ERR_LIST.iter().find(|error| error.0 == code).map(|error| error.1),
+            _ => None,
+        };
+        meaning.map(|value| value.to_string()).unwrap_or_else(|| unknown(code))
+    }
+
+    fn decode_fields(&self, frame : &mut DissectedFrame, class_id : u8, class : u8, operation_code : u8, payload : &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+
+        if class_id == core::CLASS_ID {
+            use core::classes::*;
+            let direction = frame.direction;
+            match (class, operation_code, direction) {
+                (control::ID, control::operation_code::SET_FRAME_SIZE, FrameDirection::Request) if payload.len() == 2 => {
+                    frame.field("frame_size", u16::from_be_bytes([payload[0], payload[1]]).to_string());
+                    return;
+                },
+                (control::ID, control::operation_code::SET_SCLK_SPEED, FrameDirection::Request) if payload.len() == 4 => {
+                    frame.field("sclk_khz", u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]).to_string());
+                    return;
+                },
+                (descriptor::ID, descriptor::operation_code::FW_VERSION, FrameDirection::Response) if payload.len() == 7 => {
+                    let stability = match payload[0] {
+                        1 => "A".to_string(),
+                        2 => "B".to_string(),
+                        3 => "S".to_string(),
+                        value => unknown(value),
+                    };
+                    frame.field("version", format!("{}.{}.{}.{}", stability,
+                        u16::from_be_bytes([payload[1], payload[2]]), u16::from_be_bytes([payload[3], payload[4]]), u16::from_be_bytes([payload[5], payload[6]])));
+                    return;
+                },
+                (descriptor::ID, descriptor::operation_code::VENDOR_PRODUCT_ID, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::SERIAL_CODE, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::VENDOR_NAME, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::PRODUCT_NAME, FrameDirection::Response) => {
+                    frame.field("value", text_or_hex(payload));
+                    return;
+                },
+                (descriptor::ID, descriptor::operation_code::MAX_SLCK_SPEED, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::MAX_FRAME_SIZE, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_3V3, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_5V, FrameDirection::Response) |
+                (descriptor::ID, descriptor::operation_code::MAX_POWER_12V, FrameDirection::Response) => {
+                    if let Some(value) = be_value(payload) {
+                        frame.field("value", value.to_string());
+                        return;
+                    }
+                },
+                (notification::ID, notification::operation_code::GET_NOTIFICATION, FrameDirection::Response) => {
+                    frame.field("notification", hex(payload));
+                    return;
+                },
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::ERASE, FrameDirection::Request) |
+                (bootloader::ID, bootloader::operation_code::VERIFY, FrameDirection::Request) if payload.len() == 8 => {
+                    frame.field("address", format!("0x{:08x}", be_value(&payload[..4]).unwrap_or(0)));
+                    frame.field("length", be_value(&payload[4..]).unwrap_or(0).to_string());
+                    return;
+                },
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::WRITE, FrameDirection::Request) if payload.len() > 4 => {
+                    frame.field("address", format!("0x{:08x}", be_value(&payload[..4]).unwrap_or(0)));
+                    frame.field("length", (payload.len() - 4).to_string());
+                    return;
+                },
+                #[cfg(feature = "firmware-experimental")]
+                (bootloader::ID, bootloader::operation_code::VERIFY, FrameDirection::Response) if payload.len() == 4 => {
+                    frame.field("crc", format!("0x{:08x}", be_value(payload).unwrap_or(0)));
+                    return;
+                },
+                _ => (),
+            }
+        }
+
+        #[cfg(feature = "bmc")]
+        if self.module == ModuleProtocol::Bmc && class_id == bmc::CLASS_ID && class == bmc::classes::watchdog::ID {
+            use bmc::classes::watchdog::operation_code::*;
+            let timeout = matches!((operation_code, frame.direction),
+                (ENABLE_TIMEOUT, FrameDirection::Request) | (SET_SHUTDOWN_TIMEOUT, FrameDirection::Request) |
+                (GET_TIMEOUT, FrameDirection::Response) | (GET_TIME_LEFT, FrameDirection::Response) | (GET_SHUTDOWN_TIMEOUT, FrameDirection::Response));
+            if timeout && payload.len() == 4 {
+                frame.field("timeout_ms", u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]).to_string());
+                return;
+            }
+        }
+
+        frame.field("data", hex(payload));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::CoreBuilder;
+
+    #[test]
+    fn dissect_core_frames() {
+        let dissector = Dissector::default();
+
+        let request = dissector.request(&CoreBuilder::new().control().set_sclk_speed(1000).unwrap());
+        assert_eq!(request.to_string(), "req core.control.SET_SCLK_SPEED sclk_khz=1000");
+
+        let response = dissector.response(&[0x01, 0x02, 0x04, 0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03]);
+        assert_eq!(response.to_string(), "rsp core.descriptor.FW_VERSION version=S.1.2.3");
+
+        let response = dissector.response(&[0x01, 0x06, 0x01, 0x03]);
+        assert_eq!(response.status, Some(FrameStatus { code : 0x03, meaning : "no notification pending".to_string() }));
+
+        let response = dissector.response(&[0x01, 0x03, 0x08, 0x02]);
+        // The control class has no return codes of its own, the notification codes do not apply
+        assert_eq!(response.to_string(), "rsp core.control.SET_SCLK_SPEED status=0x02 (UNKNOWN(0x02))");
+    }
+
+    #[cfg(feature = "firmware-experimental")]
+    #[test]
+    fn dissect_bootloader_frames() {
+        let dissector = Dissector::default();
+
+        let request = dissector.request(&CoreBuilder::new().bootloader().erase(0x0800_0000, 512).unwrap());
+        assert_eq!(request.to_string(), "req core.bootloader.ERASE address=0x08000000 length=512");
+
+        let response = dissector.response(&[0x01, 0x07, 0x04, 0x05]);
+        assert_eq!(response.to_string(), "rsp core.bootloader.WRITE status=0x05 (not erased)");
+    }
+
+    #[test]
+    fn dissect_unknown_and_short_frames() {
+        let dissector = Dissector::default();
+
+        let frame = dissector.response(&[0x07, 0x09, 0x42, 0xAA]);
+        assert_eq!(frame.to_string(), "rsp UNKNOWN(0x07).UNKNOWN(0x09).UNKNOWN(0x42) data=aa [unknown operation]");
+
+        let frame = dissector.request(&[0x01]);
+        assert_eq!(frame.notes, vec!["truncated frame (1 bytes)".to_string()]);
+        assert!(dissector.request(&[]).fields.is_empty());
+
+        let frame = dissector.response(&[0x01, 0x01, 0x02]);
+        assert_eq!(frame.to_string(), "rsp core.transaction_error.ERROR status=0x02 (UNKNOWN(0x02))");
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn dissect_io_status() {
+        let frame = Dissector::new(ModuleProtocol::Io).response(&[0x03, 0x02, 0x02, 0x06]);
+        assert_eq!(frame.to_string(), "rsp io.output.SET_OUTPUT status=0x06 (power config missing)");
+    }
+}
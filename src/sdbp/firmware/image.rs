@@ -0,0 +1,157 @@
+use std::path::Path;
+use crate::datatypes::{AdvancedVersion, Descriptor};
+use super::*;
+
+pub const IMAGE_MAGIC : &[u8; 6] = b"SDBPFW";
+pub const IMAGE_FORMAT_VERSION : u8 = 1;
+
+/// Firmware image of a module
+///
+/// Images start with a big-endian header:
+/// `[magic "SDBPFW"][u8 format][u8 id length][vendor product id][u8 stability][u16 major][u16 minor][u16 patch][u32 address][u32 length][u32 crc]`
+/// followed by `length` bytes which are written to the flash at `address`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct FirmwareImage {
+    vendor_product_id : String,
+    version : AdvancedVersion,
+    address : u32,
+    payload : Vec<u8>,
+    crc : u32,
+}
+
+impl FirmwareImage {
+
+    pub fn new(vendor_product_id : String, version : AdvancedVersion, address : u32, payload : Vec<u8>) -> FirmwareImage {
+        let crc = FIRMWARE_CRC.checksum(payload.as_slice());
+        FirmwareImage { vendor_product_id, version, address, payload, crc }
+    }
+
+    pub fn load(path : &Path) -> Result<FirmwareImage,FirmwareError> {
+        FirmwareImage::parse(std::fs::read(path)?.as_slice())
+    }
+
+    pub fn parse(raw : &[u8]) -> Result<FirmwareImage,FirmwareError> {
+        let invalid = |msg : &str| FirmwareError::InvalidImage(msg.to_string());
+
+        if raw.len() < IMAGE_MAGIC.len() + 2 || &raw[..IMAGE_MAGIC.len()] != IMAGE_MAGIC {
+            return Err(invalid("missing magic"));
+        }
+        let mut offset = IMAGE_MAGIC.len();
+        if raw[offset] != IMAGE_FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported format {}", raw[offset])));
+        }
+        let id_len = raw[offset + 1] as usize;
+        offset += 2;
+
+        const FIXED_LEN : usize = 7 + 4 + 4 + 4;
+        if id_len == 0 || raw.len() < offset + id_len + FIXED_LEN {
+            return Err(invalid("truncated header"));
+        }
+        let vendor_product_id = String::from_utf8(raw[offset..offset + id_len].to_vec()).map_err(|_| invalid("vendor product id is not utf-8"))?;
+        offset += id_len;
+
+        let stability = raw[offset] as char;
+        if !matches!(stability, 'A' | 'B' | 'S') {
+            return Err(invalid("invalid stability"));
+        }
+        let be_u16 = |index : usize| u16::from_be_bytes([raw[index], raw[index + 1]]);
+        let be_u32 = |index : usize| u32::from_be_bytes([raw[index], raw[index + 1], raw[index + 2], raw[index + 3]]);
+        let version = AdvancedVersion::new(stability, be_u16(offset + 1), be_u16(offset + 3), be_u16(offset + 5));
+        offset += 7;
+
+        let address = be_u32(offset);
+        let length = be_u32(offset + 4) as usize;
+        let crc = be_u32(offset + 8);
+        offset += 12;
+
+        if raw.len() - offset != length {
+            return Err(invalid(&format!("payload has {} bytes, header announces {}", raw.len() - offset, length)));
+        }
+        if length == 0 {
+            return Err(invalid("empty payload"));
+        }
+
+        let image = FirmwareImage::new(vendor_product_id, version, address, raw[offset..].to_vec());
+        if image.crc != crc {
+            return Err(FirmwareError::CrcMismatch { expected : crc, found : image.crc });
+        }
+        Ok(image)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(IMAGE_MAGIC.len() + 2 + self.vendor_product_id.len() + 19 + self.payload.len());
+        raw.extend_from_slice(IMAGE_MAGIC);
+        raw.push(IMAGE_FORMAT_VERSION);
+        raw.push(self.vendor_product_id.len() as u8);
+        raw.extend_from_slice(self.vendor_product_id.as_bytes());
+        raw.push(self.version.stability() as u8);
+        raw.extend_from_slice(&self.version.version().major().to_be_bytes());
+        raw.extend_from_slice(&self.version.version().minor().to_be_bytes());
+        raw.extend_from_slice(&self.version.version().patch().to_be_bytes());
+        raw.extend_from_slice(&self.address.to_be_bytes());
+        raw.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        raw.extend_from_slice(&self.crc.to_be_bytes());
+        raw.extend_from_slice(self.payload.as_slice());
+        raw
+    }
+
+    pub fn vendor_product_id(&self) -> &String { &self.vendor_product_id }
+    pub fn version(&self) -> &AdvancedVersion { &self.version }
+    pub fn address(&self) -> u32 { self.address }
+    pub fn payload(&self) -> &[u8] { self.payload.as_slice() }
+    pub fn len(&self) -> u32 { self.payload.len() as u32 }
+    pub fn is_empty(&self) -> bool { self.payload.is_empty() }
+    pub fn crc(&self) -> u32 { self.crc }
+
+    /// Checks that the image is built for the module and, unless `allow_downgrade` is set, newer than the installed firmware
+    pub fn check(&self, desc : &Descriptor, allow_downgrade : bool) -> Result<(),FirmwareError> {
+        if desc.vendor_product_id() != &self.vendor_product_id {
+            return Err(FirmwareError::WrongProduct { expected : desc.vendor_product_id().clone(), found : self.vendor_product_id.clone() });
+        }
+        if !allow_downgrade && version_key(&self.version) <= version_key(desc.fw_version()) {
+            return Err(FirmwareError::NotNewer { installed : desc.fw_version().clone(), image : self.version.clone() });
+        }
+        Ok(())
+    }
+}
+
+/// Orders versions by number first, a stable release is newer than alpha and beta builds of the same number
+fn version_key(version : &AdvancedVersion) -> (u16,u16,u16,u8) {
+    let stability = match version.stability() {
+        'A' => 0,
+        'B' => 1,
+        _ => 2,
+    };
+    (version.version().major(), version.version().minor(), version.version().patch(), stability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn image_roundtrip_and_checks() {
+        let image = FirmwareImage::new("io".to_string(), AdvancedVersion::new('S', 1, 2, 0), 0x0800_4000, vec![0x5A; 100]);
+        let raw = image.to_bytes();
+        assert_eq!(FirmwareImage::parse(&raw).unwrap(), image);
+
+        let mut corrupt = raw.clone();
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(FirmwareImage::parse(&corrupt), Err(FirmwareError::CrcMismatch { .. })));
+        assert!(matches!(FirmwareImage::parse(&raw[..raw.len() - 1]), Err(FirmwareError::InvalidImage(_))));
+        assert!(matches!(FirmwareImage::parse(&raw[..10]), Err(FirmwareError::InvalidImage(_))));
+
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_vendor_product_id("io".to_string());
+        desc.set_fw_version(AdvancedVersion::new('S', 1, 1, 9));
+        assert!(image.check(&desc, false).is_ok());
+
+        desc.set_fw_version(AdvancedVersion::new('S', 1, 2, 0));
+        assert!(matches!(image.check(&desc, false), Err(FirmwareError::NotNewer { .. })));
+        assert!(image.check(&desc, true).is_ok());
+
+        desc.set_vendor_product_id("power".to_string());
+        assert!(matches!(image.check(&desc, true), Err(FirmwareError::WrongProduct { .. })));
+    }
+}
@@ -0,0 +1,80 @@
+//! Firmware update of modules via the core bootloader class
+//!
+//! A `FirmwareImage` is checked against the module descriptor, then a `FirmwareUpdater`
+//! switches the module into bootloader mode, erases, writes and verifies the flash and
+//! reboots it with `MODE_RUN`. The frames are sent through a `BootloaderTransport`, which
+//! is implemented by the client `Manager`, the driver side `DeviceHandle` and the
+//! `SimulatedBootloader` used by tests.
+//!
+//! Experimental: the bootloader class (`classes::bootloader`) is not yet checked against the
+//! module firmware, the simulator implements the same layout as the updater. `sdbpctl flash`
+//! is only built with the `firmware-experimental` feature.
+
+use std::fmt;
+use std::io::Error;
+use crc::{Crc, CRC_32_BZIP2};
+use crate::datatypes::AdvancedVersion;
+
+mod image;
+mod updater;
+mod simulator;
+
+pub use image::*;
+pub use updater::*;
+pub use simulator::*;
+
+/// CRC of firmware images and of the `VERIFY` operation, the same algorithm as the device session ids
+pub const FIRMWARE_CRC : Crc<u32> = Crc::<u32>::new(&CRC_32_BZIP2);
+
+/// Sends SDBP frames to a single module
+pub trait BootloaderTransport {
+
+    fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,Error>;
+
+    /// Called after the module switched between run and bootloader mode
+    fn reconnect(&mut self) -> Result<(),Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum FirmwareError {
+    InvalidImage(String),
+    WrongProduct { expected : String, found : String },
+    NotNewer { installed : AdvancedVersion, image : AdvancedVersion },
+    /// The module has no bootloader
+    NotSupported,
+    /// The image does not fit into the flash region reported by the bootloader
+    OutOfFlash { address : u32, length : u32 },
+    /// The bootloader answered an operation with a non-zero status
+    Bootloader { operation : u8, status : u8 },
+    CrcMismatch { expected : u32, found : u32 },
+    /// Stopped via `AbortHandle`, the module stays in bootloader mode
+    Aborted { offset : u32 },
+    Io(Error),
+}
+
+impl fmt::Display for FirmwareError {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FirmwareError::InvalidImage(msg) => write!(fmt, "Invalid firmware image: {}", msg),
+            FirmwareError::WrongProduct { expected, found } => write!(fmt, "Image is built for {}, module is {}", found, expected),
+            FirmwareError::NotNewer { installed, image } => write!(fmt, "Image version {} is not newer than installed version {}", image, installed),
+            FirmwareError::NotSupported => write!(fmt, "Module does not support firmware updates"),
+            FirmwareError::OutOfFlash { address, length } => write!(fmt, "Image at 0x{:08x} with {} bytes exceeds the flash", address, length),
+            FirmwareError::Bootloader { operation, status } => write!(fmt, "Bootloader operation 0x{:02x} failed with status 0x{:02x}", operation, status),
+            FirmwareError::CrcMismatch { expected, found } => write!(fmt, "CRC mismatch, expected 0x{:08x} found 0x{:08x}", expected, found),
+            FirmwareError::Aborted { offset } => write!(fmt, "Update aborted after {} bytes", offset),
+            FirmwareError::Io(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for FirmwareError {}
+
+impl From<Error> for FirmwareError {
+    fn from(err : Error) -> Self {
+        FirmwareError::Io(err)
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::{Error, ErrorKind};
+use crate::datatypes::BootloaderState;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::bootloader::{operation_code, return_code};
+use super::*;
+
+const ERASED : u8 = 0xFF;
+
+/// In-memory module with a bootloader, answers control and bootloader frames like a real module
+///
+/// Faults can be injected to test interrupted and corrupted updates. `handle` can also be
+/// used as callback of a virtual device `FrameHandler`.
+pub struct SimulatedBootloader {
+    state : BootloaderState,
+    flash_start : u32,
+    flash : Vec<u8>,
+    max_chunk_size : u16,
+    writes : usize,
+    erases : usize,
+    reboots : usize,
+    fail_after_writes : Option<usize>,
+    corrupt_address : Option<u32>,
+}
+
+impl SimulatedBootloader {
+
+    /// Creates a running module with erased flash
+    pub fn new(flash_start : u32, flash_size : u32, max_chunk_size : u16) -> SimulatedBootloader {
+        SimulatedBootloader {
+            state : BootloaderState::Supported,
+            flash_start,
+            flash : vec![ERASED; flash_size as usize],
+            max_chunk_size,
+            writes : 0,
+            erases : 0,
+            reboots : 0,
+            fail_after_writes : None,
+            corrupt_address : None,
+        }
+    }
+
+    pub fn state(&self) -> BootloaderState { self.state.clone() }
+    pub fn set_state(&mut self, state : BootloaderState) { self.state = state; }
+    pub fn reboots(&self) -> usize { self.reboots }
+    pub fn erases(&self) -> usize { self.erases }
+
+    pub fn flash(&self, address : u32, length : u32) -> &[u8] {
+        let start = (address - self.flash_start) as usize;
+        &self.flash[start..start + length as usize]
+    }
+
+    /// The transfer after `writes` further successful writes fails like a lost connection
+    pub fn fail_after_writes(&mut self, writes : usize) {
+        self.fail_after_writes = Some(self.writes + writes);
+    }
+
+    /// Flips the byte at `address` when it is written
+    pub fn corrupt_on_write(&mut self, address : u32) {
+        self.corrupt_address = Some(address);
+    }
+
+    /// Answers a single frame
+    pub fn handle(&mut self, request : &[u8]) -> Vec<u8> {
+        if request.len() < 3 || request[0] != CLASS_ID {
+            let header = request.iter().take(2).copied().collect::<Vec<u8>>();
+            return [header.as_slice(), &[operation_code::ERROR, return_code::COMMAND_INVALID]].concat();
+        }
+        let (class, operation) = (request[1], request[2]);
+
+        if class == classes::control::ID {
+            let status = match (operation, &self.state) {
+                (classes::control::operation_code::MODE_BOOTLOADER, BootloaderState::Supported | BootloaderState::BootloaderMode) => {
+                    self.state = BootloaderState::BootloaderMode;
+                    return_code::SUCCESS
+                },
+                (classes::control::operation_code::MODE_RUN, BootloaderState::BootloaderMode) => {
+                    self.state = BootloaderState::Supported;
+                    self.reboots += 1;
+                    return_code::SUCCESS
+                },
+                (classes::control::operation_code::MODE_RUN, _) => return_code::SUCCESS,
+                _ => return_code::COMMAND_INVALID,
+            };
+            return vec![CLASS_ID, class, operation, status];
+        }
+
+        if class != classes::bootloader::ID || self.state != BootloaderState::BootloaderMode {
+            return vec![CLASS_ID, class, operation_code::ERROR, return_code::COMMAND_INVALID];
+        }
+
+        let payload = &request[3..];
+        let be_u32 = |index : usize| u32::from_be_bytes([payload[index], payload[index + 1], payload[index + 2], payload[index + 3]]);
+        let mut response = vec![CLASS_ID, class, operation];
+        match operation {
+            operation_code::GET_INFO => {
+                response.push(return_code::SUCCESS);
+                response.extend_from_slice(&self.flash_start.to_be_bytes());
+                response.extend_from_slice(&(self.flash.len() as u32).to_be_bytes());
+                response.extend_from_slice(&self.max_chunk_size.to_be_bytes());
+            },
+            operation_code::ERASE if payload.len() == 8 => match self.range(be_u32(0), be_u32(4)) {
+                Some((start, end)) => {
+                    self.flash[start..end].fill(ERASED);
+                    self.erases += 1;
+                    response.push(return_code::SUCCESS);
+                },
+                None => response.push(return_code::INVALID_ADDRESS),
+            },
+            operation_code::WRITE if payload.len() > 4 && payload.len() - 4 <= self.max_chunk_size as usize => {
+                let data = &payload[4..];
+                match self.range(be_u32(0), data.len() as u32) {
+                    None => response.push(return_code::INVALID_ADDRESS),
+                    Some((start, end)) if self.flash[start..end].iter().any(|value| *value != ERASED) => response.push(return_code::NOT_ERASED),
+                    Some((start, end)) => {
+                        self.flash[start..end].copy_from_slice(data);
+                        if let Some(address) = self.corrupt_address {
+                            if let Some((corrupt, _)) = self.range(address, 1).filter(|(corrupt, _)| (start..end).contains(corrupt)) {
+                                self.flash[corrupt] ^= 0xFF;
+                            }
+                        }
+                        self.writes += 1;
+                        response.push(return_code::SUCCESS);
+                    },
+                }
+            },
+            operation_code::VERIFY if payload.len() == 8 => match self.range(be_u32(0), be_u32(4)) {
+                Some((start, end)) => {
+                    response.push(return_code::SUCCESS);
+                    response.extend_from_slice(&FIRMWARE_CRC.checksum(&self.flash[start..end]).to_be_bytes());
+                },
+                None => response.push(return_code::INVALID_ADDRESS),
+            },
+            operation_code::ERASE | operation_code::WRITE | operation_code::VERIFY => response.push(return_code::WRONG_LENGTH),
+            _ => return vec![CLASS_ID, class, operation_code::ERROR, return_code::COMMAND_INVALID],
+        }
+        response
+    }
+
+    /// Converts an address range into flash indices
+    fn range(&self, address : u32, length : u32) -> Option<(usize,usize)> {
+        let start = address.checked_sub(self.flash_start)? as usize;
+        let end = start.checked_add(length as usize)?;
+        if end > self.flash.len() {
+            return None;
+        }
+        Some((start, end))
+    }
+}
+
+impl BootloaderTransport for SimulatedBootloader {
+
+    fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,Error> {
+        if self.fail_after_writes == Some(self.writes) {
+            self.fail_after_writes = None;
+            return Err(Error::new(ErrorKind::ConnectionReset, "Simulated connection loss"));
+        }
+        Ok(self.handle(request.as_slice()))
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::datatypes::BootloaderState;
+use crate::sdbp::CoreBuilder;
+use crate::sdbp::request::core::protocol::classes::bootloader::{operation_code, return_code};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::core::bootloader::{BootloaderInfo, BootloaderStatus, VerifyResponse};
+use crate::sdbp::response::core::control::{BootloaderModeResponse, RunResponse};
+use super::*;
+
+pub const DEFAULT_CHUNK_SIZE : usize = 256;
+
+type ProgressCallback = Box<dyn FnMut(&UpdateProgress) + Send>;
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum UpdatePhase {
+    EnterBootloader,
+    Erase,
+    Write,
+    Verify,
+    Reboot,
+    Done,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct UpdateProgress {
+    pub phase : UpdatePhase,
+    /// Bytes written and confirmed by the bootloader
+    pub bytes_done : u32,
+    pub bytes_total : u32,
+}
+
+impl UpdateProgress {
+
+    pub fn percent(&self) -> u8 {
+        if self.bytes_total == 0 {
+            return 0;
+        }
+        (self.bytes_done as u64 * 100 / self.bytes_total as u64) as u8
+    }
+}
+
+/// Progress of an interrupted update, can be stored to resume after a restart
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct UpdateCheckpoint {
+    pub image_crc : u32,
+    /// Bytes of the image which are written to the erased flash
+    pub offset : u32,
+}
+
+/// Stops a running update before the next chunk is written
+#[derive(Debug,Clone,Default)]
+pub struct AbortHandle(Arc<AtomicBool>);
+
+impl AbortHandle {
+
+    pub fn abort(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Flashes a `FirmwareImage`
+///
+/// The module is only rebooted into `MODE_RUN` after the whole image was verified. If the update
+/// fails or is aborted, the module stays in bootloader mode and `checkpoint()` tells how far it got.
+/// Running the updater again continues after the last confirmed chunk if the flash still matches.
+pub struct FirmwareUpdater {
+    image : FirmwareImage,
+    chunk_size : usize,
+    checkpoint : Option<UpdateCheckpoint>,
+    abort : AbortHandle,
+    progress : Option<ProgressCallback>,
+}
+
+impl FirmwareUpdater {
+
+    pub fn new(image : FirmwareImage) -> FirmwareUpdater {
+        FirmwareUpdater { image, chunk_size : DEFAULT_CHUNK_SIZE, checkpoint : None, abort : AbortHandle::default(), progress : None }
+    }
+
+    /// Upper limit of the bytes per `WRITE`, the bootloader may demand smaller chunks
+    pub fn chunk_size(mut self, chunk_size : usize) -> FirmwareUpdater {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn resume_from(mut self, checkpoint : UpdateCheckpoint) -> FirmwareUpdater {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    pub fn on_progress<F>(mut self, callback : F) -> FirmwareUpdater where F : FnMut(&UpdateProgress) + Send + 'static {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    pub fn checkpoint(&self) -> Option<UpdateCheckpoint> {
+        self.checkpoint
+    }
+
+    pub fn image(&self) -> &FirmwareImage {
+        &self.image
+    }
+
+    /// Runs the update, `state` is the bootloader state of the module descriptor
+    pub fn run<T : BootloaderTransport + ?Sized>(&mut self, transport : &mut T, state : &BootloaderState) -> Result<(),FirmwareError> {
+        let total = self.image.len();
+        if self.abort.is_aborted() {
+            return Err(FirmwareError::Aborted { offset : 0 });
+        }
+
+        match state {
+            BootloaderState::Supported => {
+                self.report(UpdatePhase::EnterBootloader, 0);
+                let request = CoreBuilder::new().control().mode_bootloader()?;
+                BootloaderModeResponse::from_raw(transport.transfer(request)?)?;
+                transport.reconnect()?;
+            },
+            BootloaderState::BootloaderMode => (),
+            BootloaderState::NotSupported | BootloaderState::NotInitialized => return Err(FirmwareError::NotSupported),
+        }
+
+        let info = BootloaderInfo::from_raw(transport.transfer(CoreBuilder::new().bootloader().get_info()?)?)?;
+        FirmwareUpdater::check_status(operation_code::GET_INFO, info.status)?;
+        let flash_end = info.flash_start as u64 + info.flash_size as u64;
+        if self.image.address() < info.flash_start || self.image.address() as u64 + total as u64 > flash_end {
+            return Err(FirmwareError::OutOfFlash { address : self.image.address(), length : total });
+        }
+        let chunk_size = match info.max_chunk_size {
+            0 => self.chunk_size,
+            max => self.chunk_size.min(max as usize),
+        };
+
+        let mut offset = self.resume_offset(transport)?;
+        if offset == 0 {
+            // An abort before the erase leaves the old firmware bootable
+            if self.abort.is_aborted() {
+                return Err(FirmwareError::Aborted { offset });
+            }
+            self.report(UpdatePhase::Erase, 0);
+            let request = CoreBuilder::new().bootloader().erase(self.image.address(), total)?;
+            let status = BootloaderStatus::from_raw(transport.transfer(request)?)?;
+            FirmwareUpdater::check_status(status.operation_code, status.status)?;
+            self.checkpoint = Some(UpdateCheckpoint { image_crc : self.image.crc(), offset : 0 });
+        }
+
+        while offset < total {
+            if self.abort.is_aborted() {
+                return Err(FirmwareError::Aborted { offset });
+            }
+            let end = (offset as usize + chunk_size).min(total as usize);
+            let request = CoreBuilder::new().bootloader().write(self.image.address() + offset, &self.image.payload()[offset as usize..end])?;
+            let status = BootloaderStatus::from_raw(transport.transfer(request)?)?;
+            FirmwareUpdater::check_status(status.operation_code, status.status)?;
+
+            offset = end as u32;
+            self.checkpoint = Some(UpdateCheckpoint { image_crc : self.image.crc(), offset });
+            self.report(UpdatePhase::Write, offset);
+        }
+
+        self.report(UpdatePhase::Verify, total);
+        let crc = self.flash_crc(transport, total)?;
+        if crc != self.image.crc() {
+            // The written data can not be trusted, the next run starts from scratch
+            self.checkpoint = None;
+            return Err(FirmwareError::CrcMismatch { expected : self.image.crc(), found : crc });
+        }
+
+        self.report(UpdatePhase::Reboot, total);
+        RunResponse::from_raw(transport.transfer(CoreBuilder::new().control().mode_run()?)?)?;
+        self.checkpoint = None;
+        transport.reconnect()?;
+        self.report(UpdatePhase::Done, total);
+        Ok(())
+    }
+
+    /// Returns the offset to continue at, a checkpoint is only used if the flash still holds the written part
+    fn resume_offset<T : BootloaderTransport + ?Sized>(&mut self, transport : &mut T) -> Result<u32,FirmwareError> {
+        let checkpoint = match self.checkpoint {
+            Some(value) if value.image_crc == self.image.crc() && value.offset > 0 && value.offset <= self.image.len() => value,
+            _ => return Ok(0),
+        };
+
+        let expected = FIRMWARE_CRC.checksum(&self.image.payload()[..checkpoint.offset as usize]);
+        if self.flash_crc(transport, checkpoint.offset)? == expected {
+            debug!("Resuming firmware update at {} of {} bytes", checkpoint.offset, self.image.len());
+            Ok(checkpoint.offset)
+        } else {
+            warn!("Flash does not match the update checkpoint, restarting the update");
+            Ok(0)
+        }
+    }
+
+    fn flash_crc<T : BootloaderTransport + ?Sized>(&self, transport : &mut T, length : u32) -> Result<u32,FirmwareError> {
+        let request = CoreBuilder::new().bootloader().verify(self.image.address(), length)?;
+        let response = VerifyResponse::from_raw(transport.transfer(request)?)?;
+        FirmwareUpdater::check_status(operation_code::VERIFY, response.status)?;
+        Ok(response.crc)
+    }
+
+    fn check_status(operation : u8, status : u8) -> Result<(),FirmwareError> {
+        match status {
+            return_code::SUCCESS => Ok(()),
+            _ => Err(FirmwareError::Bootloader { operation, status }),
+        }
+    }
+
+    fn report(&mut self, phase : UpdatePhase, bytes_done : u32) {
+        let progress = UpdateProgress { phase, bytes_done, bytes_total : self.image.len() };
+        trace!("Firmware update {:?} {}%", progress.phase, progress.percent());
+        if let Some(callback) = self.progress.as_mut() {
+            callback(&progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::datatypes::AdvancedVersion;
+
+    const FLASH_START : u32 = 0x0800_0000;
+
+    fn image(len : usize) -> FirmwareImage {
+        let payload = (0..len).map(|value| (value * 7) as u8).collect();
+        FirmwareImage::new("io".to_string(), AdvancedVersion::new('S', 2, 0, 0), FLASH_START + 0x4000, payload)
+    }
+
+    #[test]
+    fn update_reboots_after_verify() {
+        let image = image(1000);
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let recorded = phases.clone();
+
+        let mut bootloader = SimulatedBootloader::new(FLASH_START, 0x10000, 128);
+        let mut updater = FirmwareUpdater::new(image.clone())
+            .on_progress(move |progress| recorded.lock().unwrap().push((progress.phase, progress.bytes_done)));
+        updater.run(&mut bootloader, &BootloaderState::Supported).unwrap();
+
+        assert_eq!(bootloader.state(), BootloaderState::Supported);
+        assert_eq!(bootloader.reboots(), 1);
+        assert_eq!(bootloader.flash(image.address(), image.len()), image.payload());
+        assert_eq!(updater.checkpoint(), None);
+
+        let phases = phases.lock().unwrap();
+        assert_eq!(phases.first(), Some(&(UpdatePhase::EnterBootloader, 0)));
+        assert_eq!(phases.iter().filter(|(phase, _)| *phase == UpdatePhase::Write).count(), 8);
+        assert_eq!(phases.last(), Some(&(UpdatePhase::Done, 1000)));
+    }
+
+    #[test]
+    fn interrupted_update_resumes() {
+        let image = image(1000);
+        let mut bootloader = SimulatedBootloader::new(FLASH_START, 0x10000, 100);
+        bootloader.fail_after_writes(4);
+
+        let mut updater = FirmwareUpdater::new(image.clone());
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::Supported), Err(FirmwareError::Io(_))));
+        assert_eq!(bootloader.state(), BootloaderState::BootloaderMode);
+        let checkpoint = updater.checkpoint().unwrap();
+        assert_eq!(checkpoint.offset, 400);
+
+        let mut updater = FirmwareUpdater::new(image.clone()).resume_from(checkpoint);
+        updater.run(&mut bootloader, &BootloaderState::BootloaderMode).unwrap();
+        assert_eq!(bootloader.erases(), 1);
+        assert_eq!(bootloader.flash(image.address(), image.len()), image.payload());
+
+        let mut updater = FirmwareUpdater::new(image.clone());
+        updater.abort_handle().abort();
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::Supported), Err(FirmwareError::Aborted { offset : 0 })));
+        assert_eq!(bootloader.state(), BootloaderState::Supported);
+
+        let updater = FirmwareUpdater::new(image.clone());
+        let abort = updater.abort_handle();
+        let mut updater = updater.on_progress(move |progress| if progress.phase == UpdatePhase::EnterBootloader { abort.abort() });
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::Supported), Err(FirmwareError::Aborted { offset : 0 })));
+        assert_eq!(bootloader.state(), BootloaderState::BootloaderMode);
+        assert_eq!(bootloader.erases(), 1);
+    }
+
+    #[test]
+    fn corrupted_flash_is_not_booted() {
+        let image = image(300);
+        let mut bootloader = SimulatedBootloader::new(FLASH_START, 0x10000, 256);
+        bootloader.corrupt_on_write(image.address() + 10);
+
+        let mut updater = FirmwareUpdater::new(image.clone());
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::Supported), Err(FirmwareError::CrcMismatch { .. })));
+        assert_eq!(bootloader.state(), BootloaderState::BootloaderMode);
+        assert_eq!(bootloader.reboots(), 0);
+        assert_eq!(updater.checkpoint(), None);
+
+        let mut updater = FirmwareUpdater::new(FirmwareImage::new("io".to_string(), AdvancedVersion::new('S', 2, 0, 0), 0, vec![0; 16]));
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::BootloaderMode), Err(FirmwareError::OutOfFlash { .. })));
+        assert!(matches!(updater.run(&mut bootloader, &BootloaderState::NotSupported), Err(FirmwareError::NotSupported)));
+    }
+}
@@ -0,0 +1,154 @@
+//! Client side event loop for IO module notifications
+//!
+//! The `IoEventLoop` polls `GET_NOTIFICATION`, decodes the payload into an `IoEvent` and
+//! joins it with the configuration of the pin from the last applied `IoProfile`. The payload
+//! layout is not confirmed yet (see `IoEvent`), so every event carries the raw payload and
+//! payloads which do not decode are delivered without an `IoEvent`.
+
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::sdbp::CoreBuilder;
+use crate::sdbp::ioprofile::{IoProfile, PinFunction};
+use crate::sdbp::request::custom::io::Trigger;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::core::notification::NotificationResponse;
+use crate::sdbp::response::custom::io::notification::IoEvent;
+use crate::util::*;
+
+/// Notification together with the configuration of its pin
+#[derive(Debug,Clone,PartialEq)]
+pub struct InputEvent {
+    pub timestamp : SystemTime,
+    /// Payload of `GET_NOTIFICATION`
+    pub raw : Vec<u8>,
+    /// `None` if the payload could not be decoded
+    pub event : Option<IoEvent>,
+    /// Function of the pin in the last known profile, `None` if no profile is known
+    pub config : Option<PinFunction>,
+}
+
+impl InputEvent {
+
+    /// `true` if the pin is configured to raise this event, `false` for unexpected or undecoded events or without a profile
+    pub fn is_configured(&self) -> bool {
+        let event = match self.event {
+            Some(event) => event,
+            None => return false,
+        };
+        match (event, self.config) {
+            (IoEvent::ThresholdCrossed { direction, .. }, Some(PinFunction::Analog { threshold : Some(threshold) })) => threshold.trigger == direction.trigger(),
+            (IoEvent::Edge { rising, .. }, Some(PinFunction::Digital { interrupt : Some(interrupt), .. })) => {
+                interrupt.trigger == if rising { Trigger::Rising } else { Trigger::Falling }
+            }
+            (IoEvent::Pulse { .. }, Some(PinFunction::Digital { interrupt : Some(interrupt), .. })) => interrupt.trigger == Trigger::Pulse,
+            _ => false,
+        }
+    }
+}
+
+/// Polls the notifications of an IO module in the background and sends an `InputEvent` per notification
+///
+/// `transfer` sends a frame to the module, e.g. a `Manager` with the slot already selected.
+/// Pending notifications are read back to back, `interval` is waited after an empty poll.
+pub struct IoEventLoop {
+    handle : ManagedThreadHandle<()>,
+    profile : Arc<Mutex<Option<IoProfile>>>,
+    events : Receiver<InputEvent>,
+}
+
+impl IoEventLoop {
+
+    pub fn start<F>(transfer : F, interval : Duration, profile : Option<IoProfile>) -> IoEventLoop where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> + Send + 'static {
+        let profile = Arc::new(Mutex::new(profile));
+        let (tx, events) = crossbeam_channel::unbounded();
+        let task_profile = profile.clone();
+        let handle = spawn("IoEventLoop".to_string(), move |ctl_pair| IoEventLoop::task(ctl_pair, transfer, interval, task_profile, tx));
+        IoEventLoop { handle, profile, events }
+    }
+
+    fn task<F>(ctl_pair : ChannelPair<ManagedThreadState>, mut transfer : F, interval : Duration, profile : Arc<Mutex<Option<IoProfile>>>, tx : Sender<InputEvent>)
+        where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let mut stopped = false;
+        while !stopped {
+            ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+            if stopped {
+                break;
+            }
+
+            let notification = transfer(CoreBuilder::new().notification().get_notification())
+                .and_then(NotificationResponse::from_raw)
+                .map(|response| response.notification);
+            match notification {
+                Ok(raw) if raw.iter().any(|byte| *byte != 0) => {
+                    let event = match IoEvent::decode(&raw) {
+                        Ok(event) => event,
+                        Err(err) => {
+                            debug!("Could not decode notification {:02x?}: {}", raw, err);
+                            None
+                        }
+                    };
+                    let config = event.and_then(|event| profile.lock().expect("Could not lock IO profile").as_ref()
+                        .and_then(|profile| profile.pin(event.pin()).map(|pin| pin.function)));
+                    if tx.send(InputEvent { timestamp : SystemTime::now(), raw, event, config }).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Ok(_) => (),
+                Err(err) => debug!("Reading notification failed: {}", err),
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    /// Replaces the profile events are joined with, e.g. after `IoProfile::apply`
+    pub fn set_profile(&self, profile : IoProfile) {
+        *self.profile.lock().expect("Could not lock IO profile") = Some(profile);
+    }
+
+    pub fn events(&self) -> &Receiver<InputEvent> {
+        &self.events
+    }
+
+    pub fn stop(&self, timeout : Duration) {
+        let _ = self.handle.stop(timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::ioprofile::{DigitalInterrupt, PinPower, PinProfile};
+    use crate::sdbp::request::custom::io::PinId;
+
+    #[test]
+    fn notifications_are_joined_with_the_profile() {
+        let mut pending = vec![vec![1, 4, 2, 1], vec![1, 4, 3, 3], vec![0x80, 0, 0, 5]];
+        let module = move |_request : Vec<u8>| {
+            let mut response = vec![0x01, 0x06, 0x02, 0x00];
+            response.extend(if pending.is_empty() { vec![0; 4] } else { pending.remove(0) });
+            Ok(response)
+        };
+
+        let digital = |pin : u8, trigger : Trigger| PinProfile {
+            pin : PinId::new(pin).unwrap(),
+            power : PinPower { rail : 0, current_limit_ma : 10 },
+            function : PinFunction::Digital { interrupt : Some(DigitalInterrupt { debounce_ms : 0, trigger }), counter : None },
+        };
+        let profile = IoProfile { pins : vec![digital(2, Trigger::Rising), digital(3, Trigger::Rising)] };
+        let events = IoEventLoop::start(module, Duration::from_millis(5), Some(profile));
+
+        let edge = events.events().recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(edge.event, Some(IoEvent::Edge { pin : PinId::new(2).unwrap(), rising : true }));
+        assert!(edge.is_configured());
+        let pulse = events.events().recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(pulse.event, Some(IoEvent::Pulse { pin : PinId::new(3).unwrap() }));
+        assert!(!pulse.is_configured());
+        let unknown = events.events().recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!((unknown.raw, unknown.event), (vec![0x80, 0, 0, 5], None));
+        events.stop(Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,211 @@
+//! Output control with fault handling for IO modules
+//!
+//! The `OutputController` sends `SET_OUTPUT` and acts on the output statuses instead of
+//! handing them to the caller. A missing power configuration is sent from the stored power
+//! profile and the output is set again. An external voltage on a pin latches a fault, the
+//! pin is not driven again until the fault is cleared.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Error;
+use std::time::SystemTime;
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::sdbp::ioprofile::IoProfile;
+use crate::sdbp::request::custom::io::{IoBuilder, OutputMode, PinId};
+use crate::sdbp::request::custom::io::protocol::classes::output_class::error_code;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::custom::io::output::OutputModeStatus;
+use crate::sdbp::response::custom::io::powermgmt::SetPowerConfig;
+
+/// Latched fault of an output pin
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OutputFault {
+    pub pin : PinId,
+    pub since : SystemTime,
+    /// Status the module answered with
+    pub status : u8,
+    /// Output mode that was refused
+    pub mode : OutputMode,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum OutputEvent {
+    Faulted(OutputFault),
+    Cleared { pin : PinId },
+    /// The stored power configuration was sent after `POWER_CONFIG_MISSING`
+    PowerConfigApplied,
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    /// The pin has a latched fault, see `OutputController::clear`
+    Faulted(OutputFault),
+    /// The module has no power configuration and none is stored, or sending it did not help
+    PowerConfigMissing,
+    /// The module answered with another non-zero status
+    Rejected { status : u8, msg : String },
+    Io(Error),
+}
+
+impl fmt::Display for OutputError {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::Faulted(fault) => write!(fmt, "Pin {} is faulted with status 0x{:02x}, clear the fault first", fault.pin, fault.status),
+            OutputError::PowerConfigMissing => write!(fmt, "Module has no power configuration"),
+            OutputError::Rejected { status, msg } => write!(fmt, "Output rejected with status 0x{:02x}: {}", status, msg),
+            OutputError::Io(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+impl From<Error> for OutputError {
+    fn from(err : Error) -> Self {
+        OutputError::Io(err)
+    }
+}
+
+/// Sets the outputs of an IO module, `transfer` sends a frame and returns the response
+pub struct OutputController<F> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+    transfer : F,
+    power_config : Option<Vec<(u8,u16)>>,
+    faults : BTreeMap<PinId,OutputFault>,
+    subscribers : Vec<Sender<OutputEvent>>,
+}
+
+impl <F>OutputController<F> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+
+    pub fn new(transfer : F) -> OutputController<F> {
+        OutputController { transfer, power_config : None, faults : BTreeMap::new(), subscribers : vec![] }
+    }
+
+    /// Power configuration sent when the module reports `POWER_CONFIG_MISSING`
+    pub fn power_profile(mut self, profile : &IoProfile) -> OutputController<F> {
+        self.power_config = Some(profile.power_config());
+        self
+    }
+
+    pub fn set_power_profile(&mut self, profile : &IoProfile) {
+        self.power_config = Some(profile.power_config());
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<OutputEvent> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Sets the output, refused without contacting the module while the pin is faulted
+    pub fn set(&mut self, pin : PinId, mode : OutputMode) -> Result<(),OutputError> {
+        if let Some(fault) = self.faults.get(&pin) {
+            return Err(OutputError::Faulted(*fault));
+        }
+
+        let mut status = self.send(pin, mode)?;
+        if status.status == error_code::POWER_CONFIG_MISSING {
+            self.apply_power_config()?;
+            status = self.send(pin, mode)?;
+        }
+
+        match status.status {
+            error_code::OK => Ok(()),
+            error_code::POWER_CONFIG_MISSING => Err(OutputError::PowerConfigMissing),
+            error_code::EXTERNAL_VOLTAGE => {
+                let fault = OutputFault { pin, since : SystemTime::now(), status : status.status, mode };
+                warn!("External voltage on output pin {}, pin latched off", pin);
+                self.faults.insert(pin, fault);
+                self.emit(OutputEvent::Faulted(fault));
+                Err(OutputError::Faulted(fault))
+            }
+            _ => Err(OutputError::Rejected { status : status.status, msg : status.msg }),
+        }
+    }
+
+    /// Removes the fault of `pin` so it can be driven again
+    pub fn clear(&mut self, pin : PinId) -> Option<OutputFault> {
+        let fault = self.faults.remove(&pin)?;
+        self.emit(OutputEvent::Cleared { pin });
+        Some(fault)
+    }
+
+    pub fn fault(&self, pin : PinId) -> Option<&OutputFault> {
+        self.faults.get(&pin)
+    }
+
+    /// Latched faults ordered by pin
+    pub fn faults(&self) -> Vec<OutputFault> {
+        self.faults.values().copied().collect()
+    }
+
+    fn send(&mut self, pin : PinId, mode : OutputMode) -> Result<OutputModeStatus,Error> {
+        let frame = IoBuilder::new().output().set_pin_output(pin, mode)?;
+        OutputModeStatus::from_raw((self.transfer)(frame)?)
+    }
+
+    fn apply_power_config(&mut self) -> Result<(),OutputError> {
+        let config = self.power_config.clone().ok_or(OutputError::PowerConfigMissing)?;
+        let frame = IoBuilder::new().powermgmt().set_power_config(config)?;
+        let status = SetPowerConfig::from_raw((self.transfer)(frame)?)?.status;
+        if status != 0 {
+            return Err(OutputError::Rejected { status, msg : "Power config rejected".to_string() });
+        }
+        self.emit(OutputEvent::PowerConfigApplied);
+        Ok(())
+    }
+
+    fn emit(&mut self, event : OutputEvent) {
+        self.subscribers.retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::ioprofile::{PinFunction, PinPower, PinProfile};
+    use crate::sdbp::request::custom::io::protocol::*;
+
+    #[test]
+    fn missing_power_config_is_sent_and_external_voltage_latches() {
+        let mut powered = false;
+        let module = move |request : Vec<u8>| {
+            if request[1] == classes::power_management_class::ID {
+                powered = true;
+                return Ok(vec![CLASS_ID, request[1], request[2], 0]);
+            }
+            let status = match request[3] {
+                _ if !powered => error_code::POWER_CONFIG_MISSING,
+                3 => error_code::EXTERNAL_VOLTAGE,
+                _ => error_code::OK,
+            };
+            Ok(vec![CLASS_ID, request[1], request[2], status])
+        };
+
+        let pins = PinId::all().map(|pin| PinProfile {
+            pin,
+            power : PinPower { rail : 0, current_limit_ma : 100 },
+            function : PinFunction::Output { output : OutputMode::Digital(false) },
+        });
+        let profile = IoProfile { pins : pins.collect() };
+        let (one, three) = (PinId::new(1).unwrap(), PinId::new(3).unwrap());
+
+        let mut outputs = OutputController::new(module);
+        let events = outputs.subscribe();
+        assert!(matches!(outputs.set(one, OutputMode::Digital(true)), Err(OutputError::PowerConfigMissing)));
+
+        outputs.set_power_profile(&profile);
+        outputs.set(one, OutputMode::Digital(true)).unwrap();
+        assert_eq!(events.try_recv().unwrap(), OutputEvent::PowerConfigApplied);
+
+        assert!(matches!(outputs.set(three, OutputMode::Digital(true)), Err(OutputError::Faulted(_))));
+        assert!(matches!(events.try_recv().unwrap(), OutputEvent::Faulted(OutputFault { status : error_code::EXTERNAL_VOLTAGE, .. })));
+        assert!(matches!(outputs.set(three, OutputMode::Digital(false)), Err(OutputError::Faulted(fault)) if fault.mode == OutputMode::Digital(true)));
+        assert_eq!(outputs.faults().len(), 1);
+
+        assert!(outputs.clear(three).is_some());
+        assert_eq!(events.try_recv().unwrap(), OutputEvent::Cleared { pin : three });
+        assert!(outputs.fault(three).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::Error;
+
+use crate::sdbp::request::custom::io::*;
+use crate::sdbp::request::custom::io::protocol::classes::*;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::custom::io::input::*;
+use crate::sdbp::response::custom::io::output::OutputModeStatus;
+use crate::sdbp::response::custom::io::powermgmt::{SetPowerConfig, TestPowerConfig};
+use super::*;
+
+impl IoProfile {
+
+    /// Configures the module, `transfer` sends a frame and returns the response
+    ///
+    /// Nothing is sent if the profile is invalid or `TEST_POWER_CONFIG` fails. Once the module
+    /// was changed, a failure applies `previous` again and returns the original error, or
+    /// `ProfileError::RollbackFailed` if that did not work either.
+    pub fn apply<F>(&self, previous : Option<&IoProfile>, mut transfer : F) -> Result<(),ProfileError> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let steps = self.steps()?;
+        let test = IoBuilder::new().powermgmt().test_power_config(self.power_config())
+            .map_err(|err| ProfileError::Invalid(err.to_string()))?;
+        let status = TestPowerConfig::from_raw(transfer(test)?)?.status;
+        if status != 0 {
+            return Err(ProfileError::PowerConfig { status });
+        }
+
+        let result = IoProfile::send(&steps, &mut transfer).and_then(|_| self.verify(&mut transfer));
+        let cause = match result {
+            Ok(()) => return Ok(()),
+            Err(cause) => cause,
+        };
+
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Err(cause),
+        };
+        warn!("Applying IO profile failed ({}), restoring the previous profile", cause);
+        match previous.steps().and_then(|steps| IoProfile::send(&steps, &mut transfer)) {
+            Ok(()) => Err(cause),
+            Err(rollback) => Err(ProfileError::RollbackFailed { cause : Box::new(cause), rollback : Box::new(rollback) }),
+        }
+    }
+
+    fn send<F>(steps : &[ProfileStep], transfer : &mut F) -> Result<(),ProfileError> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        for step in steps {
+            let raw = transfer(step.frame.clone())?;
+            let (status, msg) = match (step.frame[1], step.frame[2]) {
+                (power_management_class::ID, _) => (SetPowerConfig::from_raw(raw)?.status, "Power config rejected".to_string()),
+                (input_class::ID, input_class::operation_code::SET_INPUT_MODE) => {
+                    let response = InputModeStatus::from_raw(raw)?;
+                    (response.status, response.msg)
+                }
+                (input_class::ID, input_class::operation_code::SET_ANALOG_THRESHOLD) => {
+                    let response = AnalogThresholdStatus::from_raw(raw)?;
+                    (response.status, response.msg)
+                }
+                (input_class::ID, input_class::operation_code::SET_DIGITAL_INTERRUPT) => {
+                    let response = DigitalInterruptStatus::from_raw(raw)?;
+                    (response.status, response.msg)
+                }
+                (input_class::ID, _) => {
+                    let response = DigitalCounterStatus::from_raw(raw)?;
+                    (response.status, response.msg)
+                }
+                _ => {
+                    let response = OutputModeStatus::from_raw(raw)?;
+                    (response.status, response.msg)
+                }
+            };
+            if status != 0 {
+                return Err(ProfileError::Rejected { step : step.name.clone(), status, msg });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the current values and checks that every input reports a value of its function
+    fn verify<F>(&self, transfer : &mut F) -> Result<(),ProfileError> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let request = IoBuilder::new().input().get_current_values()?;
+        let values = GetValuesStatus::from_raw(transfer(request)?)?;
+
+        for profile in &self.pins {
+            let expected : &[&str] = match profile.function {
+                PinFunction::Analog { .. } => &["voltage_millivolt", "current_milliampere"],
+                PinFunction::Digital { .. } => &["digital_input", "frequency_hertz"],
+                PinFunction::Output { .. } => continue,
+            };
+            match values.pins.iter().find(|value| value.pin == profile.pin.value()) {
+                Some(value) if expected.contains(&value.pin_type.as_str()) => (),
+                Some(value) => return Err(ProfileError::Verification(format!("pin {} reports {}", profile.pin, value.pin_type))),
+                None => return Err(ProfileError::Verification(format!("pin {} reports no value", profile.pin))),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::request::custom::io::protocol::CLASS_ID;
+
+    /// Keeps the input modes and rejects outputs on `faulty_pin`
+    struct Module {
+        modes : [u8; PIN_COUNT as usize],
+        faulty_pin : Option<u8>,
+    }
+
+    impl Module {
+
+        fn transfer(&mut self, request : Vec<u8>) -> Result<Vec<u8>,Error> {
+            let mut response = vec![CLASS_ID, request[1], request[2], 0];
+            match (request[1], request[2]) {
+                (input_class::ID, input_class::operation_code::SET_INPUT_MODE) => self.modes[request[3] as usize] = request[4],
+                (input_class::ID, input_class::operation_code::GET_CURRENT_VALUES) => {
+                    for (pin, mode) in self.modes.iter().enumerate() {
+                        response.extend_from_slice(&[pin as u8, if *mode == 1 { 0x00 } else { 0x02 }, 2, 0, 1]);
+                    }
+                }
+                (output_class::ID, _) if Some(request[3]) == self.faulty_pin => response[3] = output_class::error_code::EXTERNAL_VOLTAGE,
+                _ => (),
+            }
+            Ok(response)
+        }
+    }
+
+    fn profile(output_pin : u8) -> IoProfile {
+        let pins = PinId::all().map(|pin| PinProfile {
+            pin,
+            power : PinPower { rail : 0, current_limit_ma : 100 },
+            function : match pin.value() {
+                pin if pin == output_pin => PinFunction::Output { output : OutputMode::Digital(true) },
+                0 | 1 => PinFunction::Analog { threshold : Some(AnalogThreshold { threshold_mv : 1000, trigger : Trigger::Rising }) },
+                _ => PinFunction::Digital { interrupt : None, counter : Some(CounterState::Enabled) },
+            },
+        });
+        IoProfile { pins : pins.collect() }
+    }
+
+    #[test]
+    fn failed_profile_restores_the_previous_one() {
+        let toml = "[[pins]]\npin = 0\npower = { rail = 1, current_limit_ma = 400 }\nfunction = \"output\"\noutput = { pwm = { prescaler = 1, time_on = 10, period = 20 } }\n";
+        let pin = &IoProfile::from_toml(toml).unwrap().pins[0];
+        assert_eq!(pin.function, PinFunction::Output { output : OutputMode::Pwm { prescaler : 1, time_on : 10, period : 20 } });
+        let json = serde_json::to_string(&profile(5)).unwrap();
+        assert_eq!(IoProfile::from_json(&json).unwrap(), profile(5));
+
+        let mut invalid = profile(5);
+        invalid.pins[0].function = PinFunction::Analog { threshold : Some(AnalogThreshold { threshold_mv : 1000, trigger : Trigger::Pulse }) };
+        assert!(matches!(invalid.validate(), Err(ProfileError::Invalid(_))));
+        invalid.pins.pop();
+        assert!(matches!(invalid.validate(), Err(ProfileError::Invalid(_))));
+
+        let mut module = Module { modes : [0; PIN_COUNT as usize], faulty_pin : Some(4) };
+        let first = profile(5);
+        first.apply(None, |frame| module.transfer(frame)).unwrap();
+        assert_eq!(module.modes, [1, 1, 2, 2, 2, 0]);
+
+        let mut second = profile(4);
+        second.pins[0].function = PinFunction::Digital { interrupt : None, counter : None };
+        let err = second.apply(Some(&first), |frame| module.transfer(frame)).unwrap_err();
+        assert!(matches!(err, ProfileError::Rejected { status : output_class::error_code::EXTERNAL_VOLTAGE, .. }));
+        assert_eq!(module.modes, [1, 1, 2, 2, 2, 2]);
+    }
+}
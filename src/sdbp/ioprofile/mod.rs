@@ -0,0 +1,58 @@
+//! Declarative configuration of IO modules
+//!
+//! An `IoProfile` describes the power rail and the function of every pin and is loaded from
+//! JSON or TOML. `IoProfile::apply` checks the profile offline and with `TEST_POWER_CONFIG`,
+//! sends the power configuration, the input modes, the thresholds, interrupts and counters
+//! and at last the outputs, and reads the current values back. If the module rejects a step
+//! or the read back does not match, the previous profile is applied again.
+//!
+//! The `PowerPlanner` assigns the rails of the pins from their loads, checks them against
+//! the descriptor and the power management budget and sends the power configuration. The
+//! supply and voltage of the rails are module hardware and passed in as a `RailConfig`.
+
+use std::fmt;
+use std::io::Error;
+
+mod profile;
+mod apply;
+mod planner;
+
+pub use profile::*;
+pub use planner::*;
+
+#[derive(Debug)]
+pub enum ProfileError {
+    /// The profile is incomplete or holds values the module does not accept
+    Invalid(String),
+    /// `TEST_POWER_CONFIG` failed, nothing was changed on the module
+    PowerConfig { status : u8 },
+    /// The module answered a step with a non-zero status
+    Rejected { step : String, status : u8, msg : String },
+    /// The current values do not match the applied profile
+    Verification(String),
+    /// Applying the previous profile after `cause` failed as well, the module is half configured
+    RollbackFailed { cause : Box<ProfileError>, rollback : Box<ProfileError> },
+    Io(Error),
+}
+
+impl fmt::Display for ProfileError {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Invalid(msg) => write!(fmt, "Invalid profile: {}", msg),
+            ProfileError::PowerConfig { status } => write!(fmt, "Module rejected the power configuration with status 0x{:02x}", status),
+            ProfileError::Rejected { step, status, msg } => write!(fmt, "{} failed with status 0x{:02x}: {}", step, status, msg),
+            ProfileError::Verification(msg) => write!(fmt, "Verification failed: {}", msg),
+            ProfileError::RollbackFailed { cause, rollback } => write!(fmt, "{}, restoring the previous profile failed: {}", cause, rollback),
+            ProfileError::Io(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl From<Error> for ProfileError {
+    fn from(err : Error) -> Self {
+        ProfileError::Io(err)
+    }
+}
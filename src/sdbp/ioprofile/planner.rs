@@ -0,0 +1,486 @@
+use std::fmt;
+use std::io::Error;
+
+use crate::datatypes::Descriptor;
+#[cfg(feature = "power-mgmt")]
+use crate::powermgmt::manager::PowerManager;
+use crate::sdbp::request::custom::io::*;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::custom::io::powermgmt::SetPowerConfig;
+
+/// Rail of a pin as sent in `SET_POWER_CONFIG`
+///
+/// The protocol only numbers the rails and limits the current of a pin, which supply of the
+/// slot feeds a rail and at which voltage depends on the module, see `RailConfig`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinRail {
+    Rail0,
+    Rail1,
+}
+
+impl PinRail {
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            PinRail::Rail0 => 0,
+            PinRail::Rail1 => 1,
+        }
+    }
+
+    /// Highest current limit of a single pin on this rail, as checked by `PowerMgmtBuilder::set_power_config`
+    pub fn max_current_ma(self) -> u16 {
+        match self {
+            PinRail::Rail0 => 300,
+            PinRail::Rail1 => 500,
+        }
+    }
+}
+
+/// Supply of the slot, as in the descriptor and the power management requests
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Supply {
+    Supply3v3,
+    Supply5v0,
+    Supply12v,
+}
+
+/// Supply feeding a rail and the voltage of the rail
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RailSupply {
+    pub supply : Supply,
+    pub voltage_mv : u32,
+}
+
+impl RailSupply {
+
+    fn power_mw(&self, current_ma : u16) -> u32 {
+        self.voltage_mv * current_ma as u32 / 1000
+    }
+}
+
+/// Hardware of the rails of a module, taken from its documentation
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RailConfig {
+    pub rail0 : RailSupply,
+    pub rail1 : RailSupply,
+}
+
+impl RailConfig {
+
+    pub fn get(&self, rail : PinRail) -> RailSupply {
+        match rail {
+            PinRail::Rail0 => self.rail0,
+            PinRail::Rail1 => self.rail1,
+        }
+    }
+}
+
+/// Power in mW per supply of the slot, as in the descriptor and the power management requests
+#[derive(Debug,Clone,Copy,Default,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RailPower {
+    pub power_3v3 : u32,
+    pub power_5v0 : u32,
+    pub power_12v : u32,
+}
+
+impl RailPower {
+
+    fn add(&mut self, supply : Supply, power : u32) {
+        match supply {
+            Supply::Supply3v3 => self.power_3v3 += power,
+            Supply::Supply5v0 => self.power_5v0 += power,
+            Supply::Supply12v => self.power_12v += power,
+        }
+    }
+
+    fn get(&self, supply : Supply) -> u32 {
+        match supply {
+            Supply::Supply3v3 => self.power_3v3,
+            Supply::Supply5v0 => self.power_5v0,
+            Supply::Supply12v => self.power_12v,
+        }
+    }
+}
+
+/// Load connected to a pin, `rail` is chosen by the planner if not given
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PinLoad {
+    pub name : String,
+    pub pin : PinId,
+    pub current_ma : u16,
+    #[serde(default)]
+    pub rail : Option<PinRail>,
+}
+
+impl PinLoad {
+
+    pub fn new(name : &str, pin : PinId, current_ma : u16) -> PinLoad {
+        PinLoad { name : name.to_string(), pin, current_ma, rail : None }
+    }
+
+    pub fn rail(mut self, rail : PinRail) -> PinLoad {
+        self.rail = Some(rail);
+        self
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PlannedPin {
+    pub pin : PinId,
+    /// Name of the load, `None` for unused pins which get a limit of 0 mA
+    pub load : Option<String>,
+    pub rail : PinRail,
+    pub current_limit_ma : u16,
+}
+
+/// Rail assignment of all pins and the resulting power per supply
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PowerPlan {
+    pub pins : Vec<PlannedPin>,
+    pub power : RailPower,
+}
+
+impl PowerPlan {
+
+    /// `(rail, current limit)` ordered by pin, as taken by `PowerMgmtBuilder::set_power_config`
+    pub fn power_config(&self) -> Vec<(u8,u16)> {
+        self.pins.iter().map(|pin| (pin.rail.to_byte(), pin.current_limit_ma)).collect()
+    }
+}
+
+/// Constraint a plan does not meet
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum PlanViolation {
+    /// More than one load on the pin
+    PinInUse { pin : PinId, loads : Vec<String> },
+    /// The load draws more than a pin of the rail supplies, `rail` is `None` if no rail fits
+    PinLimit { load : String, rail : Option<PinRail>, current_ma : u16 },
+    /// The loads of a supply exceed the maximum power of the descriptor
+    DescriptorLimit { supply : Supply, required_mw : u32, max_mw : u32 },
+    /// The power management denied the budget, `excess` is the power above the available budget
+    Budget { excess : RailPower },
+}
+
+impl fmt::Display for PlanViolation {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanViolation::PinInUse { pin, loads } => write!(fmt, "pin {} is used by {}", pin, loads.join(", ")),
+            PlanViolation::PinLimit { load, rail : Some(rail), current_ma } => {
+                write!(fmt, "{} needs {} mA, pins on {:?} supply up to {} mA", load, current_ma, rail, rail.max_current_ma())
+            }
+            PlanViolation::PinLimit { load, rail : None, current_ma } => write!(fmt, "{} needs {} mA, no rail supplies that much", load, current_ma),
+            PlanViolation::DescriptorLimit { supply, required_mw, max_mw } => write!(fmt, "{:?} needs {} mW, the module allows {} mW", supply, required_mw, max_mw),
+            PlanViolation::Budget { excess } => write!(fmt, "power budget exceeded by {} mW on 3V3, {} mW on 5V0 and {} mW on 12V",
+                                                       excess.power_3v3, excess.power_5v0, excess.power_12v),
+        }
+    }
+}
+
+/// Outcome of a dry run, `plan` is the assignment that was checked
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PlanReport {
+    pub plan : PowerPlan,
+    pub violations : Vec<PlanViolation>,
+}
+
+impl PlanReport {
+
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    /// The plan violates a constraint, nothing was sent to the module
+    Rejected(PlanReport),
+    /// `SET_POWER_CONFIG` failed with a non-zero status
+    Module { status : u8 },
+    Io(Error),
+}
+
+impl fmt::Display for PlanError {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Rejected(report) => {
+                let violations = report.violations.iter().map(|violation| violation.to_string()).collect::<Vec<String>>();
+                write!(fmt, "Power plan rejected: {}", violations.join("; "))
+            }
+            PlanError::Module { status } => write!(fmt, "Module rejected the power configuration with status 0x{:02x}", status),
+            PlanError::Io(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+impl From<Error> for PlanError {
+    fn from(err : Error) -> Self {
+        PlanError::Io(err)
+    }
+}
+
+/// Power budget of the carrier, implemented by `powermgmt::PowerManager`
+pub trait PowerBudget {
+
+    /// Requests `power` for `slot`, returns the excess per supply if the request was denied
+    fn request(&mut self, slot : u8, power : RailPower) -> Result<Option<RailPower>,Error>;
+
+    /// Tells the power management that the requested configuration was applied
+    fn finish(&mut self) -> Result<(),Error>;
+
+    /// Releases a granted request whose configuration was not applied
+    fn cancel(&mut self) -> Result<(),Error>;
+}
+
+#[cfg(feature = "power-mgmt")]
+impl PowerBudget for PowerManager {
+
+    fn request(&mut self, slot : u8, power : RailPower) -> Result<Option<RailPower>,Error> {
+        let clamp = |power : u32| power.min(u16::MAX as u32) as u16;
+        let response = PowerManager::request(self, slot, clamp(power.power_3v3), clamp(power.power_5v0), clamp(power.power_12v))
+            .map_err(|err| Error::new(std::io::ErrorKind::ConnectionRefused, err))?;
+        if response.successful {
+            return Ok(None);
+        }
+        Ok(Some(RailPower {
+            power_3v3 : response.to_much_power_3v3 as u32,
+            power_5v0 : response.to_much_power_5v0 as u32,
+            power_12v : response.to_much_power_12v as u32,
+        }))
+    }
+
+    fn finish(&mut self) -> Result<(),Error> {
+        let response = self.finish_request().map_err(|err| Error::new(std::io::ErrorKind::ConnectionRefused, err))?;
+        match response.successful {
+            true => Ok(()),
+            false => Err(Error::new(std::io::ErrorKind::InvalidData, response.message)),
+        }
+    }
+
+    fn cancel(&mut self) -> Result<(),Error> {
+        let response = self.cancel_request().map_err(|err| Error::new(std::io::ErrorKind::ConnectionRefused, err))?;
+        match response.successful {
+            true => Ok(()),
+            false => Err(Error::new(std::io::ErrorKind::InvalidData, response.message)),
+        }
+    }
+}
+
+/// Assigns the pin loads of an IO module to rails within the limits of its descriptor
+///
+/// Loads without a rail go to rail 0 as long as the current fits a pin and the descriptor
+/// limit of its supply is not reached, otherwise to rail 1. Larger loads are placed first.
+pub struct PowerPlanner {
+    rails : RailConfig,
+    max_power : RailPower,
+    loads : Vec<PinLoad>,
+}
+
+impl PowerPlanner {
+
+    pub fn new(desc : &Descriptor, rails : RailConfig) -> PowerPlanner {
+        let max_power = RailPower {
+            power_3v3 : desc.max_power_3v3() as u32,
+            power_5v0 : desc.max_power_5v() as u32,
+            power_12v : desc.max_power_12v() as u32,
+        };
+        PowerPlanner { rails, max_power, loads : vec![] }
+    }
+
+    pub fn load(mut self, load : PinLoad) -> PowerPlanner {
+        self.loads.push(load);
+        self
+    }
+
+    /// Plans the rails and checks every constraint except the power budget
+    pub fn dry_run(&self) -> PlanReport {
+        let mut violations = vec![];
+        let mut power = RailPower::default();
+        let mut pins : Vec<PlannedPin> = PinId::all()
+            .map(|pin| PlannedPin { pin, load : None, rail : PinRail::Rail0, current_limit_ma : 0 })
+            .collect();
+
+        for pin in PinId::all() {
+            let loads : Vec<String> = self.loads.iter().filter(|load| load.pin == pin).map(|load| load.name.clone()).collect();
+            if loads.len() > 1 {
+                violations.push(PlanViolation::PinInUse { pin, loads });
+            }
+        }
+
+        let mut loads : Vec<&PinLoad> = self.loads.iter().collect();
+        loads.sort_by_key(|load| (load.rail.is_none(), std::cmp::Reverse(load.current_ma)));
+        for load in loads {
+            let rail = match load.rail {
+                Some(rail) => rail,
+                None => {
+                    let fits = |rail : PinRail| {
+                        let supply = self.rails.get(rail);
+                        load.current_ma <= rail.max_current_ma()
+                            && power.get(supply.supply) + supply.power_mw(load.current_ma) <= self.max_power.get(supply.supply)
+                    };
+                    match [PinRail::Rail0, PinRail::Rail1].into_iter().find(|rail| fits(*rail)) {
+                        Some(rail) => rail,
+                        None if load.current_ma <= PinRail::Rail0.max_current_ma() => PinRail::Rail0,
+                        None => PinRail::Rail1,
+                    }
+                }
+            };
+            if load.current_ma > rail.max_current_ma() {
+                let rail = if load.rail.is_some() { Some(rail) } else { None };
+                violations.push(PlanViolation::PinLimit { load : load.name.clone(), rail, current_ma : load.current_ma });
+            }
+
+            let supply = self.rails.get(rail);
+            power.add(supply.supply, supply.power_mw(load.current_ma));
+            let pin = &mut pins[load.pin.value() as usize];
+            if pin.load.is_none() {
+                *pin = PlannedPin { pin : load.pin, load : Some(load.name.clone()), rail, current_limit_ma : load.current_ma };
+            }
+        }
+
+        for supply in [Supply::Supply3v3, Supply::Supply5v0, Supply::Supply12v] {
+            if power.get(supply) > self.max_power.get(supply) {
+                violations.push(PlanViolation::DescriptorLimit { supply, required_mw : power.get(supply), max_mw : self.max_power.get(supply) });
+            }
+        }
+        PlanReport { plan : PowerPlan { pins, power }, violations }
+    }
+
+    /// Checks the plan, requests the budget and sends `SET_POWER_CONFIG` with `transfer`
+    ///
+    /// The budget is cancelled if the configuration could not be applied.
+    pub fn apply<B, F>(&self, slot : u8, budget : &mut B, mut transfer : F) -> Result<PowerPlan,PlanError>
+        where B : PowerBudget, F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let mut report = self.dry_run();
+        if !report.is_ok() {
+            return Err(PlanError::Rejected(report));
+        }
+
+        if let Some(excess) = budget.request(slot, report.plan.power)? {
+            report.violations.push(PlanViolation::Budget { excess });
+            return Err(PlanError::Rejected(report));
+        }
+
+        let applied = IoBuilder::new().powermgmt().set_power_config(report.plan.power_config())
+            .and_then(&mut transfer)
+            .and_then(SetPowerConfig::from_raw)
+            .map_err(PlanError::Io)
+            .and_then(|response| match response.status {
+                0 => Ok(()),
+                status => Err(PlanError::Module { status }),
+            });
+        if let Err(err) = applied {
+            if let Err(cancel_err) = budget.cancel() {
+                warn!("Could not cancel power budget of slot {}: {}", slot, cancel_err);
+            }
+            return Err(err);
+        }
+        budget.finish()?;
+        Ok(report.plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::sdbp::request::custom::io::protocol::*;
+
+    struct Budget {
+        available : RailPower,
+        finished : bool,
+        cancelled : bool,
+    }
+
+    impl PowerBudget for Budget {
+
+        fn request(&mut self, _slot : u8, power : RailPower) -> Result<Option<RailPower>,Error> {
+            match power.power_12v > self.available.power_12v {
+                true => Ok(Some(RailPower { power_12v : power.power_12v - self.available.power_12v, ..RailPower::default() })),
+                false => Ok(None),
+            }
+        }
+
+        fn finish(&mut self) -> Result<(),Error> {
+            self.finished = true;
+            Ok(())
+        }
+
+        fn cancel(&mut self) -> Result<(),Error> {
+            self.cancelled = true;
+            Ok(())
+        }
+    }
+
+    fn pin(pin : u8) -> PinId {
+        PinId::new(pin).unwrap()
+    }
+
+    const RAILS : RailConfig = RailConfig {
+        rail0 : RailSupply { supply : Supply::Supply5v0, voltage_mv : 5000 },
+        rail1 : RailSupply { supply : Supply::Supply12v, voltage_mv : 12000 },
+    };
+
+    #[test]
+    fn loads_are_placed_within_the_limits() {
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_max_power_5v(2000);
+        desc.set_max_power_12v(6000);
+
+        let planner = PowerPlanner::new(&desc, RAILS)
+            .load(PinLoad::new("sensor", pin(0), 100))
+            .load(PinLoad::new("relay", pin(1), 250))
+            .load(PinLoad::new("valve", pin(2), 400))
+            .load(PinLoad::new("lamp", pin(3), 50).rail(PinRail::Rail1));
+        let report = planner.dry_run();
+        assert!(report.is_ok());
+        assert_eq!(report.plan.power_config(), vec![(0, 100), (0, 250), (1, 400), (1, 50), (0, 0), (0, 0)]);
+        assert_eq!(report.plan.power, RailPower { power_3v3 : 0, power_5v0 : 1750, power_12v : 5400 });
+
+        let report = planner.load(PinLoad::new("heater", pin(3), 600)).dry_run();
+        assert!(report.violations.contains(&PlanViolation::PinInUse { pin : pin(3), loads : vec!["lamp".to_string(), "heater".to_string()] }));
+        assert!(report.violations.contains(&PlanViolation::PinLimit { load : "heater".to_string(), rail : None, current_ma : 600 }));
+        assert!(report.violations.iter().any(|violation| matches!(violation, PlanViolation::DescriptorLimit { supply : Supply::Supply12v, .. })));
+
+        let planner = PowerPlanner::new(&desc, RAILS).load(PinLoad::new("valve", pin(2), 400));
+        let mut budget = Budget { available : RailPower { power_12v : 1000, ..RailPower::default() }, finished : false, cancelled : false };
+        match planner.apply(1, &mut budget, |_| panic!("nothing may be sent")) {
+            Err(PlanError::Rejected(report)) => assert_eq!(report.violations, vec![PlanViolation::Budget { excess : RailPower { power_12v : 3800, ..RailPower::default() } }]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        budget.available.power_12v = 5000;
+        let mut sent = vec![];
+        let plan = planner.apply(1, &mut budget, |frame| {
+            sent = frame;
+            Ok(vec![CLASS_ID, classes::power_management_class::ID, classes::power_management_class::operation_code::SET_POWER_CONFIG, 0])
+        }).unwrap();
+        assert_eq!(sent[..6], [CLASS_ID, classes::power_management_class::ID, classes::power_management_class::operation_code::SET_POWER_CONFIG, 0, 0, 0]);
+        assert_eq!(sent[9..12], [1, 0x01, 0x90]);
+        assert_eq!(plan.power.power_12v, 4800);
+        assert!(budget.finished);
+        assert!(!budget.cancelled);
+    }
+
+    #[test]
+    fn failed_power_config_cancels_the_budget() {
+        let mut desc = Descriptor::new(PathBuf::new());
+        desc.set_max_power_5v(2000);
+        let planner = PowerPlanner::new(&desc, RAILS).load(PinLoad::new("sensor", pin(0), 100));
+
+        let mut budget = Budget { available : RailPower::default(), finished : false, cancelled : false };
+        let rejected = planner.apply(1, &mut budget, |frame| Ok(vec![frame[0], frame[1], frame[2], classes::power_management_class::error_code::INVALID_VALUE]));
+        assert!(matches!(rejected, Err(PlanError::Module { status : classes::power_management_class::error_code::INVALID_VALUE })));
+        assert!(budget.cancelled && !budget.finished);
+
+        let mut budget = Budget { available : RailPower::default(), finished : false, cancelled : false };
+        let failed = planner.apply(1, &mut budget, |_| Err(Error::new(std::io::ErrorKind::TimedOut, "no response")));
+        assert!(matches!(failed, Err(PlanError::Io(_))));
+        assert!(budget.cancelled && !budget.finished);
+    }
+}
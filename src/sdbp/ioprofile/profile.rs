@@ -0,0 +1,168 @@
+use std::path::Path;
+
+use crate::sdbp::request::custom::io::*;
+use super::ProfileError;
+
+/// Lowest analog threshold, sent with a disabled trigger if a pin has no threshold
+pub const MIN_THRESHOLD_MV : u16 = 50;
+
+/// Power supply of a pin, see `PowerMgmtBuilder::set_power_config`
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PinPower {
+    /// `0` or `1`
+    pub rail : u8,
+    /// mA, up to 300 on rail `0` and 500 on rail `1`
+    pub current_limit_ma : u16,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AnalogThreshold {
+    pub threshold_mv : u16,
+    pub trigger : Trigger,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DigitalInterrupt {
+    #[serde(default)]
+    pub debounce_ms : u16,
+    pub trigger : Trigger,
+}
+
+/// Function of a pin, thresholds, interrupts and counters that are not given are disabled
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "function", rename_all = "lowercase")]
+pub enum PinFunction {
+    Analog {
+        #[serde(default)]
+        threshold : Option<AnalogThreshold>,
+    },
+    Digital {
+        #[serde(default)]
+        interrupt : Option<DigitalInterrupt>,
+        #[serde(default)]
+        counter : Option<CounterState>,
+    },
+    Output {
+        output : OutputMode,
+    },
+}
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PinProfile {
+    pub pin : PinId,
+    pub power : PinPower,
+    #[serde(flatten)]
+    pub function : PinFunction,
+}
+
+/// Complete configuration of an IO module, every pin has to be given exactly once
+///
+/// In TOML every pin is a `[[pins]]` table:
+///
+/// ```toml
+/// [[pins]]
+/// pin = 0
+/// power = { rail = 0, current_limit_ma = 100 }
+/// function = "digital"
+/// interrupt = { debounce_ms = 10, trigger = "rising" }
+/// ```
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IoProfile {
+    pub pins : Vec<PinProfile>,
+}
+
+/// A single frame sent by `IoProfile::apply`
+#[derive(Debug,Clone,PartialEq)]
+pub struct ProfileStep {
+    /// e.g. `pin 2 input mode`, used in error messages
+    pub name : String,
+    pub frame : Vec<u8>,
+}
+
+impl IoProfile {
+
+    /// Loads a `.toml` file as TOML and everything else as JSON
+    pub fn load(path : &Path) -> Result<IoProfile,ProfileError> {
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => IoProfile::from_toml(&content),
+            _ => IoProfile::from_json(&content),
+        }
+    }
+
+    pub fn from_json(content : &str) -> Result<IoProfile,ProfileError> {
+        serde_json::from_str(content).map_err(|err| ProfileError::Invalid(err.to_string()))
+    }
+
+    pub fn from_toml(content : &str) -> Result<IoProfile,ProfileError> {
+        toml::from_str(content).map_err(|err| ProfileError::Invalid(err.to_string()))
+    }
+
+    pub fn pin(&self, pin : PinId) -> Option<&PinProfile> {
+        self.pins.iter().find(|profile| profile.pin == pin)
+    }
+
+    /// `(rail, current limit)` of all pins ordered by pin, as taken by the power management builder
+    pub fn power_config(&self) -> Vec<(u8,u16)> {
+        PinId::all().filter_map(|pin| self.pin(pin)).map(|profile| (profile.power.rail, profile.power.current_limit_ma)).collect()
+    }
+
+    /// Checks the profile without a module, `steps` fails for the same profiles
+    pub fn validate(&self) -> Result<(),ProfileError> {
+        self.steps().map(|_| ())
+    }
+
+    /// Frames that configure the module, power first, then inputs and outputs last
+    pub fn steps(&self) -> Result<Vec<ProfileStep>,ProfileError> {
+        let invalid = |name : &str, err : std::io::Error| ProfileError::Invalid(format!("{}: {}", name, err));
+
+        if self.pins.len() != PIN_COUNT as usize {
+            return Err(ProfileError::Invalid(format!("{} pins given, the module has {}", self.pins.len(), PIN_COUNT)));
+        }
+        if let Some(pin) = PinId::all().find(|pin| self.pin(*pin).is_none()) {
+            return Err(ProfileError::Invalid(format!("pin {} missing", pin)));
+        }
+
+        let mut steps = vec![];
+        let mut step = |name : String, frame : Result<Vec<u8>,std::io::Error>| -> Result<(),ProfileError> {
+            let frame = frame.map_err(|err| invalid(&name, err))?;
+            steps.push(ProfileStep { name, frame });
+            Ok(())
+        };
+
+        step("power config".to_string(), IoBuilder::new().powermgmt().set_power_config(self.power_config()))?;
+
+        let pins = || PinId::all().filter_map(|pin| self.pin(pin));
+        for profile in pins() {
+            let mode = match profile.function {
+                PinFunction::Analog { .. } => InputMode::Analog,
+                PinFunction::Digital { .. } => InputMode::Digital,
+                PinFunction::Output { .. } => continue,
+            };
+            step(format!("pin {} input mode", profile.pin), IoBuilder::new().input().set_pin_input_mode(profile.pin, mode))?;
+        }
+
+        for profile in pins() {
+            let pin = profile.pin;
+            match profile.function {
+                PinFunction::Analog { threshold } => {
+                    let threshold = threshold.unwrap_or(AnalogThreshold { threshold_mv : MIN_THRESHOLD_MV, trigger : Trigger::Disabled });
+                    step(format!("pin {} analog threshold", pin), IoBuilder::new().input().set_pin_analog_threshold(pin, threshold.threshold_mv, threshold.trigger))?;
+                }
+                PinFunction::Digital { interrupt, counter } => {
+                    let interrupt = interrupt.unwrap_or(DigitalInterrupt { debounce_ms : 0, trigger : Trigger::Disabled });
+                    step(format!("pin {} digital interrupt", pin), IoBuilder::new().input().set_pin_digital_interrupt(pin, interrupt.debounce_ms, interrupt.trigger))?;
+                    step(format!("pin {} digital counter", pin), IoBuilder::new().input().set_pin_digital_counter(pin, counter.unwrap_or(CounterState::Disabled)))?;
+                }
+                PinFunction::Output { .. } => (),
+            }
+        }
+
+        for profile in pins() {
+            if let PinFunction::Output { output } = profile.function {
+                step(format!("pin {} output", profile.pin), IoBuilder::new().output().set_pin_output(profile.pin, output))?;
+            }
+        }
+        Ok(steps)
+    }
+}
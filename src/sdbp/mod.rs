@@ -1,7 +1,21 @@
 pub mod response;
 pub mod request;
+pub mod dissector;
+#[cfg(feature = "firmware-experimental")]
+pub mod firmware;
+#[cfg(feature = "io")]
+pub mod ioprofile;
+#[cfg(feature = "io")]
+pub mod sampling;
+#[cfg(feature = "io")]
+pub mod ioevents;
+#[cfg(feature = "io")]
+pub mod iooutput;
+#[cfg(feature = "io")]
+pub mod calibration;
 
 pub use request::corebuilder::*;
 pub use request::custombuilder::*;
 pub use request::framebuilder::*;
 pub use request::standardbuilder::*;
+pub use dissector::*;
@@ -0,0 +1,78 @@
+use super::protocol::*;
+use std::io::{Error, ErrorKind};
+
+pub struct BootloaderBuilder {
+    frame: Vec<u8>,
+}
+
+impl Default for BootloaderBuilder {
+    fn default() -> Self {
+        BootloaderBuilder::new()
+    }
+}
+
+impl BootloaderBuilder {
+
+    pub fn new() -> BootloaderBuilder {
+        let frame = vec![CLASS_ID, classes::bootloader::ID];
+        BootloaderBuilder { frame }
+    }
+
+    pub fn get_info(mut self) -> Result<Vec<u8>,Error> {
+        self.frame.push(classes::bootloader::operation_code::GET_INFO);
+        Ok(self.frame)
+    }
+
+    pub fn erase(mut self, address : u32, length : u32) -> Result<Vec<u8>,Error> {
+        if length == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Erase length must not be zero"));
+        }
+        self.frame.push(classes::bootloader::operation_code::ERASE);
+        self.frame.extend_from_slice(&address.to_be_bytes());
+        self.frame.extend_from_slice(&length.to_be_bytes());
+        Ok(self.frame)
+    }
+
+    pub fn write(mut self, address : u32, data : &[u8]) -> Result<Vec<u8>,Error> {
+        if data.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Write data must not be empty"));
+        }
+        self.frame.push(classes::bootloader::operation_code::WRITE);
+        self.frame.extend_from_slice(&address.to_be_bytes());
+        self.frame.extend_from_slice(data);
+        Ok(self.frame)
+    }
+
+    /// Requests the CRC of a flash region, see `sdbp::firmware::FIRMWARE_CRC`
+    pub fn verify(mut self, address : u32, length : u32) -> Result<Vec<u8>,Error> {
+        if length == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Verify length must not be zero"));
+        }
+        self.frame.push(classes::bootloader::operation_code::VERIFY);
+        self.frame.extend_from_slice(&address.to_be_bytes());
+        self.frame.extend_from_slice(&length.to_be_bytes());
+        Ok(self.frame)
+    }
+}
+
+#[allow(unused_imports,unused_macros)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_cmd_erase() {
+        let expected : Vec<u8> = vec![CLASS_ID, classes::bootloader::ID, classes::bootloader::operation_code::ERASE, 0x08, 0x00, 0x40, 0x00, 0x00, 0x00, 0x10, 0x00];
+        let result = BootloaderBuilder::new().erase(0x0800_4000, 0x1000).unwrap();
+        assert_eq!(expected, result, "Failed to build ERASE");
+        assert!(BootloaderBuilder::new().erase(0x0800_4000, 0).is_err());
+    }
+
+    #[test]
+    fn test_cmd_write() {
+        let expected : Vec<u8> = vec![CLASS_ID, classes::bootloader::ID, classes::bootloader::operation_code::WRITE, 0x00, 0x00, 0x01, 0x00, 0xAB, 0xCD];
+        let result = BootloaderBuilder::new().write(0x100, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(expected, result, "Failed to build WRITE");
+        assert!(BootloaderBuilder::new().write(0x100, &[]).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+use super::protocol::*;
+
+/// Frames without effect, e.g. to check that a module answers at all
+pub struct DummyBuilder {
+    frame: Vec<u8>,
+}
+
+impl Default for DummyBuilder {
+    fn default() -> Self {
+        DummyBuilder::new()
+    }
+}
+
+impl DummyBuilder {
+
+    pub fn new() -> DummyBuilder {
+        let frame = vec![CLASS_ID, classes::dummy::ID];
+        DummyBuilder { frame }
+    }
+
+    pub fn dummy(mut self) -> Vec<u8> {
+        self.frame.push(classes::dummy::operation_code::DUMMY);
+        self.frame
+    }
+}
@@ -1,5 +1,8 @@
+#[cfg(feature = "firmware-experimental")]
+pub mod bootloader;
 pub mod control;
 pub mod descriptor;
+pub mod dummy;
 pub mod notification;
 pub mod protocol;
-
+pub mod wait;
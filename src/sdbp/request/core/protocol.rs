@@ -3,6 +3,10 @@ pub const CLASS_ID : u8 = 0x01;
 #[allow(dead_code)]
 pub mod classes {
 
+    /// Sent by a module instead of the response if it could not process the frame at all,
+    /// the return code takes the place of the operation code.
+    ///
+    /// The meaning of the return codes is not specified, they are passed on as raw codes.
     pub mod transaction_error {
         pub const ID : u8 = 0x01;
     }
@@ -55,8 +59,10 @@ pub mod classes {
 
     }
 
+    /// Answered by a busy module, the request has to be sent again later
     pub mod wait {
         pub const ID : u8 = 0x05;
+
         pub mod operation_code {
             pub const RFU : u8   = 0x00;
             pub const ERROR : u8 = 0x01;
@@ -80,6 +86,32 @@ pub mod classes {
         }
     }
 
+    /// Only answered by modules in bootloader mode, see `control::operation_code::MODE_BOOTLOADER`
+    ///
+    /// Experimental: the ids, return codes and payloads are not yet checked against the module firmware.
+    #[cfg(feature = "firmware-experimental")]
+    pub mod bootloader {
+        pub const ID : u8 = 0x07;
+
+        pub mod operation_code {
+            pub const RFU : u8      = 0x00;
+            pub const ERROR : u8    = 0x01;
+            pub const GET_INFO : u8 = 0x02;
+            pub const ERASE : u8    = 0x03;
+            pub const WRITE : u8    = 0x04;
+            pub const VERIFY : u8   = 0x05;
+        }
+
+        pub mod return_code {
+            pub const SUCCESS: u8 = 0x00;
+            pub const COMMAND_INVALID: u8 = 0x01;
+            pub const WRONG_LENGTH: u8 = 0x02;
+            pub const INVALID_ADDRESS: u8 = 0x03;
+            pub const FLASH_ERROR: u8 = 0x04;
+            pub const NOT_ERASED: u8 = 0x05;
+        }
+    }
+
 }
 
 
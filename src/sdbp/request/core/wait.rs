@@ -0,0 +1,37 @@
+use super::protocol::*;
+
+pub struct WaitBuilder {
+    frame: Vec<u8>,
+}
+
+impl Default for WaitBuilder {
+    fn default() -> Self {
+        WaitBuilder::new()
+    }
+}
+
+impl WaitBuilder {
+
+    pub fn new() -> WaitBuilder {
+        let frame = vec![CLASS_ID, classes::wait::ID];
+        WaitBuilder { frame }
+    }
+
+    pub fn wait(mut self) -> Vec<u8> {
+        self.frame.push(classes::wait::operation_code::WAIT);
+        self.frame
+    }
+}
+
+#[allow(unused_imports,unused_macros)]
+mod test {
+
+    use super::*;
+    use crate::sdbp::request::core::dummy::DummyBuilder;
+
+    #[test]
+    fn test_cmd_wait_and_dummy() {
+        assert_eq!(vec![CLASS_ID, classes::wait::ID, classes::wait::operation_code::WAIT], WaitBuilder::new().wait(), "Failed to build WAIT");
+        assert_eq!(vec![CLASS_ID, classes::dummy::ID, classes::dummy::operation_code::DUMMY], DummyBuilder::new().dummy(), "Failed to build DUMMY");
+    }
+}
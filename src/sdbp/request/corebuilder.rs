@@ -1,6 +1,10 @@
+#[cfg(feature = "firmware-experimental")]
+use crate::sdbp::request::core::bootloader::BootloaderBuilder;
 use crate::sdbp::request::core::control::ControlBuilder;
 use crate::sdbp::request::core::descriptor::DescriptorBuilder;
+use crate::sdbp::request::core::dummy::DummyBuilder;
 use crate::sdbp::request::core::notification::NotificationBuilder;
+use crate::sdbp::request::core::wait::WaitBuilder;
 
 pub struct CoreBuilder {}
 
@@ -13,4 +17,8 @@ impl CoreBuilder {
     }
     pub fn descriptor(self) -> DescriptorBuilder { DescriptorBuilder::new() }
     pub fn notification(self) -> NotificationBuilder { NotificationBuilder::new() }
+    #[cfg(feature = "firmware-experimental")]
+    pub fn bootloader(self) -> BootloaderBuilder { BootloaderBuilder::new() }
+    pub fn dummy(self) -> DummyBuilder { DummyBuilder::new() }
+    pub fn wait(self) -> WaitBuilder { WaitBuilder::new() }
 }
\ No newline at end of file
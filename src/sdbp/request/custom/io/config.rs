@@ -0,0 +1,213 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+/// Number of pins of an IO module
+pub const PIN_COUNT : u8 = 6;
+
+/// Pin of an IO module, `0` to `PIN_COUNT - 1` in the order of the power configuration
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,PartialOrd,Ord, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct PinId(u8);
+
+impl PinId {
+
+    pub fn new(pin : u8) -> Result<PinId,Error> {
+        if pin >= PIN_COUNT {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid pin {} (0-{})", pin, PIN_COUNT - 1)));
+        }
+        Ok(PinId(pin))
+    }
+
+    pub fn all() -> impl Iterator<Item = PinId> {
+        (0..PIN_COUNT).map(PinId)
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for PinId {
+    type Error = Error;
+
+    fn try_from(pin : u8) -> Result<PinId,Error> {
+        PinId::new(pin)
+    }
+}
+
+impl From<PinId> for u8 {
+    fn from(pin : PinId) -> u8 {
+        pin.0
+    }
+}
+
+impl FromStr for PinId {
+    type Err = Error;
+
+    fn from_str(value : &str) -> Result<PinId,Error> {
+        let pin = value.parse::<u8>().map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Invalid pin {}", value)))?;
+        PinId::new(pin)
+    }
+}
+
+impl fmt::Display for PinId {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Mode of an input pin as sent with `SET_INPUT_MODE`
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMode {
+    /// Voltage and current measurement, thresholds are set with `set_pin_analog_threshold`
+    Analog,
+    /// Logic level input, interrupts and the counter are set with `set_pin_digital_interrupt` and `set_pin_digital_counter`
+    Digital,
+}
+
+impl InputMode {
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            InputMode::Analog => 1,
+            InputMode::Digital => 2,
+        }
+    }
+}
+
+impl FromStr for InputMode {
+    type Err = Error;
+
+    fn from_str(value : &str) -> Result<InputMode,Error> {
+        match value {
+            "analog" | "1" => Ok(InputMode::Analog),
+            "digital" | "2" => Ok(InputMode::Digital),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Invalid input mode")),
+        }
+    }
+}
+
+/// Edge an analog threshold or a digital interrupt triggers on
+///
+/// `Pulse` triggers on both edges and is only supported by digital interrupts.
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Disabled,
+    Rising,
+    Falling,
+    Pulse,
+}
+
+impl Trigger {
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Trigger::Disabled => 0,
+            Trigger::Rising => 1,
+            Trigger::Falling => 2,
+            Trigger::Pulse => 3,
+        }
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = Error;
+
+    fn from_str(value : &str) -> Result<Trigger,Error> {
+        match value {
+            "disabled" => Ok(Trigger::Disabled),
+            "rising" => Ok(Trigger::Rising),
+            "falling" => Ok(Trigger::Falling),
+            "pulse" => Ok(Trigger::Pulse),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Invalid trigger")),
+        }
+    }
+}
+
+/// State of the pulse counter of a digital input
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CounterState {
+    Disabled,
+    Enabled,
+}
+
+impl CounterState {
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CounterState::Disabled => 0,
+            CounterState::Enabled => 1,
+        }
+    }
+}
+
+impl FromStr for CounterState {
+    type Err = Error;
+
+    fn from_str(value : &str) -> Result<CounterState,Error> {
+        match value {
+            "disabled" => Ok(CounterState::Disabled),
+            "enabled" => Ok(CounterState::Enabled),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Invalid state")),
+        }
+    }
+}
+
+/// Mode of an output pin together with its parameters as sent with `SET_OUTPUT`
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// Static level, `true` drives the pin high
+    Digital(bool),
+    /// `time_on` and `period` are counted in ticks of the prescaled clock
+    Pwm { prescaler : u16, time_on : u32, period : u32 },
+}
+
+impl OutputMode {
+
+    pub fn mode_byte(&self) -> u8 {
+        match self {
+            OutputMode::Digital(_) => 1,
+            OutputMode::Pwm { .. } => 2,
+        }
+    }
+
+    /// Rejects PWM settings the module can not generate
+    pub fn validate(&self) -> Result<(),Error> {
+        match *self {
+            OutputMode::Digital(_) => Ok(()),
+            OutputMode::Pwm { period : 0, .. } => Err(Error::new(ErrorKind::InvalidInput, "Invalid PWM period (> 0)")),
+            OutputMode::Pwm { time_on, period, .. } if time_on > period => {
+                Err(Error::new(ErrorKind::InvalidInput, "Invalid PWM time_on, must not exceed the period"))
+            }
+            OutputMode::Pwm { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_validates_the_pin_config() {
+        assert_eq!(PinId::new(5).unwrap().value(), 5);
+        assert!(PinId::new(PIN_COUNT).is_err());
+        assert!("7".parse::<PinId>().is_err());
+        assert_eq!(PinId::all().count(), PIN_COUNT as usize);
+
+        assert_eq!("pulse".parse::<Trigger>().unwrap().to_byte(), 3);
+        assert!("both".parse::<Trigger>().is_err());
+        assert_eq!("digital".parse::<InputMode>().unwrap().to_byte(), 2);
+        assert_eq!("enabled".parse::<CounterState>().unwrap(), CounterState::Enabled);
+
+        assert!(OutputMode::Pwm { prescaler : 1, time_on : 10, period : 20 }.validate().is_ok());
+        assert!(OutputMode::Pwm { prescaler : 1, time_on : 30, period : 20 }.validate().is_err());
+        assert!(OutputMode::Pwm { prescaler : 1, time_on : 0, period : 0 }.validate().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use super::config::*;
 use super::protocol::*;
 use std::io::Error;
 use std::io::ErrorKind;
@@ -12,31 +13,26 @@ impl InputBuilder {
         InputBuilder { frame }
     }
 
-    pub fn set_input_mode(mut self, pin_nr: u8, mode: u8) -> Result<Vec<u8>, Error> {
-        match mode {
-            1 | 2 => (),
-            _ => return Err(Error::new(ErrorKind::Other, "Invalid input mode")),
-        }
-
+    pub fn set_pin_input_mode(mut self, pin: PinId, mode: InputMode) -> Result<Vec<u8>, Error> {
         self.frame
             .push(classes::input_class::operation_code::SET_INPUT_MODE);
-        self.frame.push(pin_nr);
-        self.frame.push(mode);
+        self.frame.push(pin.value());
+        self.frame.push(mode.to_byte());
         Ok(self.frame)
     }
 
-    pub fn set_analog_threshold(
+    pub fn set_pin_analog_threshold(
         mut self,
-        pin_nr: u8,
+        pin: PinId,
         threshold_mv: u16,
-        trigger: &String,
+        trigger: Trigger,
     ) -> Result<Vec<u8>, Error> {
-        let direction = match trigger.as_str() {
-            "disabled" => 0,
-            "rising" => 1,
-            "falling" => 2,
-            _ => return Err(Error::new(ErrorKind::Other, "Invalid trigger")),
-        };
+        if trigger == Trigger::Pulse {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Pulse trigger is only supported by digital interrupts",
+            ));
+        }
 
         match threshold_mv {
             50..=25000 => (),
@@ -50,27 +46,19 @@ impl InputBuilder {
 
         self.frame
             .push(classes::input_class::operation_code::SET_ANALOG_THRESHOLD);
-        self.frame.push(pin_nr);
+        self.frame.push(pin.value());
         self.frame.push((threshold_mv >> 8) as u8);
         self.frame.push(threshold_mv as u8);
-        self.frame.push(direction);
+        self.frame.push(trigger.to_byte());
         Ok(self.frame)
     }
 
-    pub fn set_digital_interrupt(
+    pub fn set_pin_digital_interrupt(
         mut self,
-        pin_nr: u8,
+        pin: PinId,
         debounce_time_ms: u16,
-        trigger: &String,
+        trigger: Trigger,
     ) -> Result<Vec<u8>, Error> {
-        let trigger_direction = match trigger.as_str() {
-            "disabled" => 0,
-            "rising" => 1,
-            "falling" => 2,
-            "pulse" => 3,
-            _ => return Err(Error::new(ErrorKind::Other, "Invalid trigger")),
-        };
-
         match debounce_time_ms {
             0..=1000 => (),
             _ => {
@@ -83,27 +71,49 @@ impl InputBuilder {
 
         self.frame
             .push(classes::input_class::operation_code::SET_DIGITAL_INTERRUPT);
-        self.frame.push(pin_nr);
+        self.frame.push(pin.value());
         self.frame.push((debounce_time_ms >> 8) as u8);
         self.frame.push(debounce_time_ms as u8);
-        self.frame.push(trigger_direction);
+        self.frame.push(trigger.to_byte());
         Ok(self.frame)
     }
 
-    pub fn set_digital_counter(mut self, pin_nr: u8, state: &String) -> Result<Vec<u8>, Error> {
-        let counter_state = match state.as_str() {
-            "disabled" => 0,
-            "enabled" => 1,
-            _ => return Err(Error::new(ErrorKind::Other, "Invalid state")),
-        };
-
+    pub fn set_pin_digital_counter(mut self, pin: PinId, state: CounterState) -> Result<Vec<u8>, Error> {
         self.frame
             .push(classes::input_class::operation_code::SET_DIGITAL_COUNTER);
-        self.frame.push(pin_nr);
-        self.frame.push(counter_state);
+        self.frame.push(pin.value());
+        self.frame.push(state.to_byte());
         Ok(self.frame)
     }
 
+    #[deprecated(note = "use set_pin_input_mode with a PinId and an InputMode")]
+    pub fn set_input_mode(self, pin_nr: u8, mode: u8) -> Result<Vec<u8>, Error> {
+        let mode = match mode {
+            1 => InputMode::Analog,
+            2 => InputMode::Digital,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "Invalid input mode")),
+        };
+        self.set_pin_input_mode(PinId::new(pin_nr)?, mode)
+    }
+
+    #[deprecated(note = "use set_pin_analog_threshold with a PinId and a Trigger")]
+    #[allow(clippy::ptr_arg)]
+    pub fn set_analog_threshold(self, pin_nr: u8, threshold_mv: u16, trigger: &String) -> Result<Vec<u8>, Error> {
+        self.set_pin_analog_threshold(PinId::new(pin_nr)?, threshold_mv, trigger.parse()?)
+    }
+
+    #[deprecated(note = "use set_pin_digital_interrupt with a PinId and a Trigger")]
+    #[allow(clippy::ptr_arg)]
+    pub fn set_digital_interrupt(self, pin_nr: u8, debounce_time_ms: u16, trigger: &String) -> Result<Vec<u8>, Error> {
+        self.set_pin_digital_interrupt(PinId::new(pin_nr)?, debounce_time_ms, trigger.parse()?)
+    }
+
+    #[deprecated(note = "use set_pin_digital_counter with a PinId and a CounterState")]
+    #[allow(clippy::ptr_arg)]
+    pub fn set_digital_counter(self, pin_nr: u8, state: &String) -> Result<Vec<u8>, Error> {
+        self.set_pin_digital_counter(PinId::new(pin_nr)?, state.parse()?)
+    }
+
     pub fn get_values(mut self) -> Result<Vec<u8>, Error> {
         self.frame
             .push(classes::input_class::operation_code::GET_VALUES);
@@ -116,3 +126,17 @@ impl InputBuilder {
         Ok(self.frame)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_builders_match_the_typed_ones() {
+        let pin = PinId::new(2).unwrap();
+        assert_eq!(InputBuilder::new().set_digital_counter(2, &"enabled".to_string()).unwrap(),
+            InputBuilder::new().set_pin_digital_counter(pin, CounterState::Enabled).unwrap());
+        assert!(InputBuilder::new().set_input_mode(PIN_COUNT, 1).is_err());
+    }
+}
@@ -3,10 +3,15 @@ use crate::sdbp::request::custom::io::output::OutputBuilder;
 use crate::sdbp::request::custom::io::powermgmt::PowerMgmtBuilder;
 
 pub mod protocol;
+pub mod config;
+pub mod pwm;
 mod input;
 mod output;
 mod powermgmt;
 
+pub use config::*;
+pub use pwm::*;
+
 pub struct IoBuilder{}
 
 impl IoBuilder {
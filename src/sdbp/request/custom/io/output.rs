@@ -1,3 +1,4 @@
+use super::config::*;
 use super::protocol::*;
 use std::io::Error;
 use std::io::ErrorKind;
@@ -13,50 +14,57 @@ impl OutputBuilder {
         OutputBuilder{frame}
     }
 
-    pub fn set_output(mut self, pin_nr : u8, mode : u8, state: u8 ) -> Result<Vec<u8>,Error> {
+    pub fn set_pin_output(mut self, pin : PinId, mode : OutputMode) -> Result<Vec<u8>,Error> {
+        mode.validate()?;
 
+        self.frame.push(classes::output_class::operation_code::SET_OUTPUT);
+        self.frame.push(pin.value());
+        self.frame.push(mode.mode_byte());
         match mode {
-            1 => (),
-            _ => return Err(Error::new(ErrorKind::Other,"Invalid input mode")),
-        }
-
-        match state {
-            0 | 1 => (),
-            _ => return Err(Error::new(ErrorKind::Other,"Invalid state")),
+            OutputMode::Digital(state) => self.frame.push(state as u8),
+            OutputMode::Pwm { prescaler, time_on, period } => {
+                self.frame.extend_from_slice(&prescaler.to_be_bytes());
+                self.frame.extend_from_slice(&time_on.to_be_bytes());
+                self.frame.extend_from_slice(&period.to_be_bytes());
+            }
         }
-
-        self.frame.push(classes::output_class::operation_code::SET_OUTPUT);
-        self.frame.push(pin_nr);
-        self.frame.push(mode);
-        self.frame.push(state);
         Ok(self.frame)
     }
 
-    pub fn set_output_pwm(mut self, pin_nr : u8, mode : u8, prescaler: u16, time_on : u32, period : u32 ) -> Result<Vec<u8>,Error> {
-
-        match mode {
-             2 => (),
-            _ => return Err(Error::new(ErrorKind::Other,"Invalid input mode")),
+    #[deprecated(note = "use set_pin_output with a PinId and OutputMode::Digital")]
+    pub fn set_output(self, pin_nr : u8, mode : u8, state: u8 ) -> Result<Vec<u8>,Error> {
+        if mode != 1 {
+            return Err(Error::new(ErrorKind::InvalidInput,"Invalid input mode"));
         }
-
-        self.frame.push(classes::output_class::operation_code::SET_OUTPUT);
-        self.frame.push(pin_nr);
-        self.frame.push(mode);
-        self.frame.push((prescaler >> 8) as u8);
-        self.frame.push((prescaler >> 0) as u8);
-        self.frame.push((time_on >> 24) as u8);
-        self.frame.push((time_on >> 16) as u8);
-        self.frame.push((time_on >> 8) as u8);
-        self.frame.push((time_on >> 0) as u8);
-        self.frame.push((period >> 24) as u8);
-        self.frame.push((period >> 16) as u8);
-        self.frame.push((period >> 8) as u8);
-        self.frame.push((period >> 0) as u8);
-        Ok(self.frame)
+        let state = match state {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::new(ErrorKind::InvalidInput,"Invalid state")),
+        };
+        self.set_pin_output(PinId::new(pin_nr)?, OutputMode::Digital(state))
     }
 
+    #[deprecated(note = "use set_pin_output with a PinId and OutputMode::Pwm")]
+    pub fn set_output_pwm(self, pin_nr : u8, mode : u8, prescaler: u16, time_on : u32, period : u32 ) -> Result<Vec<u8>,Error> {
+        if mode != 2 {
+            return Err(Error::new(ErrorKind::InvalidInput,"Invalid input mode"));
+        }
+        self.set_pin_output(PinId::new(pin_nr)?, OutputMode::Pwm { prescaler, time_on, period })
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_builders_match_the_typed_ones() {
+        let pin = PinId::new(2).unwrap();
+        assert_eq!(OutputBuilder::new().set_output(2, 1, 1).unwrap(), OutputBuilder::new().set_pin_output(pin, OutputMode::Digital(true)).unwrap());
+        assert_eq!(OutputBuilder::new().set_output_pwm(2, 2, 1, 10, 20).unwrap(),
+            OutputBuilder::new().set_pin_output(pin, OutputMode::Pwm { prescaler : 1, time_on : 10, period : 20 }).unwrap());
+        assert!(OutputBuilder::new().set_output(2, 2, 1).is_err());
+        assert!(OutputBuilder::new().set_output(2, 1, 2).is_err());
+    }
 }
-
@@ -0,0 +1,181 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use super::config::OutputMode;
+
+/// How the timer divides its clock by the prescaler sent with `SET_OUTPUT`
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrescalerDivider {
+    /// The clock is divided by `prescaler`, `0` is invalid
+    Prescaler,
+    /// The clock is divided by `prescaler + 1`, `0` runs the timer at the full clock
+    PrescalerPlusOne,
+}
+
+/// Timer generating the PWM outputs
+///
+/// The output frequency is `clock_hz / (divider * period)`, the pin is high for `time_on`
+/// of the `period` ticks. The clock and the divider are not documented for the IO module and
+/// have to be given by the caller, the limits default to the ranges of the `SET_OUTPUT` fields.
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PwmTimer {
+    pub clock_hz : u32,
+    pub divider : PrescalerDivider,
+    pub max_prescaler : u16,
+    pub max_period : u32,
+}
+
+impl PwmTimer {
+
+    pub fn new(clock_hz : u32, divider : PrescalerDivider) -> PwmTimer {
+        PwmTimer { clock_hz, divider, max_prescaler : u16::MAX, max_period : u32::MAX }
+    }
+}
+
+/// High time of a PWM signal
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum PwmDuty {
+    /// 0 to 100
+    Percent(f64),
+    PulseWidth(Duration),
+}
+
+/// Timer values of a PWM output together with the frequency and duty cycle they produce
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PwmSetting {
+    pub prescaler : u16,
+    pub time_on : u32,
+    pub period : u32,
+    pub frequency_hz : f64,
+    pub duty_percent : f64,
+    /// Achieved minus requested frequency
+    pub frequency_error_hz : f64,
+    /// Achieved minus requested duty cycle in percentage points
+    pub duty_error : f64,
+}
+
+impl PwmSetting {
+
+    pub fn output_mode(&self) -> OutputMode {
+        OutputMode::Pwm { prescaler : self.prescaler, time_on : self.time_on, period : self.period }
+    }
+}
+
+impl PwmTimer {
+
+    /// Finds the prescaler and period closest to `frequency_hz`, preferring the finest duty resolution
+    pub fn solve(&self, frequency_hz : f64, duty : PwmDuty) -> Result<PwmSetting,Error> {
+        let invalid = |msg : String| Error::new(ErrorKind::InvalidInput, msg);
+
+        if !frequency_hz.is_finite() || frequency_hz <= 0.0 {
+            return Err(invalid(format!("Invalid PWM frequency {} Hz", frequency_hz)));
+        }
+        let ticks = self.clock_hz as f64 / frequency_hz;
+        if ticks < 2.0 {
+            return Err(invalid(format!("PWM frequency {} Hz above {} Hz", frequency_hz, self.clock_hz / 2)));
+        }
+        let offset = self.offset();
+        let max_divider = self.max_prescaler as u32 + offset;
+        let min_divider = (ticks / self.max_period as f64).ceil().max(1.0);
+        if min_divider > max_divider as f64 {
+            let lowest = self.clock_hz as f64 / (max_divider as f64 * self.max_period as f64);
+            return Err(invalid(format!("PWM frequency {} Hz below {:.4} Hz", frequency_hz, lowest)));
+        }
+
+        let mut best : Option<(u16, u32, f64)> = None;
+        for divider in (min_divider as u32).max(1)..=max_divider {
+            let period = (ticks / divider as f64).round();
+            if period < 2.0 {
+                break;
+            }
+            let prescaler = (divider - offset) as u16;
+            let period = (period.min(u32::MAX as f64) as u32).min(self.max_period);
+            let error = (self.frequency(prescaler, period) - frequency_hz).abs();
+            if best.map(|(_, _, best_error)| error < best_error).unwrap_or(true) {
+                best = Some((prescaler, period, error));
+            }
+            if error == 0.0 {
+                break;
+            }
+        }
+        let (prescaler, period, _) = best.ok_or_else(|| invalid(format!("No timer setting for {} Hz", frequency_hz)))?;
+
+        let requested_duty = match duty {
+            PwmDuty::Percent(percent) if (0.0..=100.0).contains(&percent) => percent,
+            PwmDuty::Percent(percent) => return Err(invalid(format!("Invalid duty cycle {}% (0-100)", percent))),
+            PwmDuty::PulseWidth(width) => width.as_secs_f64() * frequency_hz * 100.0,
+        };
+        if requested_duty > 100.0 {
+            return Err(invalid(format!("Pulse width exceeds the period of {} Hz", frequency_hz)));
+        }
+        let time_on = ((requested_duty / 100.0) * period as f64).round() as u32;
+
+        let frequency = self.frequency(prescaler, period);
+        let duty_percent = time_on as f64 * 100.0 / period as f64;
+        Ok(PwmSetting {
+            prescaler, time_on, period,
+            frequency_hz : frequency,
+            duty_percent,
+            frequency_error_hz : frequency - frequency_hz,
+            duty_error : duty_percent - requested_duty,
+        })
+    }
+
+    /// Frequency and duty cycle of a PWM output mode, `None` for digital outputs and invalid settings
+    pub fn decode(&self, mode : &OutputMode) -> Option<(f64, f64)> {
+        match *mode {
+            OutputMode::Pwm { prescaler, time_on, period } if prescaler as u32 + self.offset() > 0 && period > 0 && time_on <= period => {
+                Some((self.frequency(prescaler, period), time_on as f64 * 100.0 / period as f64))
+            }
+            _ => None,
+        }
+    }
+
+    fn offset(&self) -> u32 {
+        match self.divider {
+            PrescalerDivider::Prescaler => 0,
+            PrescalerDivider::PrescalerPlusOne => 1,
+        }
+    }
+
+    fn frequency(&self, prescaler : u16, period : u32) -> f64 {
+        self.clock_hz as f64 / ((prescaler as u32 + self.offset()) as f64 * period as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_timer_setting_and_decodes_it() {
+        let timer = PwmTimer { max_period : 0xFFFF, ..PwmTimer::new(48_000_000, PrescalerDivider::Prescaler) };
+        let setting = timer.solve(1000.0, PwmDuty::Percent(25.0)).unwrap();
+        assert_eq!((setting.frequency_hz, setting.duty_percent), (1000.0, 25.0));
+        assert_eq!(setting.prescaler as u32 * setting.period, 48_000);
+        assert_eq!(timer.decode(&setting.output_mode()), Some((1000.0, 25.0)));
+
+        let setting = timer.solve(50.0, PwmDuty::PulseWidth(Duration::from_millis(1))).unwrap();
+        assert!(setting.frequency_error_hz.abs() < 0.01);
+        assert!((setting.duty_percent - 5.0).abs() < 0.01);
+
+        let setting = timer.solve(7.0e6, PwmDuty::Percent(50.0)).unwrap();
+        assert_eq!((setting.prescaler, setting.period, setting.time_on), (1, 7, 4));
+        assert!(setting.duty_error > 0.0);
+
+        assert!(timer.solve(30.0e6, PwmDuty::Percent(50.0)).is_err());
+        assert!(timer.solve(0.001, PwmDuty::Percent(50.0)).is_err());
+        assert!(timer.solve(1000.0, PwmDuty::Percent(120.0)).is_err());
+        assert!(timer.solve(1000.0, PwmDuty::PulseWidth(Duration::from_millis(2))).is_err());
+        assert_eq!(timer.decode(&OutputMode::Digital(true)), None);
+        assert_eq!(timer.decode(&OutputMode::Pwm { prescaler : 0, time_on : 1, period : 2 }), None);
+
+        // The same frequency needs a prescaler one lower if the timer divides by prescaler + 1
+        let plus_one = PwmTimer { divider : PrescalerDivider::PrescalerPlusOne, ..timer };
+        let setting = plus_one.solve(1.0, PwmDuty::Percent(50.0)).unwrap();
+        assert_eq!((setting.prescaler as u32 + 1) * setting.period, 48_000_000);
+        assert_eq!(plus_one.decode(&OutputMode::Pwm { prescaler : 0, time_on : 1, period : 2 }), Some((24.0e6, 50.0)));
+        assert!(PwmTimer::new(48_000_000, PrescalerDivider::Prescaler).solve(0.1, PwmDuty::Percent(50.0)).is_ok());
+    }
+}
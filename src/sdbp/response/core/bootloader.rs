@@ -0,0 +1,98 @@
+use std::io::{Error, ErrorKind};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::bootloader::operation_code;
+
+/// Checks the header of a bootloader response and returns `(operation code, status)`
+///
+/// `ERROR` responses are accepted for every operation, their error code is returned as status.
+fn parse_header(value : &[u8], operation : u8) -> Result<(u8,u8),Error> {
+    if value.len() < 4 {
+        return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+    }
+
+    if value[0] != CLASS_ID || value[1] != classes::bootloader::ID || (value[2] != operation && value[2] != operation_code::ERROR) {
+        return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+    }
+    Ok((value[2], value[3]))
+}
+
+fn be_u32(value : &[u8]) -> u32 {
+    u32::from_be_bytes([value[0], value[1], value[2], value[3]])
+}
+
+/// Response of `GET_INFO`, the flash layout is only valid if `status` is zero
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BootloaderInfo {
+    pub status: u8,
+    pub flash_start: u32,
+    pub flash_size: u32,
+    pub max_chunk_size: u16,
+}
+
+impl SdbpResponse for BootloaderInfo {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let value = raw.as_slice();
+        let (_, status) = parse_header(value, operation_code::GET_INFO)?;
+        if status != classes::bootloader::return_code::SUCCESS {
+            return Ok(BootloaderInfo { status, flash_start: 0, flash_size: 0, max_chunk_size: 0 });
+        }
+
+        if value.len() != 4 + 4 + 4 + 2 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+        Ok(BootloaderInfo {
+            status,
+            flash_start: be_u32(&value[4..8]),
+            flash_size: be_u32(&value[8..12]),
+            max_chunk_size: u16::from_be_bytes([value[12], value[13]]),
+        })
+    }
+}
+
+/// Response of `ERASE` and `WRITE`
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BootloaderStatus {
+    pub operation_code: u8,
+    pub status: u8,
+}
+
+impl SdbpResponse for BootloaderStatus {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let value = raw.as_slice();
+        if value.len() != 4 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        let (operation, status) = match parse_header(value, operation_code::ERASE) {
+            Ok(header) => header,
+            Err(_) => parse_header(value, operation_code::WRITE)?,
+        };
+        Ok(BootloaderStatus { operation_code: operation, status })
+    }
+}
+
+/// Response of `VERIFY`, the CRC is only valid if `status` is zero
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VerifyResponse {
+    pub status: u8,
+    pub crc: u32,
+}
+
+impl SdbpResponse for VerifyResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let value = raw.as_slice();
+        let (_, status) = parse_header(value, operation_code::VERIFY)?;
+        if status != classes::bootloader::return_code::SUCCESS {
+            return Ok(VerifyResponse { status, crc: 0 });
+        }
+
+        if value.len() != 4 + 4 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+        Ok(VerifyResponse { status, crc: be_u32(&value[4..8]) })
+    }
+}
@@ -58,4 +58,93 @@ impl SdbpResponse for RunResponse {
             status:  "success".to_string()
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "firmware-experimental")]
+#[derive(Debug,Clone, serde::Serialize, serde::Deserialize)]
+pub struct BootloaderModeResponse {
+    pub status: String,
+}
+
+#[cfg(feature = "firmware-experimental")]
+impl SdbpResponse for BootloaderModeResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+
+        let value = raw.as_slice();
+        if value.len() < 4 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        if value[0] != protocol::CLASS_ID ||
+            value[1] != protocol::classes::control::ID {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+
+        if value[2] != protocol::classes::control::operation_code::MODE_BOOTLOADER || value[3] != 0x00 {
+            return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+        }
+
+        Ok( BootloaderModeResponse{
+            status:  "success".to_string()
+        })
+    }
+}
+
+#[derive(Debug,Clone, serde::Serialize, serde::Deserialize)]
+pub struct SetFrameSizeResponse {
+    pub status: String,
+}
+
+impl SdbpResponse for SetFrameSizeResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+
+        let value = raw.as_slice();
+        if value.len() < 4 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        if value[0] != protocol::CLASS_ID ||
+            value[1] != protocol::classes::control::ID {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+
+        if value[2] != protocol::classes::control::operation_code::SET_FRAME_SIZE || value[3] != 0x00 {
+            return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+        }
+
+        Ok( SetFrameSizeResponse{
+            status:  "success".to_string()
+        })
+    }
+}
+
+#[derive(Debug,Clone, serde::Serialize, serde::Deserialize)]
+pub struct SetSclkSpeedResponse {
+    pub status: String,
+}
+
+impl SdbpResponse for SetSclkSpeedResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+
+        let value = raw.as_slice();
+        if value.len() < 4 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        if value[0] != protocol::CLASS_ID ||
+            value[1] != protocol::classes::control::ID {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+
+        if value[2] != protocol::classes::control::operation_code::SET_SCLK_SPEED || value[3] != 0x00 {
+            return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+        }
+
+        Ok( SetSclkSpeedResponse{
+            status:  "success".to_string()
+        })
+    }
+}
@@ -0,0 +1,283 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use crate::datatypes::{AdvancedVersion, BootloaderState, Descriptor, Version};
+use crate::sdbp::CoreBuilder;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::descriptor::{ID, operation_code};
+
+/// Checks the header of a descriptor response and returns its payload
+///
+/// `ERROR` responses are turned into an error with the raw return code of the module.
+fn parse_payload(value : &[u8], operations : &[u8]) -> Result<(u8,Vec<u8>),Error> {
+    if value.len() < 3 {
+        return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+    }
+
+    if value[0] != CLASS_ID || value[1] != ID {
+        return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+    }
+
+    // The descriptor class defines no return codes of its own, the code is passed on raw
+    if value[2] == operation_code::ERROR {
+        let msg = match value.get(3) {
+            Some(code) => format!("Descriptor error 0x{:02x}", code),
+            None => "Descriptor error".to_string(),
+        };
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+
+    if !operations.contains(&value[2]) {
+        return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+    }
+    Ok((value[2], value[3..].to_vec()))
+}
+
+/// Strings are sent without terminator, trailing zero bytes of fixed size fields are dropped
+fn parse_string(payload : Vec<u8>) -> Result<String,Error> {
+    let end = payload.iter().rposition(|value| *value != 0).map(|index| index + 1).unwrap_or(0);
+    String::from_utf8(payload[..end].to_vec()).map_err(|_| Error::new(ErrorKind::InvalidData, "String is not utf-8"))
+}
+
+fn parse_u16(payload : &[u8]) -> Result<u16,Error> {
+    match payload {
+        [high, low] => Ok(u16::from_be_bytes([*high, *low])),
+        _ => Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",payload.len()))),
+    }
+}
+
+fn parse_version(payload : &[u8]) -> Result<Version,Error> {
+    if payload.len() != 6 {
+        return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",payload.len())));
+    }
+    Ok(Version::new(parse_u16(&payload[0..2])?, parse_u16(&payload[2..4])?, parse_u16(&payload[4..6])?))
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VendorProductIdResponse {
+    pub vendor_product_id: String,
+}
+
+impl SdbpResponse for VendorProductIdResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::VENDOR_PRODUCT_ID])?;
+        Ok(VendorProductIdResponse { vendor_product_id: parse_string(payload)? })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SerialCodeResponse {
+    pub serial: String,
+}
+
+impl SdbpResponse for SerialCodeResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::SERIAL_CODE])?;
+        Ok(SerialCodeResponse { serial: parse_string(payload)? })
+    }
+}
+
+/// The stability is sent as number, 1 - Alpha, 2 - Beta, 3 - Stable Release
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FwVersionResponse {
+    pub version: AdvancedVersion,
+}
+
+impl SdbpResponse for FwVersionResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::FW_VERSION])?;
+        if payload.len() != 7 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",payload.len())));
+        }
+        let stability = match payload[0] {
+            1 => 'A',
+            2 => 'B',
+            3 => 'S',
+            value => return Err(Error::new(ErrorKind::InvalidData, format!("Invalid stability {}", value))),
+        };
+        let version = parse_version(&payload[1..])?;
+        Ok(FwVersionResponse { version: AdvancedVersion::new(stability, version.major(), version.minor(), version.patch()) })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HwVersionResponse {
+    pub version: Version,
+}
+
+impl SdbpResponse for HwVersionResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::HW_VERSION])?;
+        Ok(HwVersionResponse { version: parse_version(payload.as_slice())? })
+    }
+}
+
+/// Highest SPI clock the module supports in kHz
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MaxSclkSpeedResponse {
+    pub speed: u32,
+}
+
+impl SdbpResponse for MaxSclkSpeedResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::MAX_SLCK_SPEED])?;
+        let speed = match payload.as_slice() {
+            [a, b, c, d] => u32::from_be_bytes([*a, *b, *c, *d]),
+            _ => return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",payload.len()))),
+        };
+        Ok(MaxSclkSpeedResponse { speed })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MaxFrameSizeResponse {
+    pub size: u16,
+}
+
+impl SdbpResponse for MaxFrameSizeResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::MAX_FRAME_SIZE])?;
+        Ok(MaxFrameSizeResponse { size: parse_u16(payload.as_slice())? })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProtocolVersionResponse {
+    pub version: Version,
+}
+
+impl SdbpResponse for ProtocolVersionResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::PROTOCOL_VERSION])?;
+        Ok(ProtocolVersionResponse { version: parse_version(payload.as_slice())? })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VendorNameResponse {
+    pub name: String,
+}
+
+impl SdbpResponse for VendorNameResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::VENDOR_NAME])?;
+        Ok(VendorNameResponse { name: parse_string(payload)? })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProductNameResponse {
+    pub name: String,
+}
+
+impl SdbpResponse for ProductNameResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::PRODUCT_NAME])?;
+        Ok(ProductNameResponse { name: parse_string(payload)? })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BootloaderStateResponse {
+    pub state: BootloaderState,
+}
+
+impl SdbpResponse for BootloaderStateResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (_, payload) = parse_payload(raw.as_slice(), &[operation_code::BOOTLOADER_STATE])?;
+        let state = match payload.as_slice() {
+            [value] => BootloaderState::try_from(*value).map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid bootloader state {}", value)))?,
+            _ => return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",payload.len()))),
+        };
+        Ok(BootloaderStateResponse { state })
+    }
+}
+
+/// Response of `MAX_POWER_3V3`, `MAX_POWER_5V` and `MAX_POWER_12V`, `operation_code` tells the rail
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MaxPowerResponse {
+    pub operation_code: u8,
+    pub power: u16,
+}
+
+impl SdbpResponse for MaxPowerResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let (operation_code, payload) = parse_payload(raw.as_slice(), &[operation_code::MAX_POWER_3V3, operation_code::MAX_POWER_5V, operation_code::MAX_POWER_12V])?;
+        Ok(MaxPowerResponse { operation_code, power: parse_u16(payload.as_slice())? })
+    }
+}
+
+/// Queries every descriptor field from the module, `transfer` sends a frame and returns the response
+///
+/// Works with any transport, e.g. `|frame| handle.transfer(frame)`. The path, slot, session and
+/// device file are not known on the bus and have to be set by the caller.
+pub fn read_full_descriptor<F>(mut transfer : F) -> Result<Descriptor,Error> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+    let invalid = |_| Error::new(ErrorKind::InvalidInput, "Could not build descriptor request");
+    let mut desc = Descriptor::new(PathBuf::new());
+
+    desc.set_vendor_product_id(VendorProductIdResponse::from_raw(transfer(CoreBuilder::new().descriptor().vendor_product_id().map_err(invalid)?)?)?.vendor_product_id);
+    desc.set_serial(SerialCodeResponse::from_raw(transfer(CoreBuilder::new().descriptor().serial_code().map_err(invalid)?)?)?.serial);
+    desc.set_fw_version(FwVersionResponse::from_raw(transfer(CoreBuilder::new().descriptor().fw_version().map_err(invalid)?)?)?.version);
+    desc.set_hw_version(HwVersionResponse::from_raw(transfer(CoreBuilder::new().descriptor().hw_version().map_err(invalid)?)?)?.version);
+    desc.set_max_sclk_speed(MaxSclkSpeedResponse::from_raw(transfer(CoreBuilder::new().descriptor().max_sclk_speed().map_err(invalid)?)?)?.speed);
+    desc.set_max_frame_size(MaxFrameSizeResponse::from_raw(transfer(CoreBuilder::new().descriptor().max_frame_size().map_err(invalid)?)?)?.size);
+    desc.set_protocol_version(ProtocolVersionResponse::from_raw(transfer(CoreBuilder::new().descriptor().protocol_version().map_err(invalid)?)?)?.version);
+    desc.set_vendor_name(VendorNameResponse::from_raw(transfer(CoreBuilder::new().descriptor().vendor_name().map_err(invalid)?)?)?.name);
+    desc.set_product_name(ProductNameResponse::from_raw(transfer(CoreBuilder::new().descriptor().product_name().map_err(invalid)?)?)?.name);
+    desc.set_bootloader_state(BootloaderStateResponse::from_raw(transfer(CoreBuilder::new().descriptor().bootloader_state().map_err(invalid)?)?)?.state.to_string());
+    desc.set_max_power_3v3(MaxPowerResponse::from_raw(transfer(CoreBuilder::new().descriptor().max_power_3v3().map_err(invalid)?)?)?.power);
+    desc.set_max_power_5v(MaxPowerResponse::from_raw(transfer(CoreBuilder::new().descriptor().max_power_5v().map_err(invalid)?)?)?.power);
+    desc.set_max_power_12v(MaxPowerResponse::from_raw(transfer(CoreBuilder::new().descriptor().max_power_12v().map_err(invalid)?)?)?.power);
+    Ok(desc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(request : Vec<u8>) -> Result<Vec<u8>,Error> {
+        let payload : Vec<u8> = match request[2] {
+            operation_code::VENDOR_PRODUCT_ID => b"io".to_vec(),
+            operation_code::SERIAL_CODE => b"SN0001\0\0".to_vec(),
+            operation_code::FW_VERSION => vec![3, 0, 1, 0, 2, 0, 3],
+            operation_code::HW_VERSION | operation_code::PROTOCOL_VERSION => vec![0, 1, 0, 0, 0, 0],
+            operation_code::MAX_SLCK_SPEED => 20_000u32.to_be_bytes().to_vec(),
+            operation_code::MAX_FRAME_SIZE => 512u16.to_be_bytes().to_vec(),
+            operation_code::VENDOR_NAME => b"Noreya".to_vec(),
+            operation_code::PRODUCT_NAME => b"IO Module".to_vec(),
+            operation_code::BOOTLOADER_STATE => vec![1],
+            _ => 1500u16.to_be_bytes().to_vec(),
+        };
+        Ok([request.as_slice(), payload.as_slice()].concat())
+    }
+
+    #[test]
+    fn descriptor_is_read_over_the_bus() {
+        let desc = read_full_descriptor(module).unwrap();
+        assert_eq!(desc.vendor_product_id(), "io");
+        assert_eq!(desc.serial(), "SN0001");
+        assert_eq!(desc.fw_version(), &AdvancedVersion::new('S', 1, 2, 3));
+        assert_eq!(desc.hw_version(), &Version::new(1, 0, 0));
+        assert_eq!(desc.max_sclk_speed(), 20_000);
+        assert_eq!(desc.max_frame_size(), 512);
+        assert_eq!(desc.bootloader_state(), "supported");
+        assert_eq!(desc.max_power_12v(), 1500);
+
+        assert!(FwVersionResponse::from_raw(vec![CLASS_ID, ID, operation_code::FW_VERSION, 9, 0, 1, 0, 2, 0, 3]).is_err());
+        let err = FwVersionResponse::from_raw(vec![CLASS_ID, ID, operation_code::ERROR, 0x01]).unwrap_err();
+        assert_eq!(err.to_string(), "Descriptor error 0x01");
+        assert!(MaxFrameSizeResponse::from_raw(vec![CLASS_ID, ID, operation_code::MAX_SLCK_SPEED, 0x02, 0x00]).is_err());
+    }
+}
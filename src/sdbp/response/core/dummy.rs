@@ -0,0 +1,31 @@
+use std::io::{Error, ErrorKind};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::dummy::{ID, operation_code};
+
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DummyResponse {
+    pub status: String,
+}
+
+impl SdbpResponse for DummyResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let value = raw.as_slice();
+        if value.len() < 3 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        if value[0] != CLASS_ID || value[1] != ID {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+
+        if value[2] != operation_code::DUMMY {
+            return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+        }
+
+        Ok(DummyResponse {
+            status: "success".to_string()
+        })
+    }
+}
@@ -1,2 +1,10 @@
+
 pub mod notification;
-pub mod control;
\ No newline at end of file
+pub mod control;
+#[cfg(feature = "firmware-experimental")]
+pub mod bootloader;
+pub mod dummy;
+pub mod wait;
+pub mod transaction_error;
+
+pub mod descriptor;
\ No newline at end of file
@@ -24,6 +24,9 @@ impl SdbpResponse for NotificationResponse {
         }
 
         if value[2] == operation_code::GET_NOTIFICATION {
+            if value.len() != 8 {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Invalid notification length {}", value.len())));
+            }
             let mut notification = vec![0;4];
             notification.copy_from_slice(&value[4..]);
             Ok(NotificationResponse { notification })
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::transaction_error::ID;
+
+/// Frame a module sends instead of the response if it could not process the request
+///
+/// `code` is the raw return code of the module, its meaning is not specified.
+/// Converts into a `std::io::Error` of kind `InvalidData`, `from_io_error` gets it back.
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TransactionError {
+    pub code: u8,
+}
+
+impl TransactionError {
+
+    pub fn new(code : u8) -> TransactionError {
+        TransactionError { code }
+    }
+
+    pub fn is_transaction_error(raw : &[u8]) -> bool {
+        raw.len() >= 3 && raw[0] == CLASS_ID && raw[1] == ID
+    }
+
+    /// Returns the transaction error wrapped by `err`
+    pub fn from_io_error(err : &Error) -> Option<TransactionError> {
+        err.get_ref()?.downcast_ref::<TransactionError>().copied()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![CLASS_ID, ID, self.code]
+    }
+}
+
+impl fmt::Display for TransactionError {
+
+    fn fmt(&self, fmt : &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Transaction error 0x{:02x}", self.code)
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+impl From<TransactionError> for Error {
+    fn from(err : TransactionError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+impl SdbpResponse for TransactionError {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        if !TransactionError::is_transaction_error(raw.as_slice()) {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+        Ok(TransactionError { code: raw[2] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transaction_error_survives_io_error() {
+        let frame = vec![CLASS_ID, ID, 0x02];
+        let err = TransactionError::from_raw(frame.clone()).unwrap();
+        assert_eq!(err.to_bytes(), frame);
+
+        let io_err : Error = err.into();
+        assert_eq!(io_err.kind(), ErrorKind::InvalidData);
+        assert_eq!(TransactionError::from_io_error(&io_err), Some(err));
+        assert_eq!(TransactionError::from_io_error(&Error::new(ErrorKind::InvalidData, "other")), None);
+        assert!(TransactionError::from_raw(vec![CLASS_ID, classes::wait::ID, classes::wait::operation_code::WAIT]).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{Error, ErrorKind};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::request::core::protocol::*;
+use crate::sdbp::request::core::protocol::classes::wait::{ID, operation_code};
+
+/// The module is busy, the request was not processed and has to be repeated
+#[derive(Debug,Clone,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WaitResponse {
+    pub status: String,
+}
+
+impl WaitResponse {
+
+    pub fn is_wait(raw : &[u8]) -> bool {
+        raw.len() >= 3 && raw[0] == CLASS_ID && raw[1] == ID && raw[2] == operation_code::WAIT
+    }
+}
+
+impl SdbpResponse for WaitResponse {
+
+    fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
+        let value = raw.as_slice();
+        if value.len() < 3 {
+            return Err(Error::new(ErrorKind::InvalidData,format!("Invalid length {}",value.len())));
+        }
+
+        if value[0] != CLASS_ID || value[1] != ID {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
+        }
+
+        if value[2] != operation_code::WAIT {
+            return Err(Error::new(ErrorKind::InvalidData, "Response is invalid"))
+        }
+
+        Ok(WaitResponse {
+            status: "wait".to_string()
+        })
+    }
+}
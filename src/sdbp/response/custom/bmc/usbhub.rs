@@ -308,17 +308,22 @@ fn calculate_port_offset(disabled_slots: Vec<UsbPortSlotMapping>, usb_slot_map:
             }
         }
         if !found_disabled {
-            enabled_slots.push(UsbPortSlotMapping{ slot_number: map.slot_number, port_number: map.port_number-offset });
+            enabled_slots.push(UsbPortSlotMapping{ slot_number: map.slot_number, port_number: map.port_number.saturating_sub(offset) });
         }
     }
     return enabled_slots;
 }
 
-pub fn get_usb_devices(disabled_slots: Vec<UsbHubMapping>, usb_slot_map: UsbHubPortMapping) -> Result<UsbHubDevices, String> {
-    const USB_HUB: &str = "/dev/bus/usb/001/";
+/// Hub ports of the enabled slots, the hub numbers its ports without the disabled ones
+pub fn enabled_port_mapping(disabled_slots: Vec<UsbHubMapping>, usb_slot_map: UsbHubPortMapping) -> Vec<UsbPortSlotMapping> {
     let usb_slot_map= vec![usb_slot_map.slot0, usb_slot_map.slot1, usb_slot_map.slot2, usb_slot_map.slot3, usb_slot_map.slot4, usb_slot_map.slot5, usb_slot_map.slot6 , usb_slot_map.slot7 ];
     let disabled_slots = map_disabled_to_slot(disabled_slots, &usb_slot_map);
-    let usb_ports_with_offset = calculate_port_offset(disabled_slots, &usb_slot_map);
+    calculate_port_offset(disabled_slots, &usb_slot_map)
+}
+
+pub fn get_usb_devices(disabled_slots: Vec<UsbHubMapping>, usb_slot_map: UsbHubPortMapping) -> Result<UsbHubDevices, String> {
+    const USB_HUB: &str = "/dev/bus/usb/001/";
+    let usb_ports_with_offset = enabled_port_mapping(disabled_slots, usb_slot_map);
 
     let mut enumerator = match udev::Enumerator::new() {
         Ok(val) => { val }
@@ -28,9 +28,19 @@ pub struct PinValues {
     pub pin: u8,
     pub pin_type: String,
     pub value: u32,
+    /// Value of a type this library does not know, `pin_type` is "unknown"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown: Option<UnknownValue>,
 
 }
 
+/// Value record with a type not known to this library, kept as sent by the module
+#[derive(Debug,Clone,PartialEq,serde::Serialize, serde::Deserialize)]
+pub struct UnknownValue {
+    pub value_type: u8,
+    pub data: Vec<u8>,
+}
+
 
 const VALUE_TYPE_VOLTAGE : u8 = 0x00;
 const VALUE_TYPE_CURRENT : u8 = 0x01;
@@ -40,7 +50,7 @@ const VALUE_TYPE_FREQUENCY_COUNTER : u8 = 0x03;
 impl PinValues {
 
     pub fn new_empty() -> PinValues {
-        return PinValues{pin : 0, pin_type: "Invalid".to_string(), value: 0}
+        PinValues{pin : 0, pin_type: "Invalid".to_string(), value: 0, unknown: None}
     }
 
     pub fn set_id(&mut self, pin : u8) {
@@ -80,6 +90,17 @@ impl PinValues {
 
     }
 
+    /// Value of the pin, `None` if the module sent an unknown type or a value of the wrong length
+    pub fn typed(&self) -> Option<PinValue> {
+        match self.pin_type.as_str() {
+            "voltage_millivolt" => Some(PinValue::Voltage(self.value as u16)),
+            "current_milliampere" => Some(PinValue::Current(self.value as u16)),
+            "digital_input" => Some(PinValue::Digital(self.value != 0)),
+            "frequency_hertz" => Some(PinValue::Frequency(self.value)),
+            _ => None,
+        }
+    }
+
     pub fn set_frequency_counter(&mut self, input : &[u8]) {
         if input.len() == 4 {
             let mut frequency_counter_value = 0;
@@ -93,6 +114,35 @@ impl PinValues {
     }
 }
 
+/// Decoded value of a pin, see `PinValues::typed`
+#[derive(Debug,Clone,Copy,PartialEq,serde::Serialize, serde::Deserialize)]
+pub enum PinValue {
+    /// mV
+    Voltage(u16),
+    /// mA
+    Current(u16),
+    Digital(bool),
+    /// Hz
+    Frequency(u32),
+}
+
+impl PinValue {
+
+    /// Numeric value in the unit of the type, digital inputs are `0` or `1`
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            PinValue::Voltage(value) | PinValue::Current(value) => value as f64,
+            PinValue::Digital(value) => value as u8 as f64,
+            PinValue::Frequency(value) => value as f64,
+        }
+    }
+
+    /// `true` if both values are of the same type
+    pub fn same_kind(&self, other : &PinValue) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
 #[derive(Debug,serde::Serialize, serde::Deserialize)]
 pub struct GetValuesStatus {
     pub pins : Vec<PinValues>,
@@ -113,6 +163,73 @@ impl GetValuesStatus {
 }
 
 
+/// Message of an input class status, see `classes::input_class::error_code`
+pub fn input_status_message(status : u8) -> &'static str {
+    use classes::input_class::error_code::*;
+    match status {
+        OK => "success",
+        COMMAND_INVALID => "Invalid command",
+        WRONG_LENGTH => "Wrong command length",
+        INVALID_MODE => "Invalid mode",
+        PIN_OUT_OF_RANGE => "Invalid pin",
+        INVALID_DIRECTION => "Invalid direction",
+        INVALID_VALUE => "Invalid value",
+        _ => "Unknown error code",
+    }
+}
+
+impl GetValuesStatus {
+
+    /// Decodes the `(pin, type, length, value)` records of a `GET_VALUES` response
+    ///
+    /// `offset` is the position of `records` in the frame and is only used for error messages.
+    /// Records of unknown types are kept, known types with a wrong length are rejected.
+    pub fn decode_records(records : &[u8], offset : usize) -> Result<Vec<PinValues>,Error> {
+        let invalid = |msg : String| Err(Error::new(ErrorKind::InvalidData, msg));
+        let mut pins = vec![];
+        let mut idx = 0;
+
+        while idx < records.len() {
+            let (pin_id, value_type, value_length) = match records[idx..] {
+                [pin_id, value_type, value_length, ..] => (pin_id, value_type, value_length as usize),
+                _ => return invalid(format!("Truncated record header at byte {}", offset + idx)),
+            };
+            let start = idx + 3;
+            let value = match records.get(start..start + value_length) {
+                Some(value) => value,
+                None => return invalid(format!("Value of pin {} at byte {} truncated, {} of {} bytes present",
+                                               pin_id, offset + start, records.len() - start, value_length)),
+            };
+
+            let expected = match value_type {
+                VALUE_TYPE_VOLTAGE | VALUE_TYPE_CURRENT | VALUE_TYPE_DIGITAL_INPUT_STATE => Some(2),
+                VALUE_TYPE_FREQUENCY_COUNTER => Some(4),
+                _ => None,
+            };
+            if let Some(expected) = expected.filter(|expected| *expected != value_length) {
+                return invalid(format!("Value of pin {} at byte {} has type 0x{:02x} and length {}, expected {}",
+                                       pin_id, offset + start, value_type, value_length, expected));
+            }
+
+            let mut pin = PinValues::new_empty();
+            pin.set_id(pin_id);
+            match value_type {
+                VALUE_TYPE_VOLTAGE => pin.set_voltage(value),
+                VALUE_TYPE_CURRENT => pin.set_current(value),
+                VALUE_TYPE_DIGITAL_INPUT_STATE => pin.set_digital_state(value),
+                VALUE_TYPE_FREQUENCY_COUNTER => pin.set_frequency_counter(value),
+                _ => {
+                    pin.pin_type = "unknown".to_string();
+                    pin.unknown = Some(UnknownValue { value_type, data : value.to_vec() });
+                }
+            }
+            pins.push(pin);
+            idx = start + value_length;
+        }
+        Ok(pins)
+    }
+}
+
 impl SdbpResponse for GetValuesStatus {
     fn from_raw(raw: Vec<u8>) -> Result<Self, Error> {
 
@@ -128,68 +245,14 @@ impl SdbpResponse for GetValuesStatus {
             return Err(Error::new(ErrorKind::InvalidData, "Wrong Header"))
         }
 
-        let mut idx = 3;
-
-        let msg;
-        match value[3] {
-            0 => msg = "success".to_string(),
-            1 => msg = "Invalid command".to_string(),
-            2 => msg = "Wrong command length".to_string(),
-            3 => msg = "Invalid rail".to_string(),
-            4 => msg = "Invalid pin".to_string(),
-            5 => msg = "Invalid mode".to_string(),
-            _ => msg = "Unknown error code".to_string(),
-        }
-
-        if msg != "success"{
-            return Err(Error::new(ErrorKind::InvalidData, msg.to_string()));
+        if value[3] != classes::input_class::error_code::OK {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} (status 0x{:02x})", input_status_message(value[3]), value[3])));
         }
 
-        let mut result = GetValuesStatus {pins: vec![]};
-
-        idx += 1;
-        let mut bytes_left = raw.len() - idx;
-
         trace!("{:?}",raw);
 
-        let mut pins = vec![];
-
-        while bytes_left > 3 {
-
-            let pin_id = value[idx];
-            idx +=1;
-            let value_type = value[idx];
-            idx += 1;
-            let value_length = value[idx];
-            idx += 1;
-            let value = &value[idx as usize ..(idx as usize + value_length as usize)];
-            idx += value_length as usize;
-
-            let mut pin = PinValues::new_empty();
-
-
-            pin.set_id(pin_id);
-            if value_type == VALUE_TYPE_VOLTAGE {
-                pin.set_voltage(value);
-            }
-            if value_type == VALUE_TYPE_CURRENT {
-                pin.set_current(value);
-            }
-
-            if value_type == VALUE_TYPE_FREQUENCY_COUNTER {
-                pin.set_frequency_counter(value);
-            }
-
-            if value_type == VALUE_TYPE_DIGITAL_INPUT_STATE {
-                pin.set_digital_state(value);
-            }
-            pins.push(pin);
-
-            bytes_left = raw.len() - idx;
-        }
-
-        result.pins = pins;
-        return Ok(result)
+        let pins = GetValuesStatus::decode_records(&value[4..], 4)?;
+        Ok(GetValuesStatus {pins})
     }
 }
 
@@ -329,4 +392,38 @@ impl SdbpResponse for DigitalCounterStatus {
             status: value[3], msg
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(status : u8, records : &[u8]) -> Vec<u8> {
+        let mut frame = vec![CLASS_ID, classes::input_class::ID, classes::input_class::operation_code::GET_VALUES, status];
+        frame.extend_from_slice(records);
+        frame
+    }
+
+    #[test]
+    fn records_are_decoded_with_offsets() {
+        let records = [0, 0x00, 2, 0x0c, 0xe4, 3, 0x03, 4, 0, 0, 1, 0, 5, 0x09, 3, 1, 2, 3];
+        let values = GetValuesStatus::from_raw(frame(0, &records)).unwrap();
+        assert_eq!(values.pins[0].typed(), Some(PinValue::Voltage(3300)));
+        assert_eq!(values.pins[1].typed(), Some(PinValue::Frequency(256)));
+        assert_eq!(values.pins[2].pin_type, "unknown");
+        assert_eq!(values.pins[2].unknown, Some(UnknownValue { value_type : 0x09, data : vec![1, 2, 3] }));
+
+        let err = GetValuesStatus::from_raw(frame(0, &[1, 0x00, 4, 0, 0, 0])).unwrap_err();
+        assert_eq!(err.to_string(), "Value of pin 1 at byte 7 truncated, 3 of 4 bytes present");
+        let err = GetValuesStatus::from_raw(frame(0, &[1, 0x01, 1, 0])).unwrap_err();
+        assert_eq!(err.to_string(), "Value of pin 1 at byte 7 has type 0x01 and length 1, expected 2");
+        let err = GetValuesStatus::from_raw(frame(0, &[0, 0x00, 2, 0, 1, 2])).unwrap_err();
+        assert_eq!(err.to_string(), "Truncated record header at byte 9");
+        let err = GetValuesStatus::from_raw(frame(classes::input_class::error_code::PIN_OUT_OF_RANGE, &[])).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid pin (status 0x04)");
+
+        for len in 0..records.len() {
+            let _ = GetValuesStatus::from_raw(frame(0, &records[..len]));
+        }
+    }
+}
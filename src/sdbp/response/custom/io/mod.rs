@@ -1,3 +1,4 @@
 pub mod powermgmt;
 pub mod input;
-pub mod output;
\ No newline at end of file
+pub mod output;
+pub mod notification;
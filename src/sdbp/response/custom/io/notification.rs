@@ -0,0 +1,88 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+use crate::sdbp::request::custom::io::protocol::*;
+use crate::sdbp::request::custom::io::{PinId, Trigger};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Rising,
+    Falling,
+}
+
+impl Direction {
+
+    pub fn trigger(self) -> Trigger {
+        match self {
+            Direction::Rising => Trigger::Rising,
+            Direction::Falling => Trigger::Falling,
+        }
+    }
+}
+
+/// Input event of an IO module, decoded from the payload of `GET_NOTIFICATION`
+///
+/// Experimental: the layout of the 4 byte payload is not documented for the IO firmware and
+/// was not confirmed with a captured frame. `decode` assumes `[input class, operation, pin,
+/// trigger]`, `operation` being the one the event was configured with (`SET_ANALOG_THRESHOLD`
+/// or `SET_DIGITAL_INTERRUPT`) and `trigger` the byte sent with it. All zero means no
+/// notification is pending. Keep the raw payload where the event matters.
+#[derive(Debug,Clone,Copy,PartialEq,Eq, serde::Serialize, serde::Deserialize)]
+pub enum IoEvent {
+    ThresholdCrossed { pin : PinId, direction : Direction },
+    Edge { pin : PinId, rising : bool },
+    Pulse { pin : PinId },
+}
+
+impl IoEvent {
+
+    pub fn pin(&self) -> PinId {
+        match *self {
+            IoEvent::ThresholdCrossed { pin, .. } | IoEvent::Edge { pin, .. } | IoEvent::Pulse { pin } => pin,
+        }
+    }
+
+    /// Decodes `NotificationResponse::notification`, `None` if no notification is pending
+    pub fn decode(notification : &[u8]) -> Result<Option<IoEvent>,Error> {
+        let invalid = |msg : String| Error::new(ErrorKind::InvalidData, msg);
+
+        if notification.len() != 4 {
+            return Err(invalid(format!("Invalid notification length {}", notification.len())));
+        }
+        if notification.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        if notification[0] != classes::input_class::ID {
+            return Err(invalid(format!("Notification of unknown class 0x{:02x}", notification[0])));
+        }
+        let pin = PinId::try_from(notification[2]).map_err(|err| invalid(err.to_string()))?;
+
+        let event = match (notification[1], notification[3]) {
+            (classes::input_class::operation_code::SET_ANALOG_THRESHOLD, 1) => IoEvent::ThresholdCrossed { pin, direction : Direction::Rising },
+            (classes::input_class::operation_code::SET_ANALOG_THRESHOLD, 2) => IoEvent::ThresholdCrossed { pin, direction : Direction::Falling },
+            (classes::input_class::operation_code::SET_DIGITAL_INTERRUPT, 1) => IoEvent::Edge { pin, rising : true },
+            (classes::input_class::operation_code::SET_DIGITAL_INTERRUPT, 2) => IoEvent::Edge { pin, rising : false },
+            (classes::input_class::operation_code::SET_DIGITAL_INTERRUPT, 3) => IoEvent::Pulse { pin },
+            (operation, trigger) => return Err(invalid(format!("Unknown notification 0x{:02x} with trigger {}", operation, trigger))),
+        };
+        Ok(Some(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_threshold_edge_and_pulse() {
+        let pin = PinId::new(2).unwrap();
+        assert_eq!(IoEvent::decode(&[0, 0, 0, 0]).unwrap(), None);
+        assert_eq!(IoEvent::decode(&[1, 3, 2, 2]).unwrap(), Some(IoEvent::ThresholdCrossed { pin, direction : Direction::Falling }));
+        assert_eq!(IoEvent::decode(&[1, 4, 2, 1]).unwrap(), Some(IoEvent::Edge { pin, rising : true }));
+        assert_eq!(IoEvent::decode(&[1, 4, 2, 3]).unwrap(), Some(IoEvent::Pulse { pin }));
+        assert!(IoEvent::decode(&[1, 3, 2, 3]).is_err());
+        assert!(IoEvent::decode(&[1, 4, 9, 1]).is_err());
+        assert!(IoEvent::decode(&[1, 4, 2]).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::sdbp::response::custom::io::input::PinValue;
+
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Sample {
+    pub timestamp : SystemTime,
+    pub value : PinValue,
+}
+
+/// Samples of one bucket of `SampleBuffer::downsample`
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Aggregate {
+    /// Start of the bucket, aligned to multiples of the bucket length
+    pub start : SystemTime,
+    pub count : usize,
+    pub min : PinValue,
+    pub max : PinValue,
+    /// In the unit of the values, the share of high samples for digital inputs
+    pub avg : f64,
+}
+
+/// Latest samples of a single pin, the oldest sample is dropped once `capacity` is reached
+///
+/// All samples have the same type, a sample of another type means the pin was reconfigured
+/// and drops the previous samples.
+#[derive(Debug,Clone)]
+pub struct SampleBuffer {
+    capacity : usize,
+    samples : VecDeque<Sample>,
+}
+
+impl SampleBuffer {
+
+    pub fn new(capacity : usize) -> SampleBuffer {
+        let capacity = capacity.max(1);
+        SampleBuffer { capacity, samples : VecDeque::with_capacity(capacity) }
+    }
+
+    pub fn push(&mut self, sample : Sample) {
+        if self.samples.back().map(|last| !last.value.same_kind(&sample.value)).unwrap_or(false) {
+            self.samples.clear();
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<Sample> {
+        self.samples.back().copied()
+    }
+
+    /// Samples from oldest to newest
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> {
+        self.samples.iter()
+    }
+
+    /// Reduces the samples to min, max and average per `bucket`, empty buckets are left out
+    pub fn downsample(&self, bucket : Duration) -> Vec<Aggregate> {
+        let bucket_nanos = bucket.as_nanos().max(1);
+        let mut aggregates : Vec<(u128, Aggregate, f64)> = vec![];
+
+        for sample in &self.samples {
+            let since_epoch = sample.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            let index = since_epoch / bucket_nanos;
+            let value = sample.value.as_f64();
+
+            match aggregates.last_mut() {
+                Some((last, aggregate, sum)) if *last == index => {
+                    aggregate.count += 1;
+                    *sum += value;
+                    if value < aggregate.min.as_f64() {
+                        aggregate.min = sample.value;
+                    }
+                    if value > aggregate.max.as_f64() {
+                        aggregate.max = sample.value;
+                    }
+                }
+                _ => {
+                    let start = UNIX_EPOCH + Duration::from_nanos((index * bucket_nanos) as u64);
+                    aggregates.push((index, Aggregate { start, count : 1, min : sample.value, max : sample.value, avg : 0.0 }, value));
+                }
+            }
+        }
+
+        aggregates.into_iter().map(|(_, aggregate, sum)| Aggregate { avg : sum / aggregate.count as f64, ..aggregate }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(millis : u64, value : PinValue) -> Sample {
+        Sample { timestamp : UNIX_EPOCH + Duration::from_millis(millis), value }
+    }
+
+    #[test]
+    fn ring_buffer_downsamples_per_bucket() {
+        let mut buffer = SampleBuffer::new(4);
+        for (millis, mv) in [(0, 100), (10, 300), (20, 200), (110, 50), (120, 70)] {
+            buffer.push(sample(millis, PinValue::Voltage(mv)));
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.samples().next().unwrap().value, PinValue::Voltage(300));
+
+        let aggregates = buffer.downsample(Duration::from_millis(100));
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!((aggregates[0].count, aggregates[0].min, aggregates[0].max, aggregates[0].avg), (2, PinValue::Voltage(200), PinValue::Voltage(300), 250.0));
+        assert_eq!(aggregates[1].start, UNIX_EPOCH + Duration::from_millis(100));
+        assert_eq!(aggregates[1].avg, 60.0);
+
+        buffer.push(sample(130, PinValue::Digital(true)));
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.latest().unwrap().value, PinValue::Digital(true));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::sdbp::request::custom::io::{CounterState, IoBuilder, PinId};
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::custom::io::input::{DigitalCounterStatus, GetValuesStatus, PinValue};
+
+/// Time span of readings kept for `PulseCounter::rate`
+pub const DEFAULT_COUNTER_HISTORY : Duration = Duration::from_secs(60);
+
+/// Result of a single counter read
+#[derive(Debug,Clone,Copy,PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CounterReading {
+    pub pin : u8,
+    pub timestamp : SystemTime,
+    /// Frequency measured by the module counter
+    pub frequency_hz : u32,
+    /// Pulses since the previous read, integrated from the frequencies of both reads
+    pub delta : f64,
+    pub total : f64,
+}
+
+#[derive(Debug,Clone,Default, serde::Serialize, serde::Deserialize)]
+struct CounterTrack {
+    total : f64,
+    #[serde(skip)]
+    last : Option<(SystemTime,u32)>,
+    #[serde(skip)]
+    history : VecDeque<(SystemTime,f64)>,
+}
+
+/// Accumulates the pulses of the digital counters of an IO module across reads
+///
+/// A pin with an enabled counter (see `set_pin_digital_counter`) reports the measured frequency
+/// as `frequency_hertz`, not a pulse count. The pulses are integrated over the time between two
+/// reads with the average of both frequencies, so the totals are estimates whose accuracy depends
+/// on the read interval. Totals are written to the store file after every read, so counting
+/// continues after a restart of the client; the time the client was not running is not counted.
+pub struct PulseCounter {
+    tracks : HashMap<u8,CounterTrack>,
+    history : Duration,
+    store : Option<PathBuf>,
+}
+
+impl Default for PulseCounter {
+    fn default() -> Self {
+        PulseCounter::new()
+    }
+}
+
+impl PulseCounter {
+
+    pub fn new() -> PulseCounter {
+        PulseCounter { tracks : HashMap::new(), history : DEFAULT_COUNTER_HISTORY, store : None }
+    }
+
+    /// Longest window `rate` can be asked for
+    pub fn history(mut self, history : Duration) -> PulseCounter {
+        self.history = history;
+        self
+    }
+
+    /// Loads the totals from `path` if it exists and saves them there after every read
+    pub fn store(mut self, path : &Path) -> Result<PulseCounter,Error> {
+        if path.exists() {
+            self.tracks = serde_json::from_slice(&fs::read(path)?)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Invalid counter store {:?}: {}", path, err)))?;
+        }
+        self.store = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Adds a frequency measured on `pin`, the first reading of a pin only starts the integration
+    pub fn update(&mut self, pin : u8, frequency_hz : u32, timestamp : SystemTime) -> CounterReading {
+        let track = self.tracks.entry(pin).or_default();
+
+        let delta = match track.last.map(|(last, last_frequency)| (timestamp.duration_since(last), last_frequency)) {
+            Some((Ok(elapsed), last_frequency)) => (last_frequency as f64 + frequency_hz as f64) / 2.0 * elapsed.as_secs_f64(),
+            _ => 0.0,
+        };
+        track.total += delta;
+        track.last = Some((timestamp, frequency_hz));
+
+        track.history.push_back((timestamp, track.total));
+        while let Some((oldest, _)) = track.history.front() {
+            match timestamp.duration_since(*oldest) {
+                Ok(age) if age > self.history => track.history.pop_front(),
+                _ => break,
+            };
+        }
+        CounterReading { pin, timestamp, frequency_hz, delta, total : track.total }
+    }
+
+    /// Reads the values of all pins, every pin reporting a counter frequency is updated
+    pub fn read<F>(&mut self, mut transfer : F) -> Result<Vec<CounterReading>,Error> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let values = GetValuesStatus::from_raw(transfer(IoBuilder::new().input().get_values()?)?)?;
+        let timestamp = SystemTime::now();
+        let readings = values.pins.iter()
+            .filter_map(|pin| match pin.typed() {
+                Some(PinValue::Frequency(frequency)) => Some((pin.pin, frequency)),
+                _ => None,
+            })
+            .map(|(pin, frequency)| self.update(pin, frequency, timestamp))
+            .collect();
+        self.save()?;
+        Ok(readings)
+    }
+
+    /// Restarts the counter of the module by disabling and enabling it and sets the total to 0
+    pub fn reset<F>(&mut self, pin : PinId, mut transfer : F) -> Result<(),Error> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        for state in [CounterState::Disabled, CounterState::Enabled] {
+            let response = DigitalCounterStatus::from_raw(transfer(IoBuilder::new().input().set_pin_digital_counter(pin, state)?)?)?;
+            if response.status != 0 {
+                return Err(Error::new(ErrorKind::InvalidData, response.msg));
+            }
+        }
+        self.tracks.remove(&pin.value());
+        self.save()
+    }
+
+    pub fn total(&self, pin : u8) -> Option<f64> {
+        self.tracks.get(&pin).map(|track| track.total)
+    }
+
+    /// Pulses per second over the readings of the last `window`, `None` with less than two readings
+    pub fn rate(&self, pin : u8, window : Duration) -> Option<f64> {
+        let track = self.tracks.get(&pin)?;
+        let (newest, newest_total) = *track.history.back()?;
+        let (oldest, oldest_total) = *track.history.iter()
+            .find(|(timestamp, _)| newest.duration_since(*timestamp).map(|age| age <= window).unwrap_or(true))?;
+
+        let span = newest.duration_since(oldest).ok()?.as_secs_f64();
+        if span <= 0.0 {
+            return None;
+        }
+        Some((newest_total - oldest_total) / span)
+    }
+
+    fn save(&self) -> Result<(),Error> {
+        let path = match &self.store {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_vec(&self.tracks).map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn integrates_frequencies_across_client_restarts() {
+        let at = |secs : u64| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut counter = PulseCounter::new().history(Duration::from_secs(10));
+
+        assert_eq!(counter.update(4, 10, at(0)).delta, 0.0);
+        let reading = counter.update(4, 30, at(1));
+        assert_eq!((reading.frequency_hz, reading.delta, reading.total), (30, 20.0, 20.0));
+        assert_eq!(counter.update(4, 30, at(3)).total, 80.0);
+        assert_eq!(counter.rate(4, Duration::from_secs(2)), Some(30.0));
+        assert_eq!(counter.rate(4, Duration::from_secs(60)), Some(80.0 / 3.0));
+        counter.update(4, 0, at(20));
+        assert_eq!(counter.rate(4, Duration::from_secs(60)), None);
+
+        let path = std::env::temp_dir().join(format!("sdbp_counter_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut counter = PulseCounter::new().store(&path).unwrap();
+        counter.update(1, 100, at(0));
+        counter.update(1, 100, at(1));
+        counter.save().unwrap();
+
+        // The time in between is not counted, the integration starts again with the next read
+        let mut restarted = PulseCounter::new().store(&path).unwrap();
+        assert_eq!(restarted.total(1), Some(100.0));
+        assert_eq!(restarted.update(1, 50, at(60)).total, 100.0);
+        assert_eq!(restarted.update(1, 50, at(62)).total, 200.0);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+//! Continuous sampling of IO module inputs
+//!
+//! A `Sampler` polls `GET_VALUES` at a fixed interval and stores the timestamped, typed
+//! values per pin in a `SampleBuffer`, which can be reduced to min, max and average per
+//! time bucket for display. A `PulseCounter` integrates the frequencies measured by the
+//! digital counters of the pins into pulse totals and rates that survive client restarts.
+
+mod buffer;
+mod sampler;
+mod counter;
+
+pub use buffer::*;
+pub use sampler::*;
+pub use counter::*;
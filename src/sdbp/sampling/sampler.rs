@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sdbp::request::custom::io::IoBuilder;
+use crate::sdbp::response::SdbpResponse;
+use crate::sdbp::response::custom::io::input::GetValuesStatus;
+use crate::util::*;
+use super::*;
+
+type Buffers = Arc<Mutex<HashMap<u8,SampleBuffer>>>;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct SamplerConfig {
+    pub interval : Duration,
+    /// Samples kept per pin
+    pub capacity : usize,
+    /// Polls `GET_CURRENT_VALUES` instead of `GET_VALUES`
+    pub current_values : bool,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig { interval : Duration::from_millis(100), capacity : 1000, current_values : false }
+    }
+}
+
+/// Polls the input values of an IO module in the background and keeps a `SampleBuffer` per pin
+///
+/// `transfer` sends a frame to the module, e.g. a `Manager` with the slot already selected.
+/// Failed polls and undecodable responses are counted and skipped, pins the module does not
+/// report in a poll get no sample.
+pub struct Sampler {
+    handle : ManagedThreadHandle<()>,
+    buffers : Buffers,
+    errors : Arc<AtomicU64>,
+}
+
+impl Sampler {
+
+    pub fn start<F>(transfer : F, config : SamplerConfig) -> Sampler where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> + Send + 'static {
+        let buffers = Buffers::default();
+        let errors = Arc::new(AtomicU64::new(0));
+        let (task_buffers, task_errors) = (buffers.clone(), errors.clone());
+        let handle = spawn("Sampler".to_string(), move |ctl_pair| Sampler::task(ctl_pair, transfer, config, task_buffers, task_errors));
+        Sampler { handle, buffers, errors }
+    }
+
+    fn task<F>(ctl_pair : ChannelPair<ManagedThreadState>, mut transfer : F, config : SamplerConfig, buffers : Buffers, errors : Arc<AtomicU64>)
+        where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let mut stopped = false;
+        while !stopped {
+            let next = Instant::now() + config.interval;
+            match Sampler::poll(&mut transfer, config.current_values) {
+                Ok(values) => Sampler::record(&buffers, config.capacity, SystemTime::now(), values),
+                Err(err) => {
+                    errors.fetch_add(1, Ordering::Relaxed);
+                    trace!("Sampling failed: {}", err);
+                }
+            }
+
+            loop {
+                ManagedThreadUtil::is_stopped(&mut stopped, &ctl_pair);
+                let now = Instant::now();
+                if stopped || now >= next {
+                    break;
+                }
+                std::thread::sleep((next - now).min(Duration::from_millis(50)));
+            }
+        }
+    }
+
+    fn poll<F>(transfer : &mut F, current_values : bool) -> Result<GetValuesStatus,Error> where F : FnMut(Vec<u8>) -> Result<Vec<u8>,Error> {
+        let request = match current_values {
+            true => IoBuilder::new().input().get_current_values()?,
+            false => IoBuilder::new().input().get_values()?,
+        };
+        GetValuesStatus::from_raw(transfer(request)?)
+    }
+
+    fn record(buffers : &Buffers, capacity : usize, timestamp : SystemTime, values : GetValuesStatus) {
+        let mut buffers = buffers.lock().expect("Could not lock sample buffers");
+        for pin in values.pins {
+            if let Some(value) = pin.typed() {
+                buffers.entry(pin.pin).or_insert_with(|| SampleBuffer::new(capacity)).push(Sample { timestamp, value });
+            }
+        }
+    }
+
+    /// Pins that reported at least one value
+    pub fn pins(&self) -> Vec<u8> {
+        let mut pins : Vec<u8> = self.buffers.lock().expect("Could not lock sample buffers").keys().copied().collect();
+        pins.sort_unstable();
+        pins
+    }
+
+    /// Copy of the buffer of `pin`
+    pub fn buffer(&self, pin : u8) -> Option<SampleBuffer> {
+        self.buffers.lock().expect("Could not lock sample buffers").get(&pin).cloned()
+    }
+
+    pub fn latest(&self, pin : u8) -> Option<Sample> {
+        self.buffers.lock().expect("Could not lock sample buffers").get(&pin).and_then(|buffer| buffer.latest())
+    }
+
+    pub fn downsample(&self, pin : u8, bucket : Duration) -> Vec<Aggregate> {
+        self.buffers.lock().expect("Could not lock sample buffers").get(&pin).map(|buffer| buffer.downsample(bucket)).unwrap_or_default()
+    }
+
+    /// Polls that failed or could not be decoded
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(&self, timeout : Duration) {
+        let _ = self.handle.stop(timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdbp::request::custom::io::protocol::*;
+    use crate::sdbp::response::custom::io::input::PinValue;
+
+    #[test]
+    fn mixed_and_short_frames_are_sampled_without_panic() {
+        let mut polls = 0;
+        let module = move |request : Vec<u8>| {
+            polls += 1;
+            let mut response = vec![CLASS_ID, classes::input_class::ID, request[2], 0];
+            match polls % 4 {
+                // pins 2 and 3 are missing
+                1 => response.extend_from_slice(&[0, 0x00, 2, 0x04, 0xD2, 1, 0x02, 2, 0, 1, 4, 0x03, 4, 0, 0, 0x03, 0xE8]),
+                // value of the wrong length
+                2 => response.extend_from_slice(&[3, 0x03, 2, 0, 5]),
+                // value longer than the frame
+                3 => response.extend_from_slice(&[0, 0x01, 4, 0x00]),
+                _ => response.truncate(2),
+            }
+            Ok(response)
+        };
+
+        let sampler = Sampler::start(module, SamplerConfig { interval : Duration::from_millis(5), capacity : 10, current_values : false });
+        std::thread::sleep(Duration::from_millis(100));
+        sampler.stop(Duration::from_secs(1));
+
+        assert_eq!(sampler.pins(), vec![0, 1, 4]);
+        assert_eq!(sampler.latest(0).unwrap().value, PinValue::Voltage(1234));
+        assert_eq!(sampler.latest(1).unwrap().value, PinValue::Digital(true));
+        assert_eq!(sampler.latest(4).unwrap().value, PinValue::Frequency(1000));
+        assert!(sampler.errors() > 0);
+        assert!(sampler.buffer(0).unwrap().len() <= 10);
+    }
+}
@@ -15,6 +15,7 @@ mod uds_test {
     use std::thread::{spawn, sleep};
     use std::os::unix::net::UnixStream;
     use std::time::Duration;
+    use std::io::{ErrorKind, Write};
     use crate::util::{UnixStreamReader, Connection, UnixDomainSocket};
     use crate::util::connection::client::uds_client::UdsClient;
 
@@ -78,5 +79,24 @@ mod uds_test {
         }
     }
 
+    #[test]
+    fn timeouts_keep_partial_frames() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut reader = UnixStreamReader::from_unix_stream(stream, Some(Duration::from_millis(50)));
 
+        let frame : Vec<u8> = vec![0x01,0x02,0x03];
+        let mut bytes = (frame.len() as u32).to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&frame);
+
+        // Split inside the header and inside the payload
+        peer.write_all(&bytes[..2]).unwrap();
+        assert_eq!(reader.read_msg().unwrap_err().kind(), ErrorKind::TimedOut);
+        peer.write_all(&bytes[2..5]).unwrap();
+        assert_eq!(reader.read_msg().unwrap_err().kind(), ErrorKind::TimedOut);
+        peer.write_all(&bytes[5..]).unwrap();
+        assert_eq!(reader.read_msg().unwrap(), frame);
+
+        peer.write_all(&bytes).unwrap();
+        assert_eq!(reader.read_msg().unwrap(), frame);
+    }
 }
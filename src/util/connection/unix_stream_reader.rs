@@ -7,6 +7,8 @@ use super::*;
 
 pub struct UnixStreamReader {
     stream : UnixStream,
+    /// Bytes of a frame read before a timeout, the next read continues with them
+    pending : Vec<u8>,
 }
 
 impl UnixStreamReader {
@@ -14,7 +16,23 @@ impl UnixStreamReader {
     pub fn from_unix_stream(stream : UnixStream,timeout : Option<Duration>) -> UnixStreamReader {
 
         stream.set_read_timeout(timeout).expect("Cannot set read timeout on uds socket");
-        UnixStreamReader {stream}
+        UnixStreamReader {stream, pending : Vec::new()}
+    }
+
+    /// Reads until `pending` holds `length` bytes, bytes read before a timeout are kept
+    fn fill(&mut self, length : usize) -> Result<(),Error> {
+        let mut buffer = [0u8; 1024];
+        while self.pending.len() < length {
+            let wanted = (length - self.pending.len()).min(buffer.len());
+            match self.stream.read(&mut buffer[..wanted]) {
+                Ok(0) => return Err(Error::new(ErrorKind::ConnectionAborted, "Socket closed")),
+                Ok(len) => self.pending.extend_from_slice(&buffer[..len]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => return Err(Error::new(ErrorKind::TimedOut,"No data received")),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
     }
 }
 
@@ -22,38 +40,24 @@ impl Connection for UnixStreamReader {
 
     fn read_msg(&mut self) -> Result<Vec<u8>,Error> {
 
-        let mut length_buffer: [u8;4] = [0; 4];
-        let mut buffer : [u8;4096] = [0; 4096];
-
         //Read Header
-        let status = self.stream.read(&mut length_buffer);
-
-        if let Ok(len) = status {
-            if len == 0 {  return Err(Error::new(ErrorKind::ConnectionAborted, "Socket closed"));}
-        }
-
-        if let Err(err) = status {
-            if err.kind() ==  ErrorKind::WouldBlock { return Err(Error::new(ErrorKind::TimedOut,"No data received")); }
-        }
+        self.fill(4)?;
 
-        let header = u32::from_ne_bytes(length_buffer);
+        let header = u32::from_ne_bytes([self.pending[0], self.pending[1], self.pending[2], self.pending[3]]);
 
         if header > 4096 {
+            self.pending.clear();
             let mut msg: String = "Header length out of range: ".to_owned();
             let msg_combined: String = header.to_string();
             msg.push_str(&msg_combined);
             return Err(Error::new(ErrorKind::InvalidData,msg))
         }
 
-        let status = self.stream.read(&mut buffer);
-
-        if let Ok(len) = status {
-            if len == 0 {  return Err(Error::new(ErrorKind::ConnectionAborted, "Socket closed"));}
-        } else if let Err(err) = status {
-            if err.kind() ==  ErrorKind::WouldBlock { return Err(Error::new(ErrorKind::TimedOut,"No data received")); }
-        }
-
-        Ok(Vec::from(&buffer[..header as usize]))
+        // Only the announced length is consumed, the next frame may already be buffered
+        self.fill(4 + header as usize)?;
+        let buffer = self.pending.split_off(4);
+        self.pending.clear();
+        Ok(buffer)
     }
 
     fn write_msg(&mut self, msg : &[u8],) -> Result<(),Error> {
@@ -1,50 +1,145 @@
-use std::sync::{Arc, RwLock, RwLockReadGuard};
-use std::time::{Duration, Instant};
-use std::clone::Clone;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
 
-pub struct SharedObjectError;
+struct SharedState<T> {
+    current : RwLock<(u64,Arc<T>)>,
+    watchers : Mutex<Vec<Sender<()>>>,
+}
+
+/// Versioned object shared between threads.
+///
+/// Readers get cheap immutable snapshots, writers swap in a new version and wake all watchers.
+pub struct SharedObject<T> {
+    shared : Arc<SharedState<T>>,
+}
 
-#[derive(Debug,Clone)]
-pub struct SharedObject<T : Clone > {
-    shared : Arc<RwLock<T>>,
+impl <T>Clone for SharedObject<T> {
+    fn clone(&self) -> Self {
+        SharedObject { shared : self.shared.clone() }
+    }
 }
 
-impl <T:Clone>SharedObject<T> {
+impl <T>SharedObject<T> {
 
     pub fn new(obj : T) -> SharedObject<T>{
-        SharedObject {shared : Arc::new(RwLock::new(obj.clone()))}
+        SharedObject { shared : Arc::new(SharedState { current : RwLock::new((0, Arc::new(obj))), watchers : Mutex::new(Vec::new()) }) }
     }
 
-    pub fn read(&mut self) -> T {
-        let guard = self.shared.read().expect("Could not get RW lock");
-        let result = guard.clone();
-        result
+    /// Returns the current snapshot of the object
+    pub fn snapshot(&self) -> Arc<T> {
+        self.shared.current.read().expect("Could not get RW lock").1.clone()
     }
 
-    pub fn write(&self, obj: T, dur : Option<Duration>) -> Result<(),SharedObjectError> {
+    pub fn version(&self) -> u64 {
+        self.shared.current.read().expect("Could not get RW lock").0
+    }
 
-        let mut result = Err(SharedObjectError);
-        if let Some(dur) = dur {
+    fn snapshot_with_version(&self) -> (u64,Arc<T>) {
+        let guard = self.shared.current.read().expect("Could not get RW lock");
+        (guard.0, guard.1.clone())
+    }
 
-            let now = Instant::now();
+    /// Replaces the object and returns the new version
+    pub fn replace(&self, obj : T) -> u64 {
+        let version = {
+            let mut guard = self.shared.current.write().expect("Could not get RW lock");
+            guard.0 += 1;
+            guard.1 = Arc::new(obj);
+            guard.0
+        };
+        self.notify();
+        version
+    }
 
-            while now.elapsed() <= dur {
-                match self.shared.try_write() {
-                    Ok(mut guard) => {
-                        *guard = obj;
-                        result = Ok(());
-                        break;
-                    },
-                    Err(_err) => (),
-                };
-            }
+    /// Modifies a copy of the current version and publishes it, concurrent updates are applied in order
+    pub fn update<F>(&self, func : F) -> u64 where T : Clone, F : FnOnce(&mut T) {
+        let version = {
+            let mut guard = self.shared.current.write().expect("Could not get RW lock");
+            let mut obj = (*guard.1).clone();
+            func(&mut obj);
+            guard.0 += 1;
+            guard.1 = Arc::new(obj);
+            guard.0
+        };
+        self.notify();
+        version
+    }
+
+    /// Creates a watcher which is woken on every change after the current version
+    pub fn subscribe(&self) -> Watcher<T> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        self.shared.watchers.lock().expect("Could not get watcher list").push(tx);
+        Watcher { object : self.clone(), rx, seen : self.version() }
+    }
+
+    fn notify(&self) {
+        let mut watchers = self.shared.watchers.lock().expect("Could not get watcher list");
+        // A full channel already holds a pending wakeup, dropped watchers are removed
+        watchers.retain(|tx| !matches!(tx.try_send(()), Err(TrySendError::Disconnected(_))));
+    }
+}
+
+/// Receiving side of the change notification of a `SharedObject`
+pub struct Watcher<T> {
+    object : SharedObject<T>,
+    rx : Receiver<()>,
+    seen : u64,
+}
+
+impl <T>Watcher<T> {
+
+    pub fn has_changed(&self) -> bool {
+        self.object.version() != self.seen
+    }
+
+    /// Version of the last snapshot returned by `latest` or `wait`
+    pub fn version(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns the current snapshot and marks it as seen
+    pub fn latest(&mut self) -> Arc<T> {
+        let _ = self.rx.try_recv();
+        let (version, snapshot) = self.object.snapshot_with_version();
+        self.seen = version;
+        snapshot
+    }
+
+    /// Blocks until a new version is published or the timeout elapsed
+    pub fn wait(&mut self, timeout : Duration) -> Option<Arc<T>> {
+        if !self.has_changed() && self.rx.recv_timeout(timeout).is_err() {
+            return None;
         }
-        // let mut obj_guard = self.shared.write().unwrap();
-        // *stats_guard = _stats;
-        result
+        Some(self.latest())
+    }
+
+    /// Channel which receives a wakeup on change, e.g. for `crossbeam_channel::Select`
+    pub fn receiver(&self) -> &Receiver<()> {
+        &self.rx
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_publishes_new_version() {
+        let shared = SharedObject::new(vec![1]);
+        let mut watcher = shared.subscribe();
+        let before = shared.snapshot();
+
+        assert!(!watcher.has_changed());
+        assert_eq!(shared.update(|value| value.push(2)), 1);
+        assert_eq!(*before, vec![1]);
+        assert!(watcher.has_changed());
+        assert_eq!(*watcher.wait(Duration::from_millis(10)).unwrap(), vec![1, 2]);
+        assert!(watcher.wait(Duration::from_millis(10)).is_none());
 
-    pub fn shared(&self) -> RwLockReadGuard<T>{
-        self.shared.read().expect("Could not get RW lock")
+        shared.replace(vec![3]);
+        shared.replace(vec![4]);
+        assert_eq!(*watcher.latest(), vec![4]);
+        assert!(!watcher.has_changed());
     }
 }